@@ -2,11 +2,16 @@
 
 use serde::Deserialize;
 
+// exp / iat / iss are checked by jsonwebtoken's Validation, only `sub` and `org_id` are read here
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct Claims {
     pub sub: String,   // user ID
     pub exp: usize,
     pub iat: usize,
     pub iss: String,
+    // active Clerk organization, the team in `collection` tenancy
+    #[serde(default)]
+    pub org_id: Option<String>,
 }
 
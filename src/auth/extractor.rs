@@ -13,6 +13,7 @@ use crate::auth::jwt::get_decoding_key;
 #[derive(Debug, Clone)]
 pub struct AuthUser {
     pub user_id: String,
+    pub team_id: Option<String>,
 }
 
 impl FromRequest for AuthUser {
@@ -77,6 +78,7 @@ impl FromRequest for AuthUser {
                 println!("Authenticated user: {}", data.claims.sub);
                 ready(Ok(AuthUser {
                     user_id: data.claims.sub,
+                    team_id: data.claims.org_id,
                 }))
             }
             Err(err) => {
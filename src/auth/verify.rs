@@ -2,9 +2,10 @@ use actix_web::{HttpRequest, error::ErrorUnauthorized};
 use jsonwebtoken::{decode, decode_header, Algorithm, Validation};
 
 use crate::auth::claims::Claims;
+use crate::auth::extractor::AuthUser;
 use crate::auth::jwt::get_decoding_key;
 
-pub async fn verify_ws_request(req: &HttpRequest) -> Result<String, actix_web::Error> {
+pub async fn verify_ws_request(req: &HttpRequest) -> Result<AuthUser, actix_web::Error> {
     // let auth_header = req
     //     .headers()
     //     .get("Authorization")
@@ -44,5 +45,8 @@ pub async fn verify_ws_request(req: &HttpRequest) -> Result<String, actix_web::E
     let token_data = decode::<Claims>(&token, &decoding_key, &validation)
         .map_err(|_| ErrorUnauthorized("Invalid or expired token"))?;
 
-    Ok(token_data.claims.sub)
+    Ok(AuthUser {
+        user_id: token_data.claims.sub,
+        team_id: token_data.claims.org_id,
+    })
 }
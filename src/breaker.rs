@@ -0,0 +1,167 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::config::ResilienceConfig;
+
+// Classic three state circuit breaker. After `failure_threshold` failures in
+// a row calls are refused for `open_for`, then a single probe call is let
+// through (half open): success closes the circuit, failure opens it again.
+pub struct CircuitBreaker {
+    name: &'static str,
+    failure_threshold: u32,
+    open_for: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // the probe call is in flight, everything else is refused. A probe
+    // that never reports back (dropped future) is replaced after `open_for`.
+    HalfOpen { since: Instant },
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, config: &ResilienceConfig) -> Self {
+        CircuitBreaker {
+            name,
+            failure_threshold: config.failure_threshold,
+            open_for: config.open_for,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    // Whether a call may go ahead. Once the open period is over the first
+    // caller becomes the half open probe.
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { until } if Instant::now() >= until => {
+                println!("Circuit {} half open, probing", self.name);
+                *state = State::HalfOpen { since: Instant::now() };
+                true
+            }
+            State::HalfOpen { since } if since.elapsed() >= self.open_for => {
+                *state = State::HalfOpen { since: Instant::now() };
+                true
+            }
+            State::Open { .. } | State::HalfOpen { .. } => false,
+        }
+    }
+
+    // The dependency answered, even an error response counts as a success here
+    pub fn success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, State::Closed { .. }) {
+            println!("Circuit {} closed", self.name);
+        }
+        *state = State::Closed { failures: 0 };
+    }
+
+    pub fn failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            State::Closed { failures } => failures + 1,
+            // a failed probe opens the circuit straight away
+            State::HalfOpen { .. } | State::Open { .. } => self.failure_threshold,
+        };
+        if failures >= self.failure_threshold {
+            eprintln!("Circuit {} open for {}s", self.name, self.open_for.as_secs());
+            *state = State::Open { until: Instant::now() + self.open_for };
+        } else {
+            *state = State::Closed { failures };
+        }
+    }
+
+    // Whether `allow` would let a call through right now, without taking the
+    // probe slot. Gates work that should reach the probe instead of waiting
+    // for it: a half open circuit whose probe is overdue lets the next call in.
+    pub fn permits_probe(&self) -> bool {
+        match *self.state.lock().unwrap() {
+            State::Closed { .. } => true,
+            State::Open { until } => Instant::now() >= until,
+            State::HalfOpen { since } => since.elapsed() >= self.open_for,
+        }
+    }
+
+    // Calls are being refused right now, a probe in flight included. Only
+    // for telling clients, gating on it would starve the probe.
+    pub fn is_open(&self) -> bool {
+        match *self.state.lock().unwrap() {
+            State::Closed { .. } => false,
+            State::Open { until } => Instant::now() < until,
+            State::HalfOpen { .. } => true,
+        }
+    }
+}
+
+// Breakers of the two dependencies scoring and saving need
+#[derive(Clone)]
+pub struct Breakers {
+    pub store: Arc<CircuitBreaker>,
+    pub embedder: Arc<CircuitBreaker>,
+}
+
+impl Breakers {
+    pub fn new(config: &ResilienceConfig) -> Self {
+        Breakers {
+            store: Arc::new(CircuitBreaker::new("vector store", config)),
+            embedder: Arc::new(CircuitBreaker::new("embedding provider", config)),
+        }
+    }
+
+    // Scoring and saving can't work while either dependency is refused
+    pub fn degraded(&self) -> bool {
+        self.store.is_open() || self.embedder.is_open()
+    }
+
+    // Both dependencies would take a call, the half open probe included
+    pub fn accepting(&self) -> bool {
+        self.store.permits_probe() && self.embedder.permits_probe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(open_for: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            name: "test",
+            failure_threshold: 2,
+            open_for,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    #[test]
+    fn probe_traffic_is_let_through_once_the_open_period_is_over() {
+        let breaker = breaker(Duration::from_millis(20));
+        breaker.failure();
+        assert!(breaker.permits_probe());
+        breaker.failure();
+        assert!(breaker.is_open());
+        assert!(!breaker.permits_probe());
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.permits_probe());
+        // the probe takes the slot, everyone else waits for it
+        assert!(breaker.allow());
+        assert!(breaker.is_open());
+        assert!(!breaker.permits_probe());
+        assert!(!breaker.allow());
+
+        // a probe that never reported back is replaced
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.permits_probe());
+        assert!(breaker.allow());
+        breaker.success();
+        assert!(!breaker.is_open());
+        assert!(breaker.permits_probe());
+    }
+}
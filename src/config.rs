@@ -0,0 +1,574 @@
+use std::{collections::HashMap, env, path::PathBuf, time::Duration};
+
+// Runtime configuration, read once from the environment at startup
+// and handed to the rest of the server through AppState.
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub store: StoreBackend,
+    pub tenancy: TenancyMode,
+    pub qdrant: QdrantConfig,
+    pub embedded: EmbeddedConfig,
+    pub embedding: EmbeddingConfig,
+    pub normalize: NormalizeConfig,
+    pub budgets: BudgetConfig,
+    pub vectors: VectorConfig,
+    pub upsert: UpsertConfig,
+    pub resilience: ResilienceConfig,
+    pub matching: MatchConfig,
+}
+
+// Where points are stored and searched
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StoreBackend {
+    Qdrant,
+    // in-process brute force, nothing survives a restart
+    Memory,
+    // HNSW index and payload log on local disk
+    Embedded,
+}
+
+// How saved tweets of different users are kept apart. Every mode also
+// filters on `user_id`, the others add physical separation on top.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TenancyMode {
+    // one shared collection
+    Filter,
+    // Qdrant custom sharding, one shard key per user
+    ShardKey,
+    // one collection per team (JWT `org_id`), users without a team share the base collection
+    Collection,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmbeddedConfig {
+    pub dir: PathBuf,
+    // how often a snapshot is written, None disables periodic snapshots
+    pub snapshot_interval: Option<Duration>,
+    pub snapshot_keep: usize,
+    // snapshot directory loaded over the data at startup
+    pub restore_from: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QdrantConfig {
+    pub endpoint: String,
+    // not needed for a local Qdrant without auth
+    pub api_key: Option<String>,
+    pub tweets_collection: String,
+    pub entitlements_collection: String,
+    // distance used when the tweets collection is created (Cosine, Dot, Euclid, Manhattan)
+    pub distance: String,
+    pub transport: QdrantTransport,
+    // gRPC endpoint, defaults to the REST host on port 6334
+    pub grpc_endpoint: String,
+}
+
+// Wire protocol of the point operations (upsert, delete, scroll, count, query).
+// Collection management always goes over REST.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QdrantTransport {
+    Rest,
+    Grpc,
+}
+
+// How saved tweets are written: split into chunks sent `parallelism` at a time
+#[derive(Debug, Clone)]
+pub struct UpsertConfig {
+    pub chunk_size: usize,
+    pub parallelism: usize,
+    // false returns once Qdrant has accepted a chunk, before it is indexed
+    pub wait: bool,
+    pub ordering: WriteOrdering,
+}
+
+// Circuit breakers around the vector store and the embedding provider, and
+// the queue /save falls back to while either is down
+#[derive(Debug, Clone)]
+pub struct ResilienceConfig {
+    // failures in a row that open a circuit
+    pub failure_threshold: u32,
+    // how long an open circuit refuses calls before probing
+    pub open_for: Duration,
+    pub save_queue_capacity: usize,
+}
+
+// Qdrant write ordering, stronger orderings route writes through the shard leader
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteOrdering {
+    Weak,
+    Medium,
+    Strong,
+}
+
+impl WriteOrdering {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WriteOrdering::Weak => "weak",
+            WriteOrdering::Medium => "medium",
+            WriteOrdering::Strong => "strong",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmbeddingConfig {
+    pub provider: ProviderConfig,
+    pub model: String,
+    // optional `dimensions` request parameter, must match the Qdrant collection
+    pub dimensions: Option<usize>,
+    pub cache: CacheConfig,
+    pub limits: RequestLimits,
+    pub retry: RetryConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+// Per-request limits of the provider, batches are split to stay under them
+#[derive(Debug, Clone)]
+pub struct RequestLimits {
+    pub max_inputs: usize,
+    pub max_batch_tokens: usize,
+    pub max_input_tokens: usize,
+    // cut over-long texts instead of failing the batch
+    pub truncate: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    // max vectors kept in memory, 0 disables the cache
+    pub capacity: usize,
+    // optional directory that keeps vectors across restarts
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ProviderConfig {
+    // api.openai.com (or anything that speaks the same API with a Bearer key)
+    OpenAi {
+        api_key: String,
+        endpoint: String,
+    },
+    // Azure OpenAI: api-key header and per-deployment URLs
+    Azure {
+        api_key: String,
+        endpoint: String,
+        deployment: String,
+        api_version: String,
+    },
+    // Self-hosted OpenAI-compatible servers (vLLM, Ollama, TEI, LocalAI ...)
+    Compatible {
+        endpoint: String,
+        api_key: Option<String>,
+    },
+    // Offline feature-hashed character n-grams, no network needed
+    Hashing {
+        dimensions: usize,
+        ngram: usize,
+    },
+}
+
+// Text cleanup applied to tweets before they are embedded
+#[derive(Debug, Clone)]
+pub struct NormalizeConfig {
+    pub urls: UrlMode,
+    pub drop_mentions: bool,
+    pub hashtags: HashtagMode,
+    pub strip_emoji: bool,
+    pub strip_retweet: bool,
+    // NFKC, folds full-width / styled "𝐛𝐨𝐥𝐝" characters into plain ones
+    pub unicode: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UrlMode {
+    Keep,
+    // keep only the host name, drops tracking paths and query strings
+    Collapse,
+    Strip,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum HashtagMode {
+    Keep,
+    // "#rustlang" -> "rustlang"
+    Word,
+    Drop,
+}
+
+// Which saved tweets count as a match for an incoming feed tweet. These are
+// the deployment defaults, users can override both.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchConfig {
+    // dense cosine score below which neighbors are not delivered
+    pub min_score: f32,
+    // saved tweets looked up per feed tweet
+    pub top_k: u8,
+}
+
+// Upper bound of `top_k`, deployment wide and per user
+pub const MAX_MATCH_TOP_K: u8 = 20;
+
+// Embedding token budgets per plan name, plans without an entry are unlimited
+#[derive(Debug, Clone)]
+pub struct BudgetConfig {
+    pub plans: HashMap<String, TokenBudget>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBudget {
+    pub daily: Option<u64>,
+    pub monthly: Option<u64>,
+}
+
+// How saved vectors are shrunk before they reach Qdrant
+#[derive(Debug, Clone)]
+pub struct VectorConfig {
+    // keep only this many leading dimensions (Matryoshka truncation)
+    pub truncate_dimensions: Option<usize>,
+    pub quantization: Quantization,
+    pub always_ram: bool,
+    // re-rank quantized candidates with the original vectors
+    pub rescore: bool,
+    pub oversampling: Option<f32>,
+    // store a sparse term vector next to the dense one and fuse both at query time
+    pub hybrid: bool,
+    pub sparse_vector_name: String,
+    // candidates fetched from each of the dense / sparse searches before fusion
+    pub hybrid_prefetch: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantization {
+    None,
+    // int8 per dimension, ~4x smaller
+    Scalar,
+    // 1 bit per dimension, ~32x smaller, best with rescoring
+    Binary,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Config, anyhow::Error> {
+        let store = match env_or("VECTOR_STORE", "qdrant").to_lowercase().as_str() {
+            "qdrant" => StoreBackend::Qdrant,
+            "memory" => StoreBackend::Memory,
+            "embedded" => StoreBackend::Embedded,
+            other => anyhow::bail!("Unknown VECTOR_STORE: {}", other),
+        };
+
+        let tenancy = match env_or("TENANCY", "filter").to_lowercase().as_str() {
+            "filter" => TenancyMode::Filter,
+            "shard_key" => TenancyMode::ShardKey,
+            "collection" => TenancyMode::Collection,
+            other => anyhow::bail!("Unknown TENANCY: {}", other),
+        };
+
+        Ok(Config {
+            store,
+            tenancy,
+            qdrant: QdrantConfig::from_env(store)?,
+            embedded: EmbeddedConfig::from_env()?,
+            embedding: EmbeddingConfig::from_env()?,
+            normalize: NormalizeConfig::from_env()?,
+            budgets: BudgetConfig::from_env()?,
+            vectors: VectorConfig::from_env()?,
+            upsert: UpsertConfig::from_env()?,
+            resilience: ResilienceConfig::from_env()?,
+            matching: MatchConfig::from_env()?,
+        })
+    }
+}
+
+impl QdrantConfig {
+    pub fn from_env(store: StoreBackend) -> Result<QdrantConfig, anyhow::Error> {
+        // the collection names are shared by every backend, the endpoint only matters for Qdrant
+        let endpoint = match store {
+            StoreBackend::Qdrant => required("QDRANT_ENDPOINT")?,
+            _ => env_or("QDRANT_ENDPOINT", "http://localhost:6333"),
+        };
+        let transport = match env_or("QDRANT_TRANSPORT", "rest").to_lowercase().as_str() {
+            "rest" => QdrantTransport::Rest,
+            "grpc" => QdrantTransport::Grpc,
+            other => anyhow::bail!("Unknown QDRANT_TRANSPORT: {}", other),
+        };
+        let grpc_endpoint = match optional("QDRANT_GRPC_ENDPOINT") {
+            Some(grpc_endpoint) => grpc_endpoint,
+            None => default_grpc_endpoint(&endpoint)?,
+        };
+
+        Ok(QdrantConfig {
+            endpoint,
+            api_key: optional("QDRANT_API_KEY"),
+            tweets_collection: env_or("QDRANT_TWEETS_COLLECTION", "tweet_userid"),
+            entitlements_collection: env_or("QDRANT_ENTITLEMENTS_COLLECTION", "user_entitlement"),
+            distance: env_or("QDRANT_DISTANCE", "Cosine"),
+            transport,
+            grpc_endpoint,
+        })
+    }
+}
+
+impl EmbeddedConfig {
+    pub fn from_env() -> Result<EmbeddedConfig, anyhow::Error> {
+        let interval: u64 = parse_or("EMBEDDED_SNAPSHOT_INTERVAL_SECS", 0)?;
+        Ok(EmbeddedConfig {
+            dir: PathBuf::from(env_or("EMBEDDED_DIR", "data/vectors")),
+            snapshot_interval: (interval > 0).then(|| Duration::from_secs(interval)),
+            snapshot_keep: parse_or("EMBEDDED_SNAPSHOT_KEEP", 3)?,
+            restore_from: optional("EMBEDDED_RESTORE_FROM").map(PathBuf::from),
+        })
+    }
+}
+
+impl UpsertConfig {
+    pub fn from_env() -> Result<UpsertConfig, anyhow::Error> {
+        let ordering = match env_or("UPSERT_ORDERING", "weak").to_lowercase().as_str() {
+            "weak" => WriteOrdering::Weak,
+            "medium" => WriteOrdering::Medium,
+            "strong" => WriteOrdering::Strong,
+            other => anyhow::bail!("Unknown UPSERT_ORDERING: {}", other),
+        };
+        let config = UpsertConfig {
+            chunk_size: parse_or("UPSERT_CHUNK_SIZE", 100)?,
+            parallelism: parse_or("UPSERT_PARALLELISM", 4)?,
+            wait: parse_or("UPSERT_WAIT", true)?,
+            ordering,
+        };
+        if config.chunk_size == 0 || config.parallelism == 0 {
+            anyhow::bail!("UPSERT_CHUNK_SIZE and UPSERT_PARALLELISM must be greater than 0");
+        }
+        Ok(config)
+    }
+}
+
+impl MatchConfig {
+    pub fn from_env() -> Result<MatchConfig, anyhow::Error> {
+        let config = MatchConfig {
+            min_score: parse_or("MATCH_MIN_SCORE", 0.5)?,
+            top_k: parse_or("MATCH_TOP_K", 1)?,
+        };
+        if !config.min_score.is_finite() {
+            anyhow::bail!("MATCH_MIN_SCORE must be a number");
+        }
+        if config.top_k == 0 || config.top_k > MAX_MATCH_TOP_K {
+            anyhow::bail!("MATCH_TOP_K must be between 1 and {}", MAX_MATCH_TOP_K);
+        }
+        Ok(config)
+    }
+}
+
+impl ResilienceConfig {
+    pub fn from_env() -> Result<ResilienceConfig, anyhow::Error> {
+        let config = ResilienceConfig {
+            failure_threshold: parse_or("BREAKER_FAILURE_THRESHOLD", 5)?,
+            open_for: Duration::from_secs(parse_or("BREAKER_OPEN_SECS", 30)?),
+            save_queue_capacity: parse_or("SAVE_QUEUE_CAPACITY", 1000)?,
+        };
+        if config.failure_threshold == 0 {
+            anyhow::bail!("BREAKER_FAILURE_THRESHOLD must be greater than 0");
+        }
+        Ok(config)
+    }
+}
+
+impl EmbeddingConfig {
+    pub fn from_env() -> Result<EmbeddingConfig, anyhow::Error> {
+        let provider = match env_or("EMBEDDING_PROVIDER", "openai").to_lowercase().as_str() {
+            "openai" => ProviderConfig::OpenAi {
+                api_key: required("OPENAI_API_KEY")?,
+                endpoint: env_or("OPENAI_ENDPOINT", "https://api.openai.com/v1/embeddings"),
+            },
+            "azure" => ProviderConfig::Azure {
+                api_key: required("AZURE_OPENAI_API_KEY")?,
+                endpoint: required("AZURE_OPENAI_ENDPOINT")?,
+                deployment: required("AZURE_OPENAI_DEPLOYMENT")?,
+                api_version: env_or("AZURE_OPENAI_API_VERSION", "2024-02-01"),
+            },
+            "compatible" => ProviderConfig::Compatible {
+                endpoint: required("EMBEDDING_ENDPOINT")?,
+                api_key: optional("EMBEDDING_API_KEY"),
+            },
+            "hashing" => ProviderConfig::Hashing {
+                dimensions: parse_or("EMBEDDING_HASH_DIMENSIONS", 1536)?,
+                ngram: parse_or("EMBEDDING_HASH_NGRAM", 3)?,
+            },
+            other => anyhow::bail!("Unknown EMBEDDING_PROVIDER: {}", other),
+        };
+
+        if let ProviderConfig::Hashing { dimensions, ngram } = &provider {
+            if *dimensions == 0 || *ngram == 0 {
+                anyhow::bail!("EMBEDDING_HASH_DIMENSIONS and EMBEDDING_HASH_NGRAM must be greater than 0");
+            }
+        }
+
+        let cache = CacheConfig {
+            capacity: parse_or("EMBEDDING_CACHE_CAPACITY", 10_000)?,
+            dir: optional("EMBEDDING_CACHE_DIR").map(PathBuf::from),
+        };
+
+        // defaults match OpenAI's embeddings API (2048 inputs, 300k tokens, 8191 per input)
+        let limits = RequestLimits {
+            max_inputs: parse_or("EMBEDDING_MAX_INPUTS", 2048)?,
+            max_batch_tokens: parse_or("EMBEDDING_MAX_BATCH_TOKENS", 300_000)?,
+            max_input_tokens: parse_or("EMBEDDING_MAX_INPUT_TOKENS", 8191)?,
+            truncate: parse_or("EMBEDDING_TRUNCATE", true)?,
+        };
+        if limits.max_inputs == 0 || limits.max_batch_tokens == 0 || limits.max_input_tokens == 0 {
+            anyhow::bail!("EMBEDDING_MAX_* limits must be greater than 0");
+        }
+
+        let retry = RetryConfig {
+            max_retries: parse_or("EMBEDDING_MAX_RETRIES", 4)?,
+            base_delay: Duration::from_millis(parse_or("EMBEDDING_RETRY_BASE_MS", 500)?),
+            max_delay: Duration::from_millis(parse_or("EMBEDDING_RETRY_MAX_MS", 30_000)?),
+        };
+
+        Ok(EmbeddingConfig {
+            provider,
+            model: env_or("EMBEDDING_MODEL", "text-embedding-3-small"),
+            dimensions: parse_optional("EMBEDDING_DIMENSIONS")?,
+            cache,
+            limits,
+            retry,
+        })
+    }
+}
+
+impl NormalizeConfig {
+    pub fn from_env() -> Result<NormalizeConfig, anyhow::Error> {
+        let urls = match env_or("NORMALIZE_URLS", "collapse").to_lowercase().as_str() {
+            "keep" => UrlMode::Keep,
+            "collapse" => UrlMode::Collapse,
+            "strip" => UrlMode::Strip,
+            other => anyhow::bail!("Unknown NORMALIZE_URLS: {}", other),
+        };
+        let hashtags = match env_or("NORMALIZE_HASHTAGS", "word").to_lowercase().as_str() {
+            "keep" => HashtagMode::Keep,
+            "word" => HashtagMode::Word,
+            "drop" => HashtagMode::Drop,
+            other => anyhow::bail!("Unknown NORMALIZE_HASHTAGS: {}", other),
+        };
+
+        Ok(NormalizeConfig {
+            urls,
+            drop_mentions: parse_or("NORMALIZE_DROP_MENTIONS", false)?,
+            hashtags,
+            strip_emoji: parse_or("NORMALIZE_STRIP_EMOJI", true)?,
+            strip_retweet: parse_or("NORMALIZE_STRIP_RETWEET", true)?,
+            unicode: parse_or("NORMALIZE_UNICODE", true)?,
+        })
+    }
+}
+
+impl BudgetConfig {
+    // TOKEN_BUDGETS=Free=20000/300000,Pro=-/5000000  (daily/monthly, "-" for no limit)
+    // Unset means no plan is limited
+    pub fn from_env() -> Result<BudgetConfig, anyhow::Error> {
+        let raw = env_or("TOKEN_BUDGETS", "");
+        let mut plans = HashMap::new();
+
+        for entry in raw.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (plan, limits) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Invalid TOKEN_BUDGETS entry: {}", entry))?;
+            let (daily, monthly) = limits
+                .split_once('/')
+                .ok_or_else(|| anyhow::anyhow!("Invalid TOKEN_BUDGETS entry: {}", entry))?;
+
+            let limit = |v: &str| -> Result<Option<u64>, anyhow::Error> {
+                match v.trim() {
+                    "-" => Ok(None),
+                    v => v
+                        .parse::<u64>()
+                        .map(Some)
+                        .map_err(|e| anyhow::anyhow!("Invalid TOKEN_BUDGETS entry {}: {}", entry, e)),
+                }
+            };
+
+            plans.insert(
+                plan.trim().to_string(),
+                TokenBudget {
+                    daily: limit(daily)?,
+                    monthly: limit(monthly)?,
+                },
+            );
+        }
+
+        Ok(BudgetConfig { plans })
+    }
+}
+
+impl VectorConfig {
+    pub fn from_env() -> Result<VectorConfig, anyhow::Error> {
+        let quantization = match env_or("VECTOR_QUANTIZATION", "none").to_lowercase().as_str() {
+            "none" => Quantization::None,
+            "scalar" | "int8" => Quantization::Scalar,
+            "binary" => Quantization::Binary,
+            other => anyhow::bail!("Unknown VECTOR_QUANTIZATION: {}", other),
+        };
+
+        let truncate_dimensions: Option<usize> = parse_optional("VECTOR_TRUNCATE_DIMENSIONS")?;
+        if truncate_dimensions == Some(0) {
+            anyhow::bail!("VECTOR_TRUNCATE_DIMENSIONS must be greater than 0");
+        }
+
+        Ok(VectorConfig {
+            truncate_dimensions,
+            quantization,
+            always_ram: parse_or("VECTOR_QUANTIZATION_ALWAYS_RAM", true)?,
+            rescore: parse_or("VECTOR_RESCORE", true)?,
+            oversampling: parse_optional("VECTOR_OVERSAMPLING")?,
+            hybrid: parse_or("HYBRID_SEARCH", false)?,
+            sparse_vector_name: env_or("HYBRID_SPARSE_VECTOR", "bm25"),
+            hybrid_prefetch: parse_or("HYBRID_PREFETCH_LIMIT", 20)?,
+        })
+    }
+}
+
+// Same scheme and host as the REST endpoint, Qdrant serves gRPC on 6334
+fn default_grpc_endpoint(rest: &str) -> Result<String, anyhow::Error> {
+    let mut url = reqwest::Url::parse(rest)
+        .map_err(|e| anyhow::anyhow!("Invalid QDRANT_ENDPOINT {}: {}", rest, e))?;
+    url.set_port(Some(6334))
+        .map_err(|_| anyhow::anyhow!("QDRANT_ENDPOINT {} has no host", rest))?;
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+fn required(key: &str) -> Result<String, anyhow::Error> {
+    optional(key).ok_or_else(|| anyhow::anyhow!("{} must be set in environment", key))
+}
+
+fn optional(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn env_or(key: &str, default: &str) -> String {
+    optional(key).unwrap_or_else(|| default.to_string())
+}
+
+fn parse_or<T>(key: &str, default: T) -> Result<T, anyhow::Error>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    Ok(parse_optional(key)?.unwrap_or(default))
+}
+
+fn parse_optional<T>(key: &str) -> Result<Option<T>, anyhow::Error>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    optional(key)
+        .map(|v| {
+            v.trim()
+                .parse::<T>()
+                .map_err(|e| anyhow::anyhow!("Invalid value for {}: {}", key, e))
+        })
+        .transpose()
+}
@@ -0,0 +1,55 @@
+use async_trait::async_trait;
+
+use crate::{
+    embeddings::{request_body, send, EmbeddingError, EmbeddingProvider, ModelSpec},
+    models::response::EmbeddingResponse,
+};
+
+// Azure routes by deployment instead of model name and authenticates with
+// an `api-key` header, the body is otherwise identical to OpenAI's.
+pub struct AzureOpenAiProvider {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+    model: ModelSpec,
+}
+
+impl AzureOpenAiProvider {
+    pub fn new(
+        client: reqwest::Client,
+        endpoint: &str,
+        deployment: &str,
+        api_version: &str,
+        api_key: String,
+        model: ModelSpec,
+    ) -> Self {
+        let url = format!(
+            "{}/openai/deployments/{}/embeddings?api-version={}",
+            endpoint.trim_end_matches('/'),
+            urlencoding::encode(deployment),
+            urlencoding::encode(api_version)
+        );
+        AzureOpenAiProvider { client, url, api_key, model }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for AzureOpenAiProvider {
+    fn model(&self) -> &str {
+        &self.model.name
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.model.dimensions
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+        let request = self
+            .client
+            .post(&self.url)
+            .header("api-key", &self.api_key)
+            .json(&request_body(&self.model, texts));
+
+        send(request).await
+    }
+}
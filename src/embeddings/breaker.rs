@@ -0,0 +1,45 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use crate::{
+    breaker::CircuitBreaker,
+    embeddings::{EmbeddingError, EmbeddingProvider},
+    models::response::EmbeddingResponse,
+};
+
+// Fails fast while the provider is down instead of letting every batch
+// sit through the full retry schedule. Sits outside RetryingProvider, so
+// one failure is one request whose retries were all used up.
+pub struct BreakerProvider {
+    inner: Box<dyn EmbeddingProvider>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl BreakerProvider {
+    pub fn new(inner: Box<dyn EmbeddingProvider>, breaker: Arc<CircuitBreaker>) -> Self {
+        BreakerProvider { inner, breaker }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for BreakerProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.inner.dimensions()
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+        if !self.breaker.allow() {
+            return Err(EmbeddingError::Unavailable);
+        }
+        let result = self.inner.embed(texts).await;
+        match &result {
+            Err(e) if e.is_retryable() => self.breaker.failure(),
+            _ => self.breaker.success(),
+        }
+        result
+    }
+}
@@ -0,0 +1,354 @@
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use uuid::Uuid;
+
+use crate::{
+    embeddings::{EmbeddingError, EmbeddingProvider},
+    models::response::{EmbeddingData, EmbeddingResponse, Tokens},
+};
+
+// Hit / miss totals are logged once per this many looked up texts
+const LOG_EVERY: u64 = 10_000;
+
+// Content-addressed cache in front of a provider. The extension re-sends
+// the same tweets while the user scrolls, so only texts we have never
+// embedded with this model are forwarded to the inner provider.
+pub struct CachedProvider {
+    inner: Box<dyn EmbeddingProvider>,
+    memory: Mutex<Lru>,
+    disk: Option<DiskStore>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CachedProvider {
+    pub fn new(inner: Box<dyn EmbeddingProvider>, capacity: usize, dir: Option<PathBuf>) -> Self {
+        CachedProvider {
+            inner,
+            memory: Mutex::new(Lru::new(capacity)),
+            disk: dir.map(|dir| DiskStore { dir }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn count(&self, hits: u64, misses: u64) {
+        let before = self.hits.fetch_add(hits, Ordering::Relaxed) + self.misses.fetch_add(misses, Ordering::Relaxed);
+        if before / LOG_EVERY != (before + hits + misses) / LOG_EVERY {
+            println!(
+                "Embedding cache: {} hits, {} misses since start",
+                self.hits.load(Ordering::Relaxed),
+                self.misses.load(Ordering::Relaxed)
+            );
+        }
+    }
+
+    // model name + dimensions + whitespace normalized text, so re-sent tweets with
+    // different spacing still hit and switching models never returns stale vectors
+    fn key(&self, text: &str) -> String {
+        let normalized = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let dimensions = self.inner.dimensions().map(|d| d.to_string()).unwrap_or_default();
+        let combined = format!("{}:{}:{}", self.inner.model(), dimensions, normalized);
+        Uuid::new_v5(&Uuid::NAMESPACE_OID, combined.as_bytes()).to_string()
+    }
+
+    async fn lookup(&self, key: &str) -> Option<Vec<f32>> {
+        if let Some(vector) = self.memory.lock().unwrap().get(key) {
+            return Some(vector);
+        }
+        let vector = self.disk.as_ref()?.get(key).await?;
+        self.memory.lock().unwrap().put(key.to_string(), vector.clone());
+        Some(vector)
+    }
+
+    async fn store(&self, key: String, vector: Vec<f32>) {
+        if let Some(disk) = &self.disk {
+            if let Err(e) = disk.put(&key, &vector).await {
+                eprintln!("Embedding cache write failed: {:?}", e);
+            }
+        }
+        self.memory.lock().unwrap().put(key, vector);
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for CachedProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.inner.dimensions()
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+        let keys: Vec<String> = texts.iter().map(|t| self.key(t)).collect();
+        let mut vectors: Vec<Option<Vec<f32>>> = Vec::with_capacity(texts.len());
+        for key in &keys {
+            vectors.push(self.lookup(key).await);
+        }
+
+        // unique misses, a text repeated inside one batch is only embedded once
+        let mut miss_keys: Vec<&String> = Vec::new();
+        let mut miss_texts: Vec<String> = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            if vectors[i].is_none() && !miss_keys.contains(&key) {
+                miss_keys.push(key);
+                miss_texts.push(texts[i].clone());
+            }
+        }
+
+        self.count((texts.len() - miss_texts.len()) as u64, miss_texts.len() as u64);
+
+        let mut usage = Tokens::default();
+        let mut model = self.inner.model().to_string();
+
+        if !miss_texts.is_empty() {
+            let response = self.inner.embed(&miss_texts).await?;
+            usage = response.usage;
+            model = response.model;
+
+            let mut fresh: HashMap<&String, Vec<f32>> = HashMap::new();
+            for data in response.data {
+                if let Some(key) = miss_keys.get(data.index as usize) {
+                    self.store((*key).clone(), data.embedding.clone()).await;
+                    fresh.insert(*key, data.embedding);
+                }
+            }
+
+            for (i, key) in keys.iter().enumerate() {
+                if vectors[i].is_none() {
+                    vectors[i] = fresh.get(key).cloned();
+                }
+            }
+        }
+
+        // a gap would shift every later index and pair tweets with the wrong vectors
+        let missing = vectors.iter().filter(|vector| vector.is_none()).count();
+        if missing > 0 {
+            return Err(EmbeddingError::Incomplete { missing, expected: texts.len() });
+        }
+
+        // back in the caller's order, index == position in `texts`
+        let data: Vec<EmbeddingData> = vectors
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, embedding)| EmbeddingData {
+                object: "embedding".to_string(),
+                embedding,
+                index: index as u32,
+            })
+            .collect();
+
+        Ok(EmbeddingResponse {
+            object: "list".to_string(),
+            data,
+            model,
+            usage,
+        })
+    }
+}
+
+// Bounded least-recently-used map, `ticks` orders keys by last access
+struct Lru {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, (Vec<f32>, u64)>,
+    ticks: BTreeMap<u64, String>,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Lru {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            ticks: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Vec<f32>> {
+        self.tick += 1;
+        let (vector, last_used) = self.entries.get_mut(key)?;
+        self.ticks.remove(last_used);
+        *last_used = self.tick;
+        self.ticks.insert(self.tick, key.to_string());
+        Some(vector.clone())
+    }
+
+    fn put(&mut self, key: String, vector: Vec<f32>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, last_used)) = self.entries.insert(key.clone(), (vector, self.tick)) {
+            self.ticks.remove(&last_used);
+        }
+        self.ticks.insert(self.tick, key);
+
+        while self.entries.len() > self.capacity {
+            match self.ticks.pop_first() {
+                Some((_, oldest)) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+// One little-endian f32 file per key, sharded by the first two characters
+struct DiskStore {
+    dir: PathBuf,
+}
+
+impl DiskStore {
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[..2]).join(format!("{}.f32", key))
+    }
+
+    async fn get(&self, key: &str) -> Option<Vec<f32>> {
+        let bytes = tokio::fs::read(self.path(key)).await.ok()?;
+        if bytes.len() % 4 != 0 {
+            return None;
+        }
+        Some(
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        )
+    }
+
+    async fn put(&self, key: &str, vector: &[f32]) -> std::io::Result<()> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let bytes: Vec<u8> = vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+        // write then rename so a crash never leaves a half written vector behind
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, bytes).await?;
+        tokio::fs::rename(tmp, path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    // Answers with [length, first byte] of every text and remembers what it was asked
+    #[derive(Clone, Default)]
+    struct Stub {
+        calls: Arc<Mutex<Vec<Vec<String>>>>,
+        // drop the last vector of every answer
+        short: bool,
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for Stub {
+        fn model(&self) -> &str {
+            "stub"
+        }
+
+        fn dimensions(&self) -> Option<usize> {
+            None
+        }
+
+        async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+            self.calls.lock().unwrap().push(texts.to_vec());
+            let mut data: Vec<EmbeddingData> = texts
+                .iter()
+                .enumerate()
+                .map(|(index, text)| EmbeddingData {
+                    object: "embedding".to_string(),
+                    embedding: vector(text),
+                    index: index as u32,
+                })
+                .collect();
+            if self.short {
+                data.pop();
+            }
+            let tokens = texts.len() as u32;
+            Ok(EmbeddingResponse {
+                object: "list".to_string(),
+                data,
+                model: "stub".to_string(),
+                usage: Tokens { prompt_tokens: tokens, total_tokens: tokens },
+            })
+        }
+    }
+
+    fn vector(text: &str) -> Vec<f32> {
+        vec![text.len() as f32, text.as_bytes()[0] as f32]
+    }
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|t| t.to_string()).collect()
+    }
+
+    fn cached(stub: &Stub, capacity: usize) -> CachedProvider {
+        CachedProvider::new(Box::new(stub.clone()), capacity, None)
+    }
+
+    #[tokio::test]
+    async fn hits_and_misses_come_back_in_the_callers_order() {
+        let stub = Stub::default();
+        let cache = cached(&stub, 10);
+        cache.embed(&texts(&["a", "bb"])).await.unwrap();
+
+        let response = cache.embed(&texts(&["ccc", "a", "dddd", "bb"])).await.unwrap();
+        // only the misses were sent, and only they are paid for
+        assert_eq!(stub.calls.lock().unwrap()[1], texts(&["ccc", "dddd"]));
+        assert_eq!(response.usage.total_tokens, 2);
+        let indexes: Vec<u32> = response.data.iter().map(|d| d.index).collect();
+        assert_eq!(indexes, vec![0, 1, 2, 3]);
+        let vectors: Vec<Vec<f32>> = response.data.into_iter().map(|d| d.embedding).collect();
+        assert_eq!(vectors, vec![vector("ccc"), vector("a"), vector("dddd"), vector("bb")]);
+        assert_eq!((cache.hits.load(Ordering::Relaxed), cache.misses.load(Ordering::Relaxed)), (2, 4));
+    }
+
+    #[tokio::test]
+    async fn repeated_texts_are_embedded_once() {
+        let stub = Stub::default();
+        let cache = cached(&stub, 10);
+        let response = cache.embed(&texts(&["x", "yy", "x", "yy  "])).await.unwrap();
+
+        // the trailing whitespace does not make a new text either
+        assert_eq!(*stub.calls.lock().unwrap(), vec![texts(&["x", "yy"])]);
+        let vectors: Vec<Vec<f32>> = response.data.into_iter().map(|d| d.embedding).collect();
+        assert_eq!(vectors, vec![vector("x"), vector("yy"), vector("x"), vector("yy")]);
+    }
+
+    #[tokio::test]
+    async fn the_least_recently_used_vector_is_evicted() {
+        let stub = Stub::default();
+        let cache = cached(&stub, 2);
+        for text in ["a", "b", "a", "c"] {
+            cache.embed(&texts(&[text])).await.unwrap();
+        }
+        // "a" was used after "b", so "b" made room for "c"
+        cache.embed(&texts(&["a", "b", "c"])).await.unwrap();
+        assert_eq!(stub.calls.lock().unwrap().last().unwrap(), &texts(&["b"]));
+    }
+
+    #[tokio::test]
+    async fn a_missing_vector_is_an_error() {
+        let stub = Stub { short: true, ..Default::default() };
+        let cache = cached(&stub, 10);
+        match cache.embed(&texts(&["a", "bb", "ccc"])).await {
+            Err(EmbeddingError::Incomplete { missing, expected }) => assert_eq!((missing, expected), (1, 3)),
+            other => panic!("expected an incomplete answer error, got {:?}", other.map(|r| r.data.len())),
+        }
+    }
+}
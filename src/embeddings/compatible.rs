@@ -0,0 +1,49 @@
+use async_trait::async_trait;
+
+use crate::{
+    embeddings::{request_body, send, EmbeddingError, EmbeddingProvider, ModelSpec},
+    models::response::EmbeddingResponse,
+};
+
+// Generic OpenAI-compatible server, most self-hosted ones run without a key
+pub struct CompatibleProvider {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: Option<String>,
+    model: ModelSpec,
+}
+
+impl CompatibleProvider {
+    pub fn new(
+        client: reqwest::Client,
+        endpoint: String,
+        api_key: Option<String>,
+        model: ModelSpec,
+    ) -> Self {
+        CompatibleProvider { client, endpoint, api_key, model }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for CompatibleProvider {
+    fn model(&self) -> &str {
+        &self.model.name
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.model.dimensions
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+        let mut request = self
+            .client
+            .post(&self.endpoint)
+            .json(&request_body(&self.model, texts));
+
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        send(request).await
+    }
+}
@@ -0,0 +1,72 @@
+use std::{fmt, time::Duration};
+
+#[derive(Debug)]
+pub enum EmbeddingError {
+    // Connection, timeout or body decoding problems
+    Request(reqwest::Error),
+    // The provider answered with a non 2xx status
+    Status {
+        status: u16,
+        retry_after: Option<Duration>,
+        body: String,
+    },
+    // A single input is above the per-input token limit and truncation is off
+    InputTooLong { index: usize, tokens: usize, limit: usize },
+    // Refused without calling the provider, the circuit breaker is open
+    Unavailable,
+    // The provider answered without a vector for every input
+    Incomplete { missing: usize, expected: usize },
+}
+
+impl fmt::Display for EmbeddingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddingError::Request(e) => write!(f, "Embedding request failed: {}", e),
+            EmbeddingError::Status { status, body, .. } => {
+                write!(f, "Embedding provider returned {}: {}", status, body)
+            }
+            EmbeddingError::InputTooLong { index, tokens, limit } => write!(
+                f,
+                "Input {} is ~{} tokens, above the limit of {}",
+                index, tokens, limit
+            ),
+            EmbeddingError::Unavailable => write!(f, "Embedding provider unavailable, circuit open"),
+            EmbeddingError::Incomplete { missing, expected } => {
+                write!(f, "Embedding provider returned no vector for {} of {} inputs", missing, expected)
+            }
+        }
+    }
+}
+
+impl EmbeddingError {
+    // Worth another attempt: network trouble, rate limiting or a server side error
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            EmbeddingError::Request(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            EmbeddingError::Status { status, .. } => *status == 429 || *status >= 500,
+            EmbeddingError::InputTooLong { .. } | EmbeddingError::Unavailable | EmbeddingError::Incomplete { .. } => false,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            EmbeddingError::Status { status: 429 | 503, retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for EmbeddingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmbeddingError::Request(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for EmbeddingError {
+    fn from(e: reqwest::Error) -> Self {
+        EmbeddingError::Request(e)
+    }
+}
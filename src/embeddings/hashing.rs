@@ -0,0 +1,98 @@
+use async_trait::async_trait;
+
+use crate::{
+    embeddings::{EmbeddingError, EmbeddingProvider},
+    models::response::{EmbeddingData, EmbeddingResponse, Tokens},
+};
+
+// Deterministic local embeddings for development and tests.
+// Every character n-gram of the lowercased text is hashed into one of
+// `dimensions` buckets with a +/-1 sign (the "hashing trick"), and the
+// result is L2 normalized so cosine scores behave like the real thing.
+// Texts sharing many n-grams land close together, identical texts always
+// produce identical vectors on every machine.
+pub struct HashingProvider {
+    dimensions: usize,
+    ngram: usize,
+    model: String,
+}
+
+impl HashingProvider {
+    pub fn new(dimensions: usize, ngram: usize) -> Self {
+        HashingProvider {
+            dimensions,
+            ngram,
+            model: format!("local-hashing-{}gram-{}", ngram, dimensions),
+        }
+    }
+
+    fn vector(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; self.dimensions];
+
+        // pad with spaces so short words and word boundaries still produce n-grams
+        let chars: Vec<char> = format!(" {} ", text.to_lowercase()).chars().collect();
+        let n = self.ngram.min(chars.len());
+
+        for gram in chars.windows(n) {
+            let hash = fnv1a(gram);
+            let bucket = (hash % self.dimensions as u64) as usize;
+            let sign = if (hash >> 63) == 0 { 1.0 } else { -1.0 };
+            vector[bucket] += sign;
+        }
+
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= norm);
+        }
+        vector
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for HashingProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        Some(self.dimensions)
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+        let data: Vec<EmbeddingData> = texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| EmbeddingData {
+                object: "embedding".to_string(),
+                embedding: self.vector(text),
+                index: index as u32,
+            })
+            .collect();
+
+        // rough token count so usage logging / accounting still has numbers to work with
+        let tokens: u32 = texts.iter().map(|t| t.split_whitespace().count() as u32).sum();
+
+        Ok(EmbeddingResponse {
+            object: "list".to_string(),
+            data,
+            model: self.model.clone(),
+            usage: Tokens {
+                prompt_tokens: tokens,
+                total_tokens: tokens,
+            },
+        })
+    }
+}
+
+// FNV-1a, stable across platforms and Rust releases unlike std's DefaultHasher
+fn fnv1a(chars: &[char]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for c in chars {
+        let mut buf = [0u8; 4];
+        for byte in c.encode_utf8(&mut buf).as_bytes() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
@@ -0,0 +1,174 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+
+use crate::{breaker::CircuitBreaker, config::{EmbeddingConfig, ProviderConfig, VectorConfig}, models::response::EmbeddingResponse};
+
+pub mod azure;
+pub mod breaker;
+pub mod cache;
+pub mod compatible;
+pub mod error;
+pub mod hashing;
+pub mod openai;
+pub mod retry;
+pub mod sparse;
+pub mod split;
+pub mod truncate;
+
+pub use error::EmbeddingError;
+
+// Model name sent to the provider plus the optional `dimensions` parameter
+// (text-embedding-3-* can shorten their output, None keeps the model default)
+#[derive(Debug, Clone)]
+pub struct ModelSpec {
+    pub name: String,
+    pub dimensions: Option<usize>,
+}
+
+// Anything that can turn a batch of texts into vectors. The returned
+// EmbeddingResponse keeps the OpenAI shape (data[i].index == position of
+// the text in `texts`) so the Qdrant conversion code stays vendor agnostic.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    fn model(&self) -> &str;
+
+    // Output size that was asked for, None when the model decides
+    fn dimensions(&self) -> Option<usize>;
+
+    async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError>;
+}
+
+pub fn build_provider(
+    config: &EmbeddingConfig,
+    vectors: &VectorConfig,
+    breaker: Arc<CircuitBreaker>,
+) -> Box<dyn EmbeddingProvider> {
+    let provider = cached_provider(config, breaker);
+
+    // truncation wraps the cache so full vectors are cached and any prefix can be taken
+    match vectors.truncate_dimensions {
+        Some(dimensions) => Box::new(truncate::TruncatingProvider::new(provider, dimensions)),
+        None => provider,
+    }
+}
+
+fn cached_provider(config: &EmbeddingConfig, breaker: Arc<CircuitBreaker>) -> Box<dyn EmbeddingProvider> {
+    // cache sits in front so only misses are split and sent (and cache hits
+    // are still served while the circuit is open), every split request is
+    // retried on its own
+    let limits = &config.limits;
+    let provider: Box<dyn EmbeddingProvider> = Box::new(split::SplittingProvider::new(
+        Box::new(breaker::BreakerProvider::new(
+            Box::new(retry::RetryingProvider::new(
+                vendor_provider(config),
+                config.retry.max_retries,
+                config.retry.base_delay,
+                config.retry.max_delay,
+            )),
+            breaker,
+        )),
+        limits.max_inputs,
+        limits.max_batch_tokens,
+        limits.max_input_tokens,
+        limits.truncate,
+    ));
+
+    if config.cache.capacity == 0 && config.cache.dir.is_none() {
+        return provider;
+    }
+    Box::new(cache::CachedProvider::new(
+        provider,
+        config.cache.capacity,
+        config.cache.dir.clone(),
+    ))
+}
+
+// Embeds a short probe text to learn the real vector size of the configured
+// model, used at startup to check it against the Qdrant collection.
+pub async fn probe_dimensions(provider: &dyn EmbeddingProvider) -> Result<usize, EmbeddingError> {
+    let response = provider.embed(&["dimension probe".to_string()]).await?;
+    Ok(response.data.first().map(|d| d.embedding.len()).unwrap_or(0))
+}
+
+fn vendor_provider(config: &EmbeddingConfig) -> Box<dyn EmbeddingProvider> {
+    let client = reqwest::Client::new();
+    let model = ModelSpec {
+        name: config.model.clone(),
+        dimensions: config.dimensions,
+    };
+
+    match &config.provider {
+        ProviderConfig::OpenAi { api_key, endpoint } => Box::new(openai::OpenAiProvider::new(
+            client,
+            endpoint.clone(),
+            api_key.clone(),
+            model.clone(),
+        )),
+        ProviderConfig::Azure { api_key, endpoint, deployment, api_version } => {
+            Box::new(azure::AzureOpenAiProvider::new(
+                client,
+                endpoint,
+                deployment,
+                api_version,
+                api_key.clone(),
+                model.clone(),
+            ))
+        }
+        ProviderConfig::Compatible { endpoint, api_key } => {
+            Box::new(compatible::CompatibleProvider::new(
+                client,
+                endpoint.clone(),
+                api_key.clone(),
+                model.clone(),
+            ))
+        }
+        ProviderConfig::Hashing { dimensions, ngram } => {
+            Box::new(hashing::HashingProvider::new(*dimensions, *ngram))
+        }
+    }
+}
+
+// Request body shared by every OpenAI-style embeddings API
+pub(crate) fn request_body(model: &ModelSpec, texts: &[String]) -> serde_json::Value {
+    let mut body = json!({
+        "input": texts,
+        "model": model.name
+    });
+    if let Some(dimensions) = model.dimensions {
+        body["dimensions"] = json!(dimensions);
+    }
+    body
+}
+
+// Sends an already authenticated request and decodes the embeddings response
+pub(crate) async fn send(request: reqwest::RequestBuilder) -> Result<EmbeddingResponse, EmbeddingError> {
+    let response = request
+        .header("Content-Type", "application/json")
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.text().await.unwrap_or_default();
+        return Err(EmbeddingError::Status { status, retry_after, body });
+    }
+
+    let embedding: EmbeddingResponse = response.json().await?;
+    Ok(embedding)
+}
+
+// Retry-After is either delay-seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
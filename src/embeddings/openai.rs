@@ -0,0 +1,40 @@
+use async_trait::async_trait;
+
+use crate::{
+    embeddings::{request_body, send, EmbeddingError, EmbeddingProvider, ModelSpec},
+    models::response::EmbeddingResponse,
+};
+
+pub struct OpenAiProvider {
+    client: reqwest::Client,
+    endpoint: String,
+    api_key: String,
+    model: ModelSpec,
+}
+
+impl OpenAiProvider {
+    pub fn new(client: reqwest::Client, endpoint: String, api_key: String, model: ModelSpec) -> Self {
+        OpenAiProvider { client, endpoint, api_key, model }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiProvider {
+    fn model(&self) -> &str {
+        &self.model.name
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.model.dimensions
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+        let request = self
+            .client
+            .post(&self.endpoint)
+            .bearer_auth(&self.api_key)
+            .json(&request_body(&self.model, texts));
+
+        send(request).await
+    }
+}
@@ -0,0 +1,87 @@
+use async_trait::async_trait;
+use rand::Rng;
+use std::time::Duration;
+
+use crate::{
+    embeddings::{EmbeddingError, EmbeddingProvider},
+    models::response::EmbeddingResponse,
+};
+
+// Retries transient provider failures with exponential backoff and full
+// jitter. A Retry-After sent with 429/503 is used as the minimum wait, one
+// longer than max_delay fails the call right away instead of stalling the batch.
+pub struct RetryingProvider {
+    inner: Box<dyn EmbeddingProvider>,
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryingProvider {
+    pub fn new(
+        inner: Box<dyn EmbeddingProvider>,
+        max_retries: u32,
+        base_delay: Duration,
+        max_delay: Duration,
+    ) -> Self {
+        RetryingProvider {
+            inner,
+            max_retries,
+            base_delay,
+            max_delay,
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let millis = rand::rng().random_range(0..=ceiling.as_millis() as u64);
+        Duration::from_millis(millis)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for RetryingProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.inner.dimensions()
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+        let mut attempt = 0;
+        loop {
+            match self.inner.embed(texts).await {
+                Ok(response) => return Ok(response),
+                Err(e) if e.is_retryable() && attempt < self.max_retries => {
+                    let delay = match e.retry_after() {
+                        Some(retry_after) if retry_after > self.max_delay => {
+                            eprintln!(
+                                "Embedding provider asked to retry in {}s, more than the {}ms cap, giving up",
+                                retry_after.as_secs(),
+                                self.max_delay.as_millis()
+                            );
+                            return Err(e);
+                        }
+                        Some(retry_after) => retry_after.max(self.backoff(attempt)).min(self.max_delay),
+                        None => self.backoff(attempt),
+                    };
+                    attempt += 1;
+                    eprintln!(
+                        "Embedding attempt {}/{} failed, retrying in {}ms: {}",
+                        attempt,
+                        self.max_retries + 1,
+                        delay.as_millis(),
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
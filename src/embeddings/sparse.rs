@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+
+use crate::models::middleware::SparseVector;
+
+// Local BM25 style sparse vectors for keyword matching (product names,
+// tickers, handles) that dense embeddings blur. Terms are hashed into the
+// u32 index space, Qdrant applies the IDF part through the `idf` modifier
+// of the sparse vector so only the term frequency side is computed here.
+
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+// average tweet length in terms, good enough for the length normalization
+const AVG_DOC_LEN: f32 = 20.0;
+
+// Document side: saturated, length normalized term frequencies
+pub fn document_vector(text: &str) -> SparseVector {
+    let terms = terms(text);
+    let doc_len = terms.len() as f32;
+
+    let mut counts: BTreeMap<u32, f32> = BTreeMap::new();
+    for term in &terms {
+        *counts.entry(term_index(term)).or_default() += 1.0;
+    }
+
+    let norm = K1 * (1.0 - B + B * doc_len / AVG_DOC_LEN);
+    let (indices, values) = counts
+        .into_iter()
+        .map(|(index, tf)| (index, tf * (K1 + 1.0) / (tf + norm)))
+        .unzip();
+    SparseVector { indices, values }
+}
+
+// Query side: every distinct term counts once
+pub fn query_vector(text: &str) -> SparseVector {
+    let mut counts: BTreeMap<u32, f32> = BTreeMap::new();
+    for term in terms(text) {
+        counts.insert(term_index(&term), 1.0);
+    }
+    let (indices, values) = counts.into_iter().unzip();
+    SparseVector { indices, values }
+}
+
+// Lowercased words, keeping the @ / # / $ prefix so handles, hashtags and
+// tickers stay distinct terms ("$AAPL" != "aapl")
+fn terms(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || matches!(c, '@' | '#' | '$' | '_')))
+        .map(|t| t.trim_end_matches(['@', '#', '$']))
+        .filter(|t| t.chars().filter(|c| c.is_alphanumeric()).count() >= 2)
+        .map(str::to_string)
+        .collect()
+}
+
+// FNV-1a, the index has to be identical between save time and query time
+fn term_index(term: &str) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in term.as_bytes() {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
@@ -0,0 +1,207 @@
+use async_trait::async_trait;
+
+use crate::{
+    embeddings::{EmbeddingError, EmbeddingProvider},
+    models::response::{EmbeddingData, EmbeddingResponse, Tokens},
+};
+
+// Splits one large batch into requests that stay under the provider's
+// per-request input and token limits, then stitches the answers back into
+// a single response as if it had been one call.
+pub struct SplittingProvider {
+    inner: Box<dyn EmbeddingProvider>,
+    max_inputs: usize,
+    max_batch_tokens: usize,
+    max_input_tokens: usize,
+    truncate: bool,
+}
+
+impl SplittingProvider {
+    pub fn new(
+        inner: Box<dyn EmbeddingProvider>,
+        max_inputs: usize,
+        max_batch_tokens: usize,
+        max_input_tokens: usize,
+        truncate: bool,
+    ) -> Self {
+        SplittingProvider {
+            inner,
+            max_inputs,
+            max_batch_tokens,
+            max_input_tokens,
+            truncate,
+        }
+    }
+
+    // Over-long texts are cut down to the per-input limit or rejected
+    fn fit(&self, index: usize, text: &str) -> Result<String, EmbeddingError> {
+        let tokens = estimate_tokens(text);
+        if tokens <= self.max_input_tokens {
+            return Ok(text.to_string());
+        }
+        if !self.truncate {
+            return Err(EmbeddingError::InputTooLong {
+                index,
+                tokens,
+                limit: self.max_input_tokens,
+            });
+        }
+        let max_chars = self.max_input_tokens * CHARS_PER_TOKEN;
+        Ok(text.chars().take(max_chars).collect())
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for SplittingProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        self.inner.dimensions()
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+        let fitted: Vec<String> = texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| self.fit(i, text))
+            .collect::<Result<_, _>>()?;
+
+        // (offset of the chunk in `texts`, chunk)
+        let mut chunks: Vec<(usize, Vec<String>)> = Vec::new();
+        let mut current: Vec<String> = Vec::new();
+        let mut current_tokens = 0;
+        let mut offset = 0;
+
+        for (i, text) in fitted.into_iter().enumerate() {
+            let tokens = estimate_tokens(&text);
+            let full = current.len() >= self.max_inputs
+                || current_tokens + tokens > self.max_batch_tokens;
+            if full && !current.is_empty() {
+                chunks.push((offset, std::mem::take(&mut current)));
+                current_tokens = 0;
+                offset = i;
+            }
+            current_tokens += tokens;
+            current.push(text);
+        }
+        if !current.is_empty() {
+            chunks.push((offset, current));
+        }
+
+        if chunks.len() > 1 {
+            println!("Splitting {} texts into {} embedding requests", texts.len(), chunks.len());
+        }
+
+        let mut data: Vec<EmbeddingData> = Vec::with_capacity(texts.len());
+        let mut usage = Tokens::default();
+        let mut model = self.inner.model().to_string();
+        let mut object = "list".to_string();
+
+        for (offset, chunk) in chunks {
+            let response = self.inner.embed(&chunk).await?;
+            usage.prompt_tokens += response.usage.prompt_tokens;
+            usage.total_tokens += response.usage.total_tokens;
+            model = response.model;
+            object = response.object;
+
+            // indexes are relative to the sub request, shift them back
+            data.extend(response.data.into_iter().map(|mut d| {
+                d.index += offset as u32;
+                d
+            }));
+        }
+        data.sort_by_key(|d| d.index);
+
+        Ok(EmbeddingResponse {
+            object,
+            data,
+            model,
+            usage,
+        })
+    }
+}
+
+// Rough BPE estimate (~4 characters per token for English), we only need it
+// to stay on the safe side of the provider limits, not to bill anyone.
+const CHARS_PER_TOKEN: usize = 4;
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    // Answers every sub request in reverse order, with indexes relative to
+    // that request, and charges 10 tokens per text
+    #[derive(Clone, Default)]
+    struct Stub {
+        calls: Arc<Mutex<Vec<Vec<String>>>>,
+    }
+
+    #[async_trait]
+    impl EmbeddingProvider for Stub {
+        fn model(&self) -> &str {
+            "stub"
+        }
+
+        fn dimensions(&self) -> Option<usize> {
+            None
+        }
+
+        async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+            self.calls.lock().unwrap().push(texts.to_vec());
+            let data = texts
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(index, text)| EmbeddingData {
+                    object: "embedding".to_string(),
+                    embedding: vec![text.len() as f32],
+                    index: index as u32,
+                })
+                .collect();
+            let tokens = 10 * texts.len() as u32;
+            Ok(EmbeddingResponse {
+                object: "list".to_string(),
+                data,
+                model: "stub".to_string(),
+                usage: Tokens { prompt_tokens: tokens, total_tokens: tokens + 1 },
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn sub_requests_are_stitched_back_in_order() {
+        let stub = Stub::default();
+        let provider = SplittingProvider::new(Box::new(stub.clone()), 2, 1000, 100, false);
+        let texts: Vec<String> = (1..=5).map(|n| "x".repeat(n)).collect();
+
+        let response = provider.embed(&texts).await.unwrap();
+        let sizes: Vec<usize> = stub.calls.lock().unwrap().iter().map(|c| c.len()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        let indexes: Vec<u32> = response.data.iter().map(|d| d.index).collect();
+        assert_eq!(indexes, vec![0, 1, 2, 3, 4]);
+        let vectors: Vec<f32> = response.data.iter().map(|d| d.embedding[0]).collect();
+        assert_eq!(vectors, vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(response.usage.prompt_tokens, 50);
+        assert_eq!(response.usage.total_tokens, 53);
+    }
+
+    #[tokio::test]
+    async fn the_token_budget_also_splits_a_batch() {
+        let stub = Stub::default();
+        // 8 characters are 2 estimated tokens, so only two texts fit in 5 tokens
+        let provider = SplittingProvider::new(Box::new(stub.clone()), 10, 5, 100, false);
+        let texts = vec!["a".repeat(8); 3];
+
+        provider.embed(&texts).await.unwrap();
+        let sizes: Vec<usize> = stub.calls.lock().unwrap().iter().map(|c| c.len()).collect();
+        assert_eq!(sizes, vec![2, 1]);
+    }
+}
@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+
+use crate::{
+    embeddings::{EmbeddingError, EmbeddingProvider},
+    models::response::EmbeddingResponse,
+};
+
+// Matryoshka style truncation: keeps the first `dimensions` values of every
+// vector and re-normalizes them so cosine scores stay comparable. Works with
+// any provider, unlike the `dimensions` request parameter.
+pub struct TruncatingProvider {
+    inner: Box<dyn EmbeddingProvider>,
+    dimensions: usize,
+}
+
+impl TruncatingProvider {
+    pub fn new(inner: Box<dyn EmbeddingProvider>, dimensions: usize) -> Self {
+        TruncatingProvider { inner, dimensions }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for TruncatingProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn dimensions(&self) -> Option<usize> {
+        Some(self.dimensions)
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<EmbeddingResponse, EmbeddingError> {
+        let mut response = self.inner.embed(texts).await?;
+        for data in response.data.iter_mut() {
+            data.embedding.truncate(self.dimensions);
+            let norm = data.embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                data.embedding.iter_mut().for_each(|v| *v /= norm);
+            }
+        }
+        Ok(response)
+    }
+}
@@ -1,28 +1,88 @@
 use actix_cors::Cors;
 use actix_web::{web, App, HttpServer};
 
-use std::env;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use tokio::sync::{Mutex, Notify, RwLock};
 
+mod config;
 mod embeddings;
 mod models;
+mod normalize;
 mod qdrant_functions;
 mod routes;
 mod auth;
+mod breaker;
+mod save_queue;
+mod vector_store;
 
-use embeddings::embed;
-use models::internal::{AppState, TweetPayload};
+use config::{Config, MatchConfig};
+use models::{internal::{AppState, TweetPayload}, recommend::{RecommendProfile, RecommendProfiles}, similarity_result::SimilarityResult};
 use routes::{
     routes::{handle_embed, handle_save, reset_qdrant, health},
     sockets::ws,
 };
 
 use crate::{qdrant_functions::{
-    middleware_conversion::{hashmap_score_user, into_compatible},
+    middleware_conversion::{hashmap_score_user, into_compatible, BatchMatching},
     search::similarity,
-    limits::*,
-}, routes::routes::{delete_points, search_payload}};
+    limits::{can_spend_tokens, match_settings, prorate_tokens, record_token_usage, EntitlementLocks},
+}, routes::routes::{backup, delete_points, get_match_settings, recommend_tweets, restore, search_payload, semantic_search, set_match_settings}, vector_store::tenancy::{Tenant, TenantStore}};
+
+type BatchOutcomes = HashMap<String, Result<Vec<SimilarityResult>, String>>;
+
+// Hand every user in the failed batch an explicit error instead of leaving
+// their WS session waiting for a result that never comes
+fn fail_batch(outcomes: &mut BatchOutcomes, batch: &TweetPayload, message: &str) {
+    for tweet in &batch.tweets {
+        outcomes.insert(tweet.user_id.clone(), Err(message.to_string()));
+    }
+}
+
+// Sessions are told when the failure comes from an open circuit
+fn batch_error(app_state: &AppState, message: &'static str) -> &'static str {
+    if app_state.breakers.degraded() { "Scoring unavailable" } else { message }
+}
+
+// Drops the tweets of users whose embedding token budget is used up,
+// returns the remaining batch and the users that were cut off
+async fn enforce_token_budgets(app_state: &AppState, batch: TweetPayload) -> (TweetPayload, HashSet<String>) {
+    let users: HashSet<String> = batch.tweets.iter().map(|t| t.user_id.clone()).collect();
+    let mut blocked = HashSet::new();
+    for user_id in users {
+        match can_spend_tokens(app_state.store.as_ref(), &app_state.entitlement_locks, user_id.clone(), &app_state.config.budgets).await {
+            Ok(true) => {}
+            Ok(false) => {
+                blocked.insert(user_id);
+            }
+            // don't stop scoring because the entitlement lookup hiccuped
+            Err(e) => eprintln!("Token budget check failed for {}: {}", user_id, e),
+        }
+    }
+
+    let tweets = batch.tweets.into_iter().filter(|t| !blocked.contains(&t.user_id)).collect();
+    (TweetPayload { tweets }, blocked)
+}
+
+// Score threshold and top-k of every user in the batch
+async fn load_match_settings(app_state: &AppState, batch: &TweetPayload) -> HashMap<String, MatchConfig> {
+    let users: HashSet<String> = batch.tweets.iter().map(|t| t.user_id.clone()).collect();
+    let mut settings = HashMap::new();
+    for user_id in users {
+        match match_settings(app_state.store.as_ref(), &app_state.entitlement_locks, user_id.clone(), &app_state.config.matching).await {
+            Ok(config) => {
+                settings.insert(user_id, config);
+            }
+            // the deployment defaults apply when the lookup fails
+            Err(e) => eprintln!("Match settings lookup failed for {}: {}", user_id, e),
+        }
+    }
+    settings
+}
+
+// Recommend profiles of the users in the batch that set one
+async fn load_profiles(app_state: &AppState, batch: &TweetPayload) -> HashMap<String, RecommendProfile> {
+    app_state.recommend_profiles.of(batch.tweets.iter().map(|t| t.user_id.as_str())).await
+}
 
 // Create a global buffer over here in which the tweets will be pushed
 // and every 0.6 secs the api will be called after which once there is
@@ -35,7 +95,7 @@ async fn main() -> std::io::Result<()> {
     // Remove .ok() and handle the error
     dotenv::dotenv().ok();
 
-    
+    let config = Config::from_env().expect("Invalid configuration");
 
     auth::jwt::refresh_jwks_if_needed()
         .await
@@ -54,11 +114,37 @@ async fn main() -> std::io::Result<()> {
     let host = "0.0.0.0";
     println!("Actix server running at http://{}:8080", host);
 
+    let breakers = breaker::Breakers::new(&config.resilience);
+    let embedder = embeddings::build_provider(&config.embedding, &config.vectors, breakers.embedder.clone());
+    println!("Embedding model: {}", embedder.model());
+
+    // Refuse to start when the model output does not fit the saved vectors,
+    // missing collections are created with the probed size
+    let dimensions = embeddings::probe_dimensions(embedder.as_ref())
+        .await
+        .map_err(|e| std::io::Error::other(format!("Embedding probe failed: {}", e)))?;
+    let store = vector_store::build_store(&config, dimensions, breakers.store.clone())
+        .await
+        .map_err(|e| std::io::Error::other(format!("Vector store setup failed: {}", e)))?;
+    println!("Vector store: {}", store.backend());
+    let tweets = TenantStore::new(store.clone(), config.tenancy);
+
     let app_state = web::Data::new(AppState {
         buffer: Mutex::new(TweetPayload { tweets: Vec::new() }),
         hashset: RwLock::new(HashMap::new()),
         notify: Notify::new(),
+        embedder,
+        normalizer: normalize::Normalizer::new(config.normalize.clone()),
+        store,
+        dimensions,
+        tweets,
+        save_queue: save_queue::SaveQueue::new(config.resilience.save_queue_capacity),
+        recommend_profiles: RecommendProfiles::default(),
+        entitlement_locks: EntitlementLocks::default(),
+        config,
+        breakers,
     });
+    save_queue::spawn_worker(app_state.clone());
 
     tokio::spawn({
         let app_state = app_state.clone();
@@ -78,48 +164,78 @@ async fn main() -> std::io::Result<()> {
                     tokio::time::sleep(std::time::Duration::from_millis(3000)).await;
                     continue;
                 }
-                let temp = embed(&buffer2).await;
-
-                match temp {
-                    Ok(embedding_response) => {
-                        println!(
-                            "Total tokens used: {:#?}",
-                            embedding_response.usage.total_tokens
-                        );
-
-                        let hashset = hashmap_score_user(
-                            similarity(into_compatible(&buffer2, &embedding_response)).await,
-                            buffer2
-                        );
-
-                        //app_state.notify.notify_waiters();
-
-                        match hashset {
-                            Ok(temp_hashset) => {
-                                // Printing tweets with similarity > 0.50 (score threashold logic)
-                                // for val in temp_hashset.values() {
-                                //     for (_i, item) in val.iter().enumerate() {
-                                //         if item.score > 0.50 {
-                                //             println!("{:#?}", item);
-                                //         }
-                                //     }
-                                // }
-
-                                let mut temp = app_state.hashset.write().await;
-                                *temp = temp_hashset;
-                                app_state.notify.notify_waiters();
-                            }
-                            Err(_e) => {
-                                eprintln!("Error printing similar tweets :( {:?}", _e);
+                let (buffer2, over_budget) = enforce_token_budgets(&app_state, buffer2).await;
+                let mut outcomes: BatchOutcomes = over_budget
+                    .into_iter()
+                    .map(|user_id| (user_id, Err("Token budget exceeded".to_string())))
+                    .collect();
+
+                if !buffer2.tweets.is_empty() {
+                    let texts = app_state.normalizer.texts(&buffer2);
+                    let temp = app_state.embedder.embed(&texts).await;
+
+                    match temp {
+                        Ok(embedding_response) => {
+                            println!(
+                                "Total tokens used: {:#?}",
+                                embedding_response.usage.total_tokens
+                            );
+
+                            // charge every user their share of the batch without holding up scoring
+                            let user_ids: Vec<String> = buffer2.tweets.iter().map(|t| t.user_id.clone()).collect();
+                            let shares = prorate_tokens(&user_ids, &texts, embedding_response.usage.total_tokens);
+                            let usage_state = app_state.clone();
+                            tokio::spawn(async move {
+                                for (user_id, tokens) in shares {
+                                    if let Err(e) = record_token_usage(usage_state.store.as_ref(), &usage_state.entitlement_locks, user_id.clone(), tokens).await {
+                                        eprintln!("Recording token usage failed for {}: {}", user_id, e);
+                                    }
+                                }
+                            });
+
+                            let user_settings = load_match_settings(&app_state, &buffer2).await;
+                            let profiles = load_profiles(&app_state, &buffer2).await;
+                            let matching = BatchMatching {
+                                users: &user_settings,
+                                defaults: &app_state.config.matching,
+                                profiles: &profiles,
+                            };
+                            let queries = into_compatible(&buffer2, &embedding_response, &app_state.config.vectors, &matching);
+                            // every search is scoped to the owner of its feed tweet
+                            let tenants: Vec<Tenant> = queries.tweets.iter().map(|&index| Tenant::from(&buffer2.tweets[index])).collect();
+                            let hashset = hashmap_score_user(
+                                similarity(&app_state.tweets, &tenants, queries.request).await,
+                                &queries.tweets,
+                                buffer2.clone(),
+                            );
+
+                            match hashset {
+                                Ok(temp_hashset) => {
+                                    outcomes.extend(
+                                        temp_hashset
+                                            .into_iter()
+                                            .map(|(user_id, results)| (user_id, Ok(results))),
+                                    );
+                                }
+                                Err(_e) => {
+                                    eprintln!("Error printing similar tweets :( {:?}", _e);
+                                    fail_batch(&mut outcomes, &buffer2, batch_error(&app_state, "Similarity search failed"));
+                                }
                             }
                         }
-                    }
-                    Err(_e) => {
-                        if true == false {
-                            println!("Error converting text to embedding :")
+                        Err(_e) => {
+                            // retries are exhausted at this point, tell the waiting sessions
+                            eprintln!("Error converting text to embedding : {}", _e);
+                            fail_batch(&mut outcomes, &buffer2, batch_error(&app_state, "Embedding failed"));
                         }
                     }
                 }
+
+                {
+                    let mut temp = app_state.hashset.write().await;
+                    *temp = outcomes;
+                }
+                app_state.notify.notify_waiters();
                 println!("{:#?}", app_state.hashset);
 
                 tokio::time::sleep(std::time::Duration::from_millis(3000)).await;
@@ -140,9 +256,15 @@ async fn main() -> std::io::Result<()> {
             .app_data(app_state.clone())
             .service(handle_embed)
             .service(search_payload)
+            .service(semantic_search)
+            .service(recommend_tweets)
             .service(delete_points)
             .service(handle_save)
             .service(reset_qdrant)
+            .service(backup)
+            .service(restore)
+            .service(get_match_settings)
+            .service(set_match_settings)
             .service(health)
             .service(ws)
             
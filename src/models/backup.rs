@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+// Bumped whenever the archive layout changes incompatibly
+pub const ARCHIVE_VERSION: u32 = 1;
+
+// Portable copy of everything one user saved, written by GET /backup and
+// read back by POST /restore. Only the dense vector is kept, the sparse
+// vector of hybrid mode is recomputed from the text on restore.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Archive {
+    pub version: u32,
+    pub user_id: String,
+    pub created_at: String,
+    // embedding model that produced the vectors
+    pub model: String,
+    // dense vector size, None when the archive has no vectors
+    #[serde(default)]
+    pub dimensions: Option<usize>,
+    pub points: Vec<ArchivedPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedPoint {
+    pub id: String,
+    // stored as is so fields added later survive a round trip
+    pub payload: Json,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<Vec<f32>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackupParams {
+    // false leaves the vectors out, restoring then re-embeds every text
+    #[serde(default = "include_vectors")]
+    pub vectors: bool,
+}
+
+fn include_vectors() -> bool {
+    true
+}
@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// GET /collections/{name} result, only the parts we check at startup
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollectionInfo {
+    pub config: CollectionConfig,
+    #[serde(default)]
+    pub payload_schema: HashMap<String, PayloadIndexInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PayloadIndexInfo {
+    pub data_type: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollectionConfig {
+    pub params: CollectionParams,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollectionParams {
+    pub vectors: VectorsConfig,
+    #[serde(default)]
+    pub sparse_vectors: Option<HashMap<String, serde_json::Value>>,
+    // "custom" when points are routed by shard key
+    #[serde(default)]
+    pub sharding_method: Option<String>,
+}
+
+// A collection has either one unnamed vector or a map of named vectors
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum VectorsConfig {
+    Single(VectorParams),
+    Named(HashMap<String, VectorParams>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VectorParams {
+    pub size: usize,
+    pub distance: String,
+}
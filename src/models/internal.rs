@@ -1,6 +1,6 @@
-use crate::models::similarity_result::SimilarityResult;
+use crate::{breaker::Breakers, config::Config, save_queue::SaveQueue, embeddings::EmbeddingProvider, models::{middleware::StoredVector, recommend::RecommendProfiles, similarity_result::SimilarityResult}, normalize::Normalizer, qdrant_functions::limits::EntitlementLocks, vector_store::{tenancy::TenantStore, VectorStore}};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 use tokio::sync::{Mutex, Notify, RwLock};
 
 
@@ -15,6 +15,17 @@ pub struct Tweet {
     pub id: Option<String>,
     pub text: String,
     pub username: String,
+    // when the tweet was posted, RFC 3339
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    // language code as reported by X, e.g. "en"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    // set from the verified token, never taken from the client
+    #[serde(default, skip)]
+    pub team_id: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
@@ -22,10 +33,27 @@ pub struct TweetPayload {
     pub tweets: Vec<Tweet>,
 }
 
+
 pub struct AppState {
     pub buffer: Mutex<TweetPayload>,
-    pub hashset: RwLock<HashMap<String, Vec<SimilarityResult>>>,
+    // per user outcome of the last batch, Err carries the message sent to the session
+    pub hashset: RwLock<HashMap<String, Result<Vec<SimilarityResult>, String>>>,
     pub notify: Notify,
+    pub embedder: Box<dyn EmbeddingProvider>,
+    pub normalizer: Normalizer,
+    pub store: Arc<dyn VectorStore>,
+    // dense vector size of the saved tweets, probed at startup
+    pub dimensions: usize,
+    // saved tweets, scoped per tenant
+    pub tweets: TenantStore,
+    pub config: Config,
+    pub breakers: Breakers,
+    // /save requests waiting for the vector store / embedding provider to recover
+    pub save_queue: SaveQueue,
+    // recommend profiles set over /ws, by user id
+    pub recommend_profiles: RecommendProfiles,
+    // serializes entitlement updates per user
+    pub entitlement_locks: EntitlementLocks,
 }
 
 //Qdrant Models
@@ -38,14 +66,32 @@ pub struct QdrantReqeust {
 pub struct PointVector {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
-    pub vector: Vec<f32>,
+    pub vector: StoredVector,
     pub payload: UserData,
 }
 
+// Payload of a saved tweet. Points saved before the metadata fields existed
+// only have `user_id` and `text`.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UserData {
     pub user_id: String,
     pub text: String,
+    // author, without the @
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    // X's id of the tweet, none for custom text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tweet_id: Option<String>,
+    // RFC 3339 (UTC)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_at: Option<String>,
+    // RFC 3339 (UTC), when the tweet was posted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub posted_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
 }
 
 
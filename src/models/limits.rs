@@ -13,6 +13,29 @@ pub struct UserEntitlement {
 
     pub last_reset_date: String, // YYYY-MM-DD
     pub valid_until: Option<String>, // ISO8601
+
+    // embedding token usage, defaults keep entitlements saved before this field loadable
+    #[serde(default)]
+    pub tokens_used_today: u64,
+    #[serde(default)]
+    pub tokens_used_month: u64,
+    #[serde(default)]
+    pub usage_month: String, // YYYY-MM
+
+    // user overrides of MATCH_MIN_SCORE / MATCH_TOP_K, None keeps the deployment value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub match_min_score: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub match_top_k: Option<u8>,
+}
+
+// GET / POST /match_settings, null fields fall back to the deployment defaults
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MatchSettings {
+    #[serde(default)]
+    pub min_score: Option<f32>,
+    #[serde(default)]
+    pub top_k: Option<u8>,
 }
 
 
@@ -22,21 +45,3 @@ pub struct EntitlementPoint {
     pub vector: Option<Vec<f32>>,
     pub payload: UserEntitlement,
 }
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct EntitlementRequest {
-    pub points: Vec<EntitlementPoint>,
-    pub next_page_offset: Option<u64>,
-}
-
-
-
-
-// limits results 
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct EntitlementRootSearch {
-    pub result: EntitlementRequest,
-    pub status: String,
-    pub time: f64,
-}
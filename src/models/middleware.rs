@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 // Qdrant search request model
 #[derive(Deserialize, Serialize)]
@@ -6,26 +7,137 @@ pub struct SearchRequest {
     pub searches: Vec<PointSearchVectors>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PointSearchVectors {
-    pub query: Vec<f32>,
+    // hybrid mode: dense and sparse candidates that `query` fuses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefetch: Option<Vec<Prefetch>>,
+    pub query: Query,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub using: Option<String>,
     pub filter: FilterType,
     pub with_payload: bool,
     pub limit: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<QueryParams>,
+    // points scoring lower are left out, only meaningful for cosine scores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score_threshold: Option<f32>,
+    // set by the tenancy layer in shard key mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard_key: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Prefetch {
+    pub query: Query,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub using: Option<String>,
+    pub filter: FilterType,
+    pub limit: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<QueryParams>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score_threshold: Option<f32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum Query {
+    Dense(Vec<f32>),
+    Sparse(SparseVector),
+    // {"fusion": "rrf"}
+    Fusion { fusion: String },
+    // {"recommend": {...}}
+    Recommend { recommend: RecommendInput },
+}
+
+// Closer to the positive examples and away from the negative ones. Examples
+// are always vectors, ids are resolved within the tenant beforehand.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RecommendInput {
+    pub positive: Vec<Vec<f32>>,
+    #[serde(default)]
+    pub negative: Vec<Vec<f32>>,
+    #[serde(default)]
+    pub strategy: RecommendStrategy,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecommendStrategy {
+    // one search with 2 * avg(positive) - avg(negative)
+    #[default]
+    AverageVector,
+    // every point scored against each example, also works with only negatives
+    BestScore,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SparseVector {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+// Vector(s) of a point being upserted: plain dense, or the unnamed ("")
+// dense vector next to the named sparse one in hybrid mode
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum StoredVector {
+    Dense(Vec<f32>),
+    Named(HashMap<String, NamedVector>),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum NamedVector {
+    Dense(Vec<f32>),
+    Sparse(SparseVector),
+}
+
+// Search time parameters, only sent when quantization is configured
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QueryParams {
+    pub quantization: QuantizationParams,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QuantizationParams {
+    pub rescore: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oversampling: Option<f32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct FilterType {
-    pub must: Vec<Must>,
+    pub must: Vec<Condition>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum Condition {
+    Field(Must),
+    // {"has_id": [..]}
+    HasId { has_id: Vec<String> },
+    // {"key": .., "range": {"gte": .., "lte": ..}} over an RFC 3339 datetime field
+    Range { key: String, range: DateRange },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct DateRange {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gte: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lte: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Must {
     pub key: String,
     pub r#match: KeyValue,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct KeyValue {
     pub value: String,
 }
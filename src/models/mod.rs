@@ -2,4 +2,8 @@ pub mod internal;
 pub mod response;
 pub mod middleware;
 pub mod similarity_result;
-pub mod limits;
\ No newline at end of file
+pub mod limits;
+pub mod collection;
+pub mod qdrant;
+pub mod backup;
+pub mod recommend;
\ No newline at end of file
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::middleware::FilterType;
+
+// Envelope of every Qdrant REST response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QdrantResponse<T> {
+    pub result: T,
+    pub status: serde_json::Value,
+    pub time: f64,
+}
+
+// Qdrant point ids are either unsigned integers or UUID strings,
+// ordered like Qdrant's scroll: integers first, then UUIDs
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(untagged)]
+pub enum PointId {
+    Num(u64),
+    Uuid(String),
+}
+
+// Result of upsert / delete / set payload
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateResult {
+    #[serde(default)]
+    pub operation_id: Option<u64>,
+    pub status: String,
+}
+
+// Outcome of a chunked save, point by point
+#[derive(Debug, Default, Serialize)]
+pub struct UpsertReport {
+    // applied and searchable
+    pub stored: Vec<String>,
+    // accepted without waiting (UPSERT_WAIT=false), applied shortly after
+    pub acknowledged: Vec<String>,
+    pub failed: Vec<FailedPoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedPoint {
+    pub id: String,
+    pub error: String,
+}
+
+// `shard_key` is only sent for collections with custom sharding
+#[derive(Debug, Serialize)]
+pub struct UpsertRequest<'a, P> {
+    pub points: &'a [P],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard_key: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteByFilter<'a> {
+    pub filter: &'a FilterType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard_key: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScrollRequest {
+    pub filter: FilterType,
+    pub limit: u32,
+    pub with_payload: bool,
+    #[serde(default)]
+    pub with_vector: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<PointId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScrollResult<P> {
+    pub points: Vec<P>,
+    pub next_page_offset: Option<PointId>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CountRequest<'a> {
+    pub filter: &'a FilterType,
+    pub exact: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard_key: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CountResult {
+    pub count: u64,
+}
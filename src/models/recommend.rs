@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::models::{middleware::RecommendStrategy, similarity_result::PayloadFilter};
+
+// Example tweets: saved tweets by point id and / or raw texts to embed
+#[derive(Debug, Deserialize, Default)]
+pub struct Examples {
+    #[serde(default)]
+    pub ids: Vec<String>,
+    #[serde(default)]
+    pub texts: Vec<String>,
+}
+
+impl Examples {
+    pub fn len(&self) -> usize {
+        self.ids.len() + self.texts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// POST /recommend
+#[derive(Debug, Deserialize)]
+pub struct RecommendParams {
+    #[serde(default)]
+    pub positive: Examples,
+    #[serde(default)]
+    pub negative: Examples,
+    #[serde(default)]
+    pub strategy: RecommendStrategy,
+    #[serde(default = "default_top_k")]
+    pub top_k: u32,
+    // applies to the ranked tweets, not to the examples
+    #[serde(default)]
+    pub filter: PayloadFilter,
+}
+
+fn default_top_k() -> u32 {
+    10
+}
+
+// {"recommend_profile": {...}} sent over /ws, null clears the profile. Feed
+// tweets are matched only against the positive saved tweets (all of them
+// when there are none) and steered away from the negatives.
+#[derive(Debug, Deserialize)]
+pub struct ProfileRequest {
+    #[serde(default)]
+    pub positive_ids: Vec<String>,
+    #[serde(default)]
+    pub negative: Examples,
+    #[serde(default)]
+    pub strategy: RecommendStrategy,
+}
+
+// A session's recommend profile with the negatives already resolved to vectors
+#[derive(Debug, Clone)]
+pub struct RecommendProfile {
+    pub positive_ids: Vec<String>,
+    pub negative: Vec<Vec<f32>>,
+    pub strategy: RecommendStrategy,
+}
+
+#[derive(Default)]
+struct ProfileSlot {
+    sessions: usize,
+    profile: Option<RecommendProfile>,
+}
+
+// Recommend profiles set over /ws, by user id. Feed tweets are matched per
+// user, so a profile is shared by the user's sessions and kept until the last
+// of them closes.
+#[derive(Default)]
+pub struct RecommendProfiles {
+    users: RwLock<HashMap<String, ProfileSlot>>,
+}
+
+impl RecommendProfiles {
+    pub async fn open(&self, user_id: &str) {
+        self.users.write().await.entry(user_id.to_string()).or_default().sessions += 1;
+    }
+
+    pub async fn close(&self, user_id: &str) {
+        let mut users = self.users.write().await;
+        if let Some(slot) = users.get_mut(user_id) {
+            slot.sessions -= 1;
+            if slot.sessions == 0 {
+                users.remove(user_id);
+            }
+        }
+    }
+
+    // Sets, or with None clears, the profile of a user with an open session
+    pub async fn set(&self, user_id: &str, profile: Option<RecommendProfile>) {
+        if let Some(slot) = self.users.write().await.get_mut(user_id) {
+            slot.profile = profile;
+        }
+    }
+
+    // Profiles of the given users that set one
+    pub async fn of<'a>(&self, user_ids: impl IntoIterator<Item = &'a str>) -> HashMap<String, RecommendProfile> {
+        let users = self.users.read().await;
+        user_ids
+            .into_iter()
+            .filter_map(|id| users.get(id)?.profile.clone().map(|profile| (id.to_string(), profile)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> RecommendProfile {
+        RecommendProfile { positive_ids: vec!["1".to_string()], negative: Vec::new(), strategy: RecommendStrategy::default() }
+    }
+
+    #[tokio::test]
+    async fn a_profile_outlives_all_but_the_last_session() {
+        let profiles = RecommendProfiles::default();
+        profiles.open("alice").await;
+        profiles.open("alice").await;
+        profiles.set("alice", Some(profile())).await;
+
+        profiles.close("alice").await;
+        assert!(profiles.of(["alice"]).await.contains_key("alice"));
+        profiles.close("alice").await;
+        assert!(profiles.of(["alice"]).await.is_empty());
+
+        // a new session starts without the old profile
+        profiles.open("alice").await;
+        assert!(profiles.of(["alice"]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_users_with_a_session_keep_a_profile() {
+        let profiles = RecommendProfiles::default();
+        profiles.set("bob", Some(profile())).await;
+        profiles.open("alice").await;
+        profiles.set("alice", Some(profile())).await;
+
+        let found = profiles.of(["alice", "bob", "carol"]).await;
+        assert_eq!(found.keys().collect::<Vec<_>>(), vec!["alice"]);
+        profiles.set("alice", None).await;
+        assert!(profiles.of(["alice"]).await.is_empty());
+    }
+}
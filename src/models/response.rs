@@ -2,26 +2,29 @@ use serde::{Deserialize, Serialize};
 
 
 // Embedding response model
+// `object` and `usage` are optional for OpenAI-compatible servers that omit them
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct EmbeddingResponse {
+    #[serde(default)]
     pub object: String,
     pub data: Vec<EmbeddingData>,
     pub model: String,
+    #[serde(default)]
     pub usage: Tokens
 }
 
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct EmbeddingData {
+    #[serde(default)]
     pub object: String,
     pub embedding: Vec<f32>,
     pub index: u32
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct Tokens {
     pub prompt_tokens: u32,
     pub total_tokens: u32
 }
 
-
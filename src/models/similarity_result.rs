@@ -1,13 +1,8 @@
 use serde::{Deserialize, Serialize};
 
-// Qdrant similarity result model
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Root {
-    pub result: Vec<ResultItem>,
-    pub status: String,
-    pub time: f64,
-}
+use crate::models::qdrant::ScrollResult;
 
+// Qdrant similarity result model (one entry per query of a batch)
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ResultItem {
     pub points: Vec<Point>,
@@ -25,15 +20,12 @@ pub struct Point {
 // Only for searching payload of a user_id
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RootSearch {
-    pub result: ResultItemSearch,
+    pub result: ScrollResult<PointSearch>,
     pub status: String,
     pub time: f64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ResultItemSearch {
-    pub points: Vec<PointSearch>,
-    pub next_page_offset: Option<u64>,
+    // pass back as `cursor` for the next page, null on the last page
+    #[serde(default)]
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,18 +37,75 @@ pub struct PointSearch {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Payload {
     pub user_id: String,
-    pub text: String
+    pub text: String,
+    // metadata of tweets saved since it was added, see `UserData`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tweet_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saved_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub posted_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+// Narrows /search, /search_payload and /recommend down by saved tweet
+// metadata, every given field must match. Dates are RFC 3339 and inclusive.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct PayloadFilter {
+    // author username, with or without the @
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub posted_after: Option<String>,
+    pub posted_before: Option<String>,
+    pub saved_after: Option<String>,
+    pub saved_before: Option<String>,
 }
 
-#[derive(Debug)]
+// A feed tweet and the saved tweets it matched
+#[derive(Debug, Serialize)]
 pub struct SimilarityResult {
     pub id: String,
     pub text: String,
+    // score of the best match
     pub score: f32,
+    // saved tweets above the user's threshold, best first, at most top_k
+    pub matches: Vec<SemanticHit>,
 }
 
+// POST /search
+#[derive(Debug, Deserialize)]
+pub struct SemanticSearchParams {
+    pub query: String,
+    #[serde(default = "default_top_k")]
+    pub top_k: u32,
+    #[serde(default)]
+    pub filter: PayloadFilter,
+}
+
+fn default_top_k() -> u32 {
+    10
+}
+
+// One ranked saved tweet
+#[derive(Debug, Serialize)]
+pub struct SemanticHit {
+    pub id: String,
+    pub score: f32,
+    pub text: String,
+}
+
+// Always pages the signed-in user's tweets, a `user_id` sent by older clients is ignored
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchParams {
-    pub user_id: String,
     pub limit: u32,
+    // `next_cursor` of the previous page, omitted for the first page
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub filter: PayloadFilter,
 }
\ No newline at end of file
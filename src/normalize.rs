@@ -0,0 +1,115 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    config::{HashtagMode, NormalizeConfig, UrlMode},
+    models::internal::TweetPayload,
+};
+
+// Cleans tweet text before it is embedded. Only the embedding input is
+// normalized, the original text is what gets stored and sent back.
+pub struct Normalizer {
+    config: NormalizeConfig,
+}
+
+impl Normalizer {
+    pub fn new(config: NormalizeConfig) -> Self {
+        Normalizer { config }
+    }
+
+    // Embedding inputs for a payload, in tweet order
+    pub fn texts(&self, payload: &TweetPayload) -> Vec<String> {
+        payload.tweets.iter().map(|tweet| self.normalize(&tweet.text)).collect()
+    }
+
+    pub fn normalize(&self, text: &str) -> String {
+        let text: String = if self.config.unicode {
+            text.nfkc().collect()
+        } else {
+            text.to_string()
+        };
+
+        let mut text = text.trim();
+        if self.config.strip_retweet {
+            text = strip_retweet_prefix(text);
+        }
+
+        let normalized = text
+            .split_whitespace()
+            .filter_map(|token| self.token(token))
+            .filter(|token| !token.is_empty())
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        // never hand the provider an empty input (a tweet that was only a link)
+        if normalized.is_empty() {
+            text.split_whitespace().collect::<Vec<&str>>().join(" ")
+        } else {
+            normalized
+        }
+    }
+
+    fn token(&self, token: &str) -> Option<String> {
+        if is_url(token) {
+            return match self.config.urls {
+                UrlMode::Keep => Some(token.to_string()),
+                UrlMode::Collapse => Some(url_host(token).to_string()),
+                UrlMode::Strip => None,
+            };
+        }
+
+        if token.starts_with('@') && token.len() > 1 && self.config.drop_mentions {
+            return None;
+        }
+
+        if let Some(tag) = token.strip_prefix('#').filter(|t| !t.is_empty()) {
+            match self.config.hashtags {
+                HashtagMode::Keep => {}
+                HashtagMode::Word => return Some(self.strip_emoji(tag)),
+                HashtagMode::Drop => return None,
+            }
+        }
+
+        Some(self.strip_emoji(token))
+    }
+
+    fn strip_emoji(&self, token: &str) -> String {
+        if !self.config.strip_emoji {
+            return token.to_string();
+        }
+        token.chars().filter(|c| !is_emoji(*c)).collect()
+    }
+}
+
+// "RT @user: text" -> "text"
+fn strip_retweet_prefix(text: &str) -> &str {
+    let Some(rest) = text.strip_prefix("RT @") else {
+        return text;
+    };
+    match rest.find(':') {
+        Some(end) if !rest[..end].contains(char::is_whitespace) => rest[end + 1..].trim_start(),
+        _ => text,
+    }
+}
+
+fn is_url(token: &str) -> bool {
+    let lower = token.to_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("www.")
+}
+
+// https://www.example.com/path?utm_source=x -> example.com
+fn url_host(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let host = without_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or(without_scheme);
+    host.strip_prefix("www.").unwrap_or(host)
+}
+
+// Pictographs, dingbats, flags plus the joiners / variation selectors that glue them together
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0xFE00..=0xFE0F | 0x200D | 0x20E3 | 0xE0020..=0xE007F
+    )
+}
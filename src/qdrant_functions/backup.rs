@@ -0,0 +1,140 @@
+use chrono::Utc;
+use serde_json::Value as Json;
+use uuid::Uuid;
+
+use crate::{
+    config::VectorConfig,
+    models::{
+        backup::{Archive, ArchivedPoint, ARCHIVE_VERSION},
+        middleware::{FilterType, NamedVector, StoredVector},
+        qdrant::{PointId, ScrollResult},
+    },
+    qdrant_functions::{error::QdrantError, middleware_conversion::stored_vector},
+    vector_store::tenancy::{Tenant, TenantStore},
+};
+
+const EXPORT_PAGE_SIZE: u32 = 500;
+
+// Why an uploaded archive is refused
+pub enum ArchiveProblem {
+    // made for, or containing points of, another user
+    NotOwned(String),
+    Invalid(String),
+}
+
+// Pages through everything the tenant saved
+pub async fn export(tweets: &TenantStore, tenant: &Tenant, model: &str, with_vectors: bool) -> Result<Archive, QdrantError> {
+    let mut points = Vec::new();
+    let mut offset: Option<PointId> = None;
+    loop {
+        let page: ScrollResult<Json> = tweets.scroll(tenant, &FilterType::default(), EXPORT_PAGE_SIZE, offset, with_vectors).await?;
+        points.extend(page.points.into_iter().map(archived_point));
+        offset = page.next_page_offset;
+        if offset.is_none() {
+            break;
+        }
+    }
+
+    Ok(Archive {
+        version: ARCHIVE_VERSION,
+        user_id: tenant.user_id.clone(),
+        created_at: Utc::now().to_rfc3339(),
+        model: model.to_string(),
+        dimensions: points.iter().find_map(|point| point.vector.as_ref().map(Vec::len)),
+        points,
+    })
+}
+
+pub fn archived_point(point: Json) -> ArchivedPoint {
+    let id = match &point["id"] {
+        Json::String(id) => id.clone(),
+        other => other.to_string(),
+    };
+    let vector = point
+        .get("vector")
+        .and_then(|vector| serde_json::from_value::<StoredVector>(vector.clone()).ok())
+        .and_then(dense_vector);
+    ArchivedPoint { id, payload: point["payload"].clone(), vector }
+}
+
+// the unnamed dense vector, also in hybrid mode's named layout
+fn dense_vector(vector: StoredVector) -> Option<Vec<f32>> {
+    match vector {
+        StoredVector::Dense(vector) => Some(vector),
+        StoredVector::Named(mut named) => match named.remove("")? {
+            NamedVector::Dense(vector) => Some(vector),
+            NamedVector::Sparse(_) => None,
+        },
+    }
+}
+
+pub fn validate(archive: &Archive, user_id: &str) -> Result<(), ArchiveProblem> {
+    if archive.version != ARCHIVE_VERSION {
+        return Err(ArchiveProblem::Invalid(format!("Unsupported archive version {}", archive.version)));
+    }
+    if archive.user_id != user_id {
+        return Err(ArchiveProblem::NotOwned("Archive belongs to another user".to_string()));
+    }
+
+    for point in &archive.points {
+        // every point this server saves has a UUID id
+        if Uuid::parse_str(&point.id).is_err() {
+            return Err(ArchiveProblem::Invalid(format!("Invalid point id {}", point.id)));
+        }
+        match point.payload.get("user_id") {
+            None => {}
+            Some(owner) if owner.as_str() == Some(user_id) => {}
+            Some(_) => return Err(ArchiveProblem::NotOwned(format!("Point {} belongs to another user", point.id))),
+        }
+        if !point.payload["text"].is_string() {
+            return Err(ArchiveProblem::Invalid(format!("Point {} has no text", point.id)));
+        }
+        if let Some(vector) = &point.vector {
+            if Some(vector.len()) != archive.dimensions {
+                return Err(ArchiveProblem::Invalid(format!(
+                    "Point {} has {} dimensions, the archive declares {:?}",
+                    point.id,
+                    vector.len(),
+                    archive.dimensions
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+// The archived vectors can be written back as they are only when they came
+// from the same model at the same size, otherwise the texts are re-embedded
+pub fn reusable_vectors(archive: &Archive, model: &str, dimensions: usize) -> Option<Vec<Vec<f32>>> {
+    if archive.model != model || archive.dimensions != Some(dimensions) {
+        return None;
+    }
+    archive.points.iter().map(|point| point.vector.clone()).collect()
+}
+
+pub fn texts(archive: &Archive) -> Vec<String> {
+    archive
+        .points
+        .iter()
+        .map(|point| point.payload["text"].as_str().unwrap_or_default().to_string())
+        .collect()
+}
+
+// Points in the PUT /points shape, `dense[i]` belongs to `archive.points[i]`
+pub fn restored_points(archive: &Archive, dense: Vec<Vec<f32>>, vectors: &VectorConfig) -> Vec<Json> {
+    archive
+        .points
+        .iter()
+        .zip(dense)
+        .map(|(point, dense)| {
+            let text = point.payload["text"].as_str().unwrap_or_default();
+            let mut json = serde_json::json!({
+                "id": point.id,
+                "vector": stored_vector(dense, text, vectors),
+                "payload": point.payload,
+            });
+            json["payload"]["user_id"] = Json::String(archive.user_id.clone());
+            json
+        })
+        .collect()
+}
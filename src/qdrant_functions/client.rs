@@ -0,0 +1,449 @@
+use reqwest::Method;
+use serde::{de::DeserializeOwned, Serialize};
+
+use serde_json::Value as Json;
+
+use crate::{
+    config::{QdrantConfig, QdrantTransport},
+    models::{
+        collection::CollectionInfo,
+        middleware::{FilterType, SearchRequest},
+        qdrant::{
+            CountRequest, CountResult, DeleteByFilter, QdrantResponse, ScrollRequest,
+            ScrollResult, UpdateResult, UpsertRequest,
+        },
+        similarity_result::ResultItem,
+    },
+    qdrant_functions::{error::QdrantError, grpc::GrpcTransport},
+    vector_store::WriteOptions,
+};
+
+// One pooled HTTP client for every Qdrant call, shared through AppState.
+// With QDRANT_TRANSPORT=grpc the point operations go over gRPC instead,
+// collection management stays on REST.
+pub struct QdrantClient {
+    http: reqwest::Client,
+    endpoint: String,
+    api_key: Option<String>,
+    grpc: Option<GrpcTransport>,
+    pub tweets: String,
+    pub entitlements: String,
+}
+
+impl QdrantClient {
+    pub fn new(config: &QdrantConfig) -> Result<Self, anyhow::Error> {
+        let grpc = match config.transport {
+            QdrantTransport::Rest => None,
+            QdrantTransport::Grpc => Some(GrpcTransport::new(&config.grpc_endpoint, config.api_key.as_deref())?),
+        };
+
+        Ok(QdrantClient {
+            http: reqwest::Client::new(),
+            endpoint: config.endpoint.trim_end_matches('/').to_string(),
+            api_key: config.api_key.clone(),
+            grpc,
+            tweets: config.tweets_collection.clone(),
+            entitlements: config.entitlements_collection.clone(),
+        })
+    }
+
+    pub fn transport(&self) -> &'static str {
+        if self.grpc.is_some() { "grpc" } else { "rest" }
+    }
+
+    async fn send<B, T>(&self, method: Method, path: &str, body: Option<&B>) -> Result<T, QdrantError>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let mut request = self.http.request(method, format!("{}{}", self.endpoint, path));
+        if let Some(api_key) = &self.api_key {
+            request = request.header("api-key", api_key);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let bytes = response.bytes().await?;
+
+        if !status.is_success() {
+            return Err(QdrantError::Status {
+                status: status.as_u16(),
+                body: String::from_utf8_lossy(&bytes).to_string(),
+            });
+        }
+
+        let parsed: QdrantResponse<T> = serde_json::from_slice(&bytes)?;
+        Ok(parsed.result)
+    }
+
+    pub async fn upsert(
+        &self,
+        collection: &str,
+        shard_key: Option<&str>,
+        points: &[Json],
+        options: WriteOptions,
+    ) -> Result<UpdateResult, QdrantError> {
+        if let Some(grpc) = &self.grpc {
+            return grpc.upsert(collection, shard_key, points, options.wait, options.ordering).await;
+        }
+        let request = UpsertRequest { points, shard_key };
+        self.send(
+            Method::PUT,
+            &format!(
+                "/collections/{}/points?wait={}&ordering={}",
+                collection,
+                options.wait,
+                options.ordering.as_str()
+            ),
+            Some(&request),
+        )
+        .await
+    }
+
+    pub async fn delete_by_filter(
+        &self,
+        collection: &str,
+        shard_key: Option<&str>,
+        filter: &FilterType,
+    ) -> Result<UpdateResult, QdrantError> {
+        if let Some(grpc) = &self.grpc {
+            return grpc.delete_by_filter(collection, shard_key, filter, true).await;
+        }
+        let request = DeleteByFilter { filter, shard_key };
+        self.send(
+            Method::POST,
+            &format!("/collections/{}/points/delete?wait=true", collection),
+            Some(&request),
+        )
+        .await
+    }
+
+    pub async fn scroll(&self, collection: &str, request: &ScrollRequest) -> Result<ScrollResult<Json>, QdrantError> {
+        if let Some(grpc) = &self.grpc {
+            return grpc.scroll(collection, request).await;
+        }
+        self.send(
+            Method::POST,
+            &format!("/collections/{}/points/scroll", collection),
+            Some(request),
+        )
+        .await
+    }
+
+    pub async fn count(&self, collection: &str, shard_key: Option<&str>, filter: &FilterType) -> Result<u64, QdrantError> {
+        if let Some(grpc) = &self.grpc {
+            return grpc.count(collection, shard_key, filter, true).await;
+        }
+        let request = CountRequest { filter, exact: true, shard_key };
+        let result: CountResult = self
+            .send(
+                Method::POST,
+                &format!("/collections/{}/points/count", collection),
+                Some(&request),
+            )
+            .await?;
+        Ok(result.count)
+    }
+
+    pub async fn query_batch(&self, collection: &str, request: &SearchRequest) -> Result<Vec<ResultItem>, QdrantError> {
+        if let Some(grpc) = &self.grpc {
+            return grpc.query_batch(collection, request).await;
+        }
+        self.send(
+            Method::POST,
+            &format!("/collections/{}/points/query/batch", collection),
+            Some(request),
+        )
+        .await
+    }
+
+    // None when the collection does not exist
+    pub async fn collection_info(&self, collection: &str) -> Result<Option<CollectionInfo>, QdrantError> {
+        let result: Result<CollectionInfo, QdrantError> = self
+            .send::<(), _>(Method::GET, &format!("/collections/{}", collection), None)
+            .await;
+        match result {
+            Ok(info) => Ok(Some(info)),
+            Err(QdrantError::Status { status: 404, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn create_collection(&self, collection: &str, body: &serde_json::Value) -> Result<bool, QdrantError> {
+        self.send(Method::PUT, &format!("/collections/{}", collection), Some(body))
+            .await
+    }
+
+    pub async fn create_payload_index(&self, collection: &str, field: &str, schema: &str) -> Result<UpdateResult, QdrantError> {
+        self.send(
+            Method::PUT,
+            &format!("/collections/{}/index?wait=true", collection),
+            Some(&serde_json::json!({
+                "field_name": field,
+                "field_schema": schema
+            })),
+        )
+        .await
+    }
+
+    // Ok when the key already exists
+    pub async fn create_shard_key(&self, collection: &str, shard_key: &str) -> Result<(), QdrantError> {
+        let result: Result<bool, QdrantError> = self
+            .send(
+                Method::PUT,
+                &format!("/collections/{}/shards", collection),
+                Some(&serde_json::json!({ "shard_key": shard_key })),
+            )
+            .await;
+        match result {
+            Err(QdrantError::Status { status: 400, body }) if body.contains("already exists") => Ok(()),
+            other => other.map(|_| ()),
+        }
+    }
+
+    pub async fn update_collection(&self, collection: &str, body: &serde_json::Value) -> Result<bool, QdrantError> {
+        self.send(Method::PATCH, &format!("/collections/{}", collection), Some(body))
+            .await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        config::WriteOrdering,
+        models::qdrant::PointId,
+        qdrant_functions::search::user_filter,
+    };
+
+    // Shared by the REST and gRPC transport tests, both must send and read the same thing
+
+    pub(crate) const COLLECTION: &str = "tweets";
+    pub(crate) const API_KEY: &str = "secret";
+    pub(crate) const FIRST_ID: &str = "5c56c793-69f3-4fbf-87e6-c4bf54c28c26";
+    pub(crate) const SECOND_ID: &str = "0b7e0b8c-3c1a-4a3e-9d55-6f0c1f6a3b21";
+
+    // A dense point with a nested payload and a hybrid point with a numeric id
+    pub(crate) fn points() -> Vec<Json> {
+        vec![
+            json!({
+                "id": FIRST_ID,
+                "vector": [0.5, -0.25, 1.0],
+                "payload": {
+                    "user_id": "user-1",
+                    "text": "I love Rust",
+                    "tags": ["rust", "async"],
+                    "meta": { "likes": 3, "ratio": 1.5, "pinned": true, "note": null }
+                }
+            }),
+            json!({
+                "id": 42,
+                "vector": {
+                    "": [0.25, 0.5, 0.75],
+                    "bm25": { "indices": [3, 17], "values": [1.5, 0.5] }
+                },
+                "payload": { "user_id": "user-1", "text": "#rust tips" }
+            }),
+        ]
+    }
+
+    pub(crate) fn delete_filter() -> FilterType {
+        let mut filter = user_filter("user-1");
+        filter.must.push(crate::models::middleware::Condition::HasId { has_id: vec![FIRST_ID.to_string()] });
+        filter
+    }
+
+    pub(crate) fn scroll_request() -> ScrollRequest {
+        ScrollRequest {
+            filter: user_filter("user-1"),
+            limit: 2,
+            with_payload: true,
+            with_vector: true,
+            offset: Some(PointId::Uuid(SECOND_ID.to_string())),
+            shard_key: None,
+        }
+    }
+
+    // Dense, hybrid (fused prefetches) and recommend searches of one batch
+    pub(crate) fn searches() -> Json {
+        json!({
+            "searches": [
+                {
+                    "query": [0.5, -0.25, 1.0],
+                    "filter": { "must": [{ "key": "user_id", "match": { "value": "user-1" } }] },
+                    "with_payload": true,
+                    "limit": 3,
+                    "score_threshold": 0.5,
+                    "shard_key": "user-1"
+                },
+                {
+                    "prefetch": [
+                        { "query": [0.5, -0.25, 1.0], "filter": { "must": [] }, "limit": 20, "score_threshold": 0.5 },
+                        { "query": { "indices": [3], "values": [1.0] }, "using": "bm25", "filter": { "must": [] }, "limit": 20 }
+                    ],
+                    "query": { "fusion": "rrf" },
+                    "filter": { "must": [{ "has_id": [FIRST_ID] }] },
+                    "with_payload": true,
+                    "limit": 2
+                },
+                {
+                    "query": { "recommend": { "positive": [[1.0, 0.0, 0.0]], "negative": [[0.0, 1.0, 0.0]], "strategy": "best_score" } },
+                    "filter": { "must": [{ "key": "posted_at", "range": { "gte": "2024-01-01T00:00:00Z" } }] },
+                    "with_payload": true,
+                    "limit": 1,
+                    "params": { "quantization": { "rescore": true, "oversampling": 2.0 } }
+                }
+            ]
+        })
+    }
+
+    pub(crate) fn query_results() -> Json {
+        json!([
+            [{ "id": FIRST_ID, "version": 3, "score": 0.75, "payload": { "user_id": "user-1", "text": "I love Rust" } }],
+            [],
+            [{ "id": SECOND_ID, "version": 1, "score": -0.25, "payload": { "user_id": "user-1", "text": "#rust tips" } }]
+        ])
+    }
+
+    pub(crate) fn config(transport: QdrantTransport, endpoint: &str, grpc_endpoint: &str) -> QdrantConfig {
+        QdrantConfig {
+            endpoint: endpoint.to_string(),
+            api_key: Some(API_KEY.to_string()),
+            tweets_collection: COLLECTION.to_string(),
+            entitlements_collection: "entitlements".to_string(),
+            distance: "Cosine".to_string(),
+            transport,
+            grpc_endpoint: grpc_endpoint.to_string(),
+        }
+    }
+
+    // Runs every point operation once and checks what came back. The
+    // requests are checked by each transport's test.
+    pub(crate) async fn run_point_operations(client: &QdrantClient) {
+        let options = WriteOptions { wait: false, ordering: WriteOrdering::Medium };
+        let upserted = client.upsert(COLLECTION, Some("user-1"), &points(), options).await.unwrap();
+        assert_eq!(upserted.operation_id, Some(7));
+        assert_eq!(upserted.status, "acknowledged");
+
+        let deleted = client.delete_by_filter(COLLECTION, None, &delete_filter()).await.unwrap();
+        assert_eq!(deleted.operation_id, Some(8));
+        assert_eq!(deleted.status, "completed");
+
+        let page: ScrollResult<Json> = client.scroll(COLLECTION, &scroll_request()).await.unwrap();
+        assert_eq!(page.points, points());
+        assert_eq!(page.next_page_offset, Some(PointId::Num(7)));
+
+        assert_eq!(client.count(COLLECTION, Some("user-1"), &user_filter("user-1")).await.unwrap(), 42);
+
+        let request: SearchRequest = serde_json::from_value(searches()).unwrap();
+        let results = client.query_batch(COLLECTION, &request).await.unwrap();
+        let points: Vec<_> = results.iter().map(|item| &item.points).collect();
+        assert_eq!(serde_json::to_value(points).unwrap(), query_results());
+    }
+
+    // ---- REST stub ----
+
+    #[derive(Default)]
+    struct Recorded {
+        // method, path with query, api-key header, JSON body
+        requests: Vec<(String, String, Option<String>, Json)>,
+    }
+
+    async fn answer(request: HttpRequest, body: web::Bytes, recorded: web::Data<Mutex<Recorded>>) -> HttpResponse {
+        let json: Json = serde_json::from_slice(&body).unwrap_or(Json::Null);
+        let api_key = request.headers().get("api-key").and_then(|v| v.to_str().ok()).map(str::to_string);
+        let path = request.uri().path().to_string();
+        recorded.lock().unwrap().requests.push((
+            request.method().to_string(),
+            request.uri().to_string(),
+            api_key,
+            json,
+        ));
+
+        let result = match path.as_str() {
+            "/collections/tweets/points" => json!({ "operation_id": 7, "status": "acknowledged" }),
+            "/collections/tweets/points/delete" => json!({ "operation_id": 8, "status": "completed" }),
+            "/collections/tweets/points/scroll" => json!({ "points": points(), "next_page_offset": 7 }),
+            "/collections/tweets/points/count" => json!({ "count": 42 }),
+            "/collections/tweets/points/query/batch" => query_results()
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|points| json!({ "points": points }))
+                .collect(),
+            _ => return HttpResponse::NotFound().finish(),
+        };
+        HttpResponse::Ok().json(json!({ "result": result, "status": "ok", "time": 0.001 }))
+    }
+
+    async fn start_rest_stub() -> (String, Arc<Mutex<Recorded>>) {
+        let recorded = web::Data::new(Mutex::new(Recorded::default()));
+        let shared = recorded.clone().into_inner();
+        let server = HttpServer::new(move || App::new().app_data(recorded.clone()).default_service(web::to(answer)))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        (format!("http://{}", address), shared)
+    }
+
+    #[actix_web::test]
+    async fn rest_transport_sends_qdrant_rest_requests() {
+        let (endpoint, recorded) = start_rest_stub().await;
+        let client = QdrantClient::new(&config(QdrantTransport::Rest, &endpoint, "http://127.0.0.1:1")).unwrap();
+        assert_eq!(client.transport(), "rest");
+
+        run_point_operations(&client).await;
+
+        let requests = &recorded.lock().unwrap().requests;
+        let expected = [
+            (
+                "PUT",
+                "/collections/tweets/points?wait=false&ordering=medium",
+                json!({ "points": points(), "shard_key": "user-1" }),
+            ),
+            (
+                "POST",
+                "/collections/tweets/points/delete?wait=true",
+                json!({ "filter": serde_json::to_value(delete_filter()).unwrap() }),
+            ),
+            (
+                "POST",
+                "/collections/tweets/points/scroll",
+                json!({
+                    "filter": { "must": [{ "key": "user_id", "match": { "value": "user-1" } }] },
+                    "limit": 2,
+                    "with_payload": true,
+                    "with_vector": true,
+                    "offset": SECOND_ID
+                }),
+            ),
+            (
+                "POST",
+                "/collections/tweets/points/count",
+                json!({
+                    "filter": { "must": [{ "key": "user_id", "match": { "value": "user-1" } }] },
+                    "exact": true,
+                    "shard_key": "user-1"
+                }),
+            ),
+            ("POST", "/collections/tweets/points/query/batch", searches()),
+        ];
+        assert_eq!(requests.len(), expected.len());
+        for ((method, path, api_key, body), (expected_method, expected_path, expected_body)) in requests.iter().zip(expected) {
+            assert_eq!(method, expected_method);
+            assert_eq!(path, expected_path);
+            assert_eq!(api_key.as_deref(), Some(API_KEY));
+            assert_eq!(body, &expected_body, "body of {}", path);
+        }
+    }
+}
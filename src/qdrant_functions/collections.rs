@@ -0,0 +1,191 @@
+use serde_json::json;
+
+use crate::{
+    config::{Config, Quantization, TenancyMode, VectorConfig},
+    models::collection::{CollectionParams, VectorsConfig},
+    qdrant_functions::{client::QdrantClient, error::QdrantError},
+};
+
+// Size of the unnamed dense vector of `collection`
+pub fn vector_size(collection: &str, params: &CollectionParams) -> Result<usize, anyhow::Error> {
+    match &params.vectors {
+        VectorsConfig::Single(vector) => Ok(vector.size),
+        VectorsConfig::Named(named) => match named.get("") {
+            Some(vector) => Ok(vector.size),
+            None => anyhow::bail!("Collection {} only has named vectors", collection),
+        },
+    }
+}
+
+pub fn has_sparse_vector(params: &CollectionParams, name: &str) -> bool {
+    params
+        .sparse_vectors
+        .as_ref()
+        .is_some_and(|sparse| sparse.contains_key(name))
+}
+
+// Qdrant `quantization_config` for the configured mode, None when disabled
+pub fn quantization_config(vectors: &VectorConfig) -> Option<serde_json::Value> {
+    match vectors.quantization {
+        Quantization::None => None,
+        Quantization::Scalar => Some(json!({
+            "scalar": {
+                "type": "int8",
+                "quantile": 0.99,
+                "always_ram": vectors.always_ram
+            }
+        })),
+        Quantization::Binary => Some(json!({
+            "binary": {
+                "always_ram": vectors.always_ram
+            }
+        })),
+    }
+}
+
+// Applies the configured quantization to an existing collection, Qdrant
+// builds the quantized copy in the background and keeps the originals for rescoring
+pub async fn update_quantization(client: &QdrantClient, collection: &str, vectors: &VectorConfig) -> Result<(), QdrantError> {
+    let Some(quantization) = quantization_config(vectors) else {
+        return Ok(());
+    };
+
+    client
+        .update_collection(collection, &json!({ "quantization_config": quantization }))
+        .await?;
+    Ok(())
+}
+
+// Payload fields queries filter on
+pub const TWEET_INDEXES: &[(&str, &str)] = &[
+    ("user_id", "keyword"),
+    ("username", "keyword"),
+    ("language", "keyword"),
+    ("saved_at", "datetime"),
+    ("posted_at", "datetime"),
+];
+pub const ENTITLEMENT_INDEXES: &[(&str, &str)] = &[("user_id", "keyword")];
+
+// Body of PUT /collections for a tweets collection
+pub fn tweets_collection_body(config: &Config, dimensions: usize) -> serde_json::Value {
+    let vectors = &config.vectors;
+    let mut body = json!({
+        "vectors": {
+            "size": dimensions,
+            "distance": config.qdrant.distance
+        }
+    });
+    if vectors.hybrid {
+        body["sparse_vectors"] = json!({
+            vectors.sparse_vector_name.clone(): { "modifier": "idf" }
+        });
+    }
+    if let Some(quantization) = quantization_config(vectors) {
+        body["quantization_config"] = quantization;
+    }
+    if config.tenancy == TenancyMode::ShardKey {
+        body["sharding_method"] = json!("custom");
+    }
+    body
+}
+
+// Per team tweets collection of the `collection` tenancy mode
+pub async fn create_tenant_collection(client: &QdrantClient, collection: &str, body: &serde_json::Value) -> Result<(), anyhow::Error> {
+    if client.collection_info(collection).await?.is_none() {
+        client.create_collection(collection, body).await?;
+        println!("Created tenant collection {}", collection);
+    }
+    ensure_indexes(client, collection, TWEET_INDEXES).await
+}
+
+// Creates missing collections and payload indexes, and checks existing
+// collections against the configuration. Hard mismatches (vector size,
+// missing sparse vector) stop the server, everything else is reported.
+pub async fn bootstrap(client: &QdrantClient, config: &Config, dimensions: usize) -> Result<(), anyhow::Error> {
+    let vectors = &config.vectors;
+    let tweets = &client.tweets;
+
+    match client.collection_info(tweets).await? {
+        None => {
+            client.create_collection(tweets, &tweets_collection_body(config, dimensions)).await?;
+            println!("Created collection {} ({} dims, {})", tweets, dimensions, config.qdrant.distance);
+        }
+        Some(info) => {
+            let params = &info.config.params;
+            let size = vector_size(tweets, params)?;
+            if size != dimensions {
+                anyhow::bail!(
+                    "Embedding model returns {} dimensions but collection {} expects {}",
+                    dimensions,
+                    tweets,
+                    size
+                );
+            }
+            println!("Embedding dimensions match {}: {}", tweets, dimensions);
+
+            if let Some(distance) = vector_distance(params) {
+                if !distance.eq_ignore_ascii_case(&config.qdrant.distance) {
+                    println!(
+                        "Schema drift: {} uses {} distance, QDRANT_DISTANCE is {}",
+                        tweets, distance, config.qdrant.distance
+                    );
+                }
+            }
+            if vectors.hybrid && !has_sparse_vector(params, &vectors.sparse_vector_name) {
+                anyhow::bail!(
+                    "HYBRID_SEARCH is on but {} has no sparse vector named {}",
+                    tweets,
+                    vectors.sparse_vector_name
+                );
+            }
+            if config.tenancy == TenancyMode::ShardKey && params.sharding_method.as_deref() != Some("custom") {
+                anyhow::bail!("TENANCY=shard_key needs {} to be created with custom sharding", tweets);
+            }
+            update_quantization(client, tweets, vectors).await?;
+        }
+    }
+    ensure_indexes(client, tweets, TWEET_INDEXES).await?;
+
+    // entitlements only carry a payload, the 1-d vector is a placeholder
+    let entitlements = &client.entitlements;
+    if client.collection_info(entitlements).await?.is_none() {
+        client
+            .create_collection(entitlements, &json!({ "vectors": { "size": 1, "distance": "Dot" } }))
+            .await?;
+        println!("Created collection {}", entitlements);
+    }
+    ensure_indexes(client, entitlements, ENTITLEMENT_INDEXES).await?;
+
+    Ok(())
+}
+
+// Creates missing payload indexes and reports ones indexed with another type
+async fn ensure_indexes(client: &QdrantClient, collection: &str, indexes: &[(&str, &str)]) -> Result<(), anyhow::Error> {
+    let schema = client
+        .collection_info(collection)
+        .await?
+        .map(|info| info.payload_schema)
+        .unwrap_or_default();
+
+    for (field, data_type) in indexes {
+        match schema.get(*field) {
+            Some(existing) if existing.data_type == *data_type => {}
+            Some(existing) => println!(
+                "Schema drift: {}.{} is indexed as {}, expected {}",
+                collection, field, existing.data_type, data_type
+            ),
+            None => {
+                client.create_payload_index(collection, field, data_type).await?;
+                println!("Created {} payload index on {}.{}", data_type, collection, field);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn vector_distance(params: &CollectionParams) -> Option<&str> {
+    match &params.vectors {
+        VectorsConfig::Single(vector) => Some(&vector.distance),
+        VectorsConfig::Named(named) => named.get("").map(|v| v.distance.as_str()),
+    }
+}
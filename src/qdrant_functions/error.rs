@@ -0,0 +1,76 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum QdrantError {
+    // Connection problems, timeouts
+    Request(reqwest::Error),
+    // Qdrant answered with a non 2xx status
+    Status { status: u16, body: String },
+    // The body did not match the expected response type
+    Decode(serde_json::Error),
+    // gRPC transport: call failed or returned a non OK status
+    Grpc(Box<tonic::Status>),
+    // The request could not be translated for the gRPC transport
+    Encode(String),
+    // Refused without calling Qdrant, the circuit breaker is open
+    Unavailable,
+}
+
+impl fmt::Display for QdrantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QdrantError::Request(e) => write!(f, "Qdrant request failed: {}", e),
+            QdrantError::Status { status, body } => write!(f, "Qdrant returned {}: {}", status, body),
+            QdrantError::Decode(e) => write!(f, "Failed to parse Qdrant response: {}", e),
+            QdrantError::Grpc(status) => write!(f, "Qdrant gRPC call failed: {:?} {}", status.code(), status.message()),
+            QdrantError::Encode(e) => write!(f, "Failed to encode Qdrant request: {}", e),
+            QdrantError::Unavailable => write!(f, "Vector store unavailable, circuit open"),
+        }
+    }
+}
+
+impl std::error::Error for QdrantError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QdrantError::Request(e) => Some(e),
+            QdrantError::Decode(e) => Some(e),
+            QdrantError::Grpc(status) => Some(status.as_ref()),
+            QdrantError::Status { .. } | QdrantError::Encode(_) | QdrantError::Unavailable => None,
+        }
+    }
+}
+
+impl QdrantError {
+    // The store could not be reached or is failing on its side, as opposed
+    // to rejecting this particular request
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            QdrantError::Request(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            QdrantError::Status { status, .. } => *status >= 500,
+            QdrantError::Grpc(status) => matches!(
+                status.code(),
+                tonic::Code::Unavailable | tonic::Code::DeadlineExceeded | tonic::Code::Internal
+            ),
+            QdrantError::Unavailable => true,
+            QdrantError::Decode(_) | QdrantError::Encode(_) => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for QdrantError {
+    fn from(e: reqwest::Error) -> Self {
+        QdrantError::Request(e)
+    }
+}
+
+impl From<serde_json::Error> for QdrantError {
+    fn from(e: serde_json::Error) -> Self {
+        QdrantError::Decode(e)
+    }
+}
+
+impl From<tonic::Status> for QdrantError {
+    fn from(status: tonic::Status) -> Self {
+        QdrantError::Grpc(Box::new(status))
+    }
+}
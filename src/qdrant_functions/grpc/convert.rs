@@ -0,0 +1,499 @@
+use std::{collections::HashMap, fmt};
+
+use serde::de::Error as _;
+use serde_json::{json, Map, Value as Json};
+
+use crate::{
+    config::WriteOrdering,
+    models::{
+        middleware::{self, DateRange, FilterType, PointSearchVectors, QueryParams},
+        qdrant,
+        similarity_result::{Payload, Point},
+    },
+    qdrant_functions::{
+        error::QdrantError,
+        grpc::proto::{
+            condition::ConditionOneOf, point_id::PointIdOptions, points_selector::PointsSelectorOneOf,
+            query::Variant as QueryVariant, r#match::MatchValue, shard_key::Key, value::Kind,
+            vector_input::Variant as InputVariant, vectors::VectorsOptions, vectors_output,
+            with_payload_selector, with_vectors_selector, Condition, DatetimeRange, DenseVector,
+            FieldCondition, Filter, Fusion, HasIdCondition, ListValue, Match, NamedVectors, NullValue,
+            PointId, PointStruct, PointsSelector, PrefetchQuery, QuantizationSearchParams, Query,
+            QueryPoints, RecommendInput, RecommendStrategy, RetrievedPoint, ScoredPoint, SearchParams,
+            ShardKey, ShardKeySelector, SparseIndices, SparseVector, Struct, Timestamp, Value, Vector,
+            VectorInput, Vectors, VectorsOutput, WithPayloadSelector, WithVectorsSelector,
+            WriteOrdering as GrpcWriteOrdering, WriteOrderingType,
+        },
+    },
+};
+
+// The REST models are the source of truth, they are translated here field by
+// field so both transports send the same thing. Points stay JSON, like
+// everywhere else behind the VectorStore trait.
+
+fn unsupported(what: &str, value: impl fmt::Display) -> QdrantError {
+    QdrantError::Encode(format!("{} has no gRPC equivalent: {}", what, value))
+}
+
+// ---- payload values ----
+
+pub fn to_value(json: &Json) -> Value {
+    let kind = match json {
+        Json::Null => Kind::NullValue(NullValue::NullValue as i32),
+        Json::Bool(b) => Kind::BoolValue(*b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Kind::IntegerValue(i),
+            None => Kind::DoubleValue(n.as_f64().unwrap_or_default()),
+        },
+        Json::String(s) => Kind::StringValue(s.clone()),
+        Json::Array(items) => Kind::ListValue(ListValue { values: items.iter().map(to_value).collect() }),
+        Json::Object(fields) => Kind::StructValue(Struct { fields: to_payload(fields) }),
+    };
+    Value { kind: Some(kind) }
+}
+
+pub fn to_payload(fields: &Map<String, Json>) -> HashMap<String, Value> {
+    fields.iter().map(|(k, v)| (k.clone(), to_value(v))).collect()
+}
+
+pub fn from_value(value: Value) -> Json {
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => Json::Null,
+        Some(Kind::BoolValue(b)) => Json::Bool(b),
+        Some(Kind::IntegerValue(i)) => json!(i),
+        Some(Kind::DoubleValue(d)) => json!(d),
+        Some(Kind::StringValue(s)) => Json::String(s),
+        Some(Kind::ListValue(list)) => Json::Array(list.values.into_iter().map(from_value).collect()),
+        Some(Kind::StructValue(s)) => from_payload(s.fields),
+    }
+}
+
+pub fn from_payload(payload: HashMap<String, Value>) -> Json {
+    Json::Object(payload.into_iter().map(|(k, v)| (k, from_value(v))).collect())
+}
+
+// ---- ids ----
+
+pub fn to_point_id(json: &Json) -> Result<PointId, QdrantError> {
+    let options = match json {
+        Json::String(uuid) => PointIdOptions::Uuid(uuid.clone()),
+        Json::Number(n) => PointIdOptions::Num(n.as_u64().ok_or_else(|| unsupported("point id", json))?),
+        _ => return Err(unsupported("point id", json)),
+    };
+    Ok(PointId { point_id_options: Some(options) })
+}
+
+pub fn from_point_id(id: Option<PointId>) -> Json {
+    match id.and_then(|id| id.point_id_options) {
+        Some(PointIdOptions::Num(n)) => json!(n),
+        Some(PointIdOptions::Uuid(uuid)) => Json::String(uuid),
+        None => Json::Null,
+    }
+}
+
+// Ids sent as strings are UUIDs, as with REST
+fn uuid(id: &str) -> PointId {
+    PointId { point_id_options: Some(PointIdOptions::Uuid(id.to_string())) }
+}
+
+// Scroll offsets
+pub fn to_offset(id: &qdrant::PointId) -> PointId {
+    let options = match id {
+        qdrant::PointId::Num(n) => PointIdOptions::Num(*n),
+        qdrant::PointId::Uuid(uuid) => PointIdOptions::Uuid(uuid.clone()),
+    };
+    PointId { point_id_options: Some(options) }
+}
+
+pub fn from_offset(id: PointId) -> Option<qdrant::PointId> {
+    match id.point_id_options? {
+        PointIdOptions::Num(n) => Some(qdrant::PointId::Num(n)),
+        PointIdOptions::Uuid(uuid) => Some(qdrant::PointId::Uuid(uuid)),
+    }
+}
+
+pub fn to_filter_selector(filter: &FilterType) -> Result<PointsSelector, QdrantError> {
+    Ok(PointsSelector { points_selector_one_of: Some(PointsSelectorOneOf::Filter(to_filter(filter)?)) })
+}
+
+// ---- filters ----
+
+pub fn to_filter(filter: &FilterType) -> Result<Filter, QdrantError> {
+    Ok(Filter {
+        should: Vec::new(),
+        must: filter.must.iter().map(to_condition).collect::<Result<_, _>>()?,
+        must_not: Vec::new(),
+    })
+}
+
+fn to_condition(condition: &middleware::Condition) -> Result<Condition, QdrantError> {
+    let condition = match condition {
+        middleware::Condition::Field(must) => ConditionOneOf::Field(FieldCondition {
+            key: must.key.clone(),
+            r#match: Some(Match { match_value: Some(MatchValue::Keyword(must.r#match.value.clone())) }),
+            ..Default::default()
+        }),
+        middleware::Condition::HasId { has_id } => {
+            ConditionOneOf::HasId(HasIdCondition { has_id: has_id.iter().map(|id| uuid(id)).collect() })
+        }
+        middleware::Condition::Range { key, range } => ConditionOneOf::Field(FieldCondition {
+            key: key.clone(),
+            datetime_range: Some(to_datetime_range(range)?),
+            ..Default::default()
+        }),
+    };
+    Ok(Condition { condition_one_of: Some(condition) })
+}
+
+fn to_datetime_range(range: &DateRange) -> Result<DatetimeRange, QdrantError> {
+    let bound = |value: &Option<String>| -> Result<Option<Timestamp>, QdrantError> {
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let parsed = chrono::DateTime::parse_from_rfc3339(value).map_err(|_| unsupported("datetime range bound", value))?;
+        Ok(Some(Timestamp { seconds: parsed.timestamp(), nanos: parsed.timestamp_subsec_nanos() as i32 }))
+    };
+    Ok(DatetimeRange { lt: None, gt: None, gte: bound(&range.gte)?, lte: bound(&range.lte)? })
+}
+
+// ---- vectors ----
+
+fn floats(json: &Json) -> Result<Vec<f32>, QdrantError> {
+    json.as_array()
+        .ok_or_else(|| unsupported("vector", json))?
+        .iter()
+        .map(|v| v.as_f64().map(|f| f as f32).ok_or_else(|| unsupported("vector element", v)))
+        .collect()
+}
+
+fn indices(json: &Json) -> Result<Vec<u32>, QdrantError> {
+    json.as_array()
+        .ok_or_else(|| unsupported("sparse indices", json))?
+        .iter()
+        .map(|v| v.as_u64().map(|i| i as u32).ok_or_else(|| unsupported("sparse index", v)))
+        .collect()
+}
+
+// Dense `[..]` or sparse `{"indices": [..], "values": [..]}`
+fn to_vector(json: &Json) -> Result<Vector, QdrantError> {
+    match (json.get("indices"), json.get("values")) {
+        (Some(i), Some(v)) => Ok(Vector { data: floats(v)?, indices: Some(SparseIndices { data: indices(i)? }) }),
+        _ => Ok(Vector { data: floats(json)?, indices: None }),
+    }
+}
+
+pub fn to_vectors(json: &Json) -> Result<Vectors, QdrantError> {
+    let options = match json {
+        Json::Object(named) => {
+            let vectors = named
+                .iter()
+                .map(|(name, v)| Ok((name.clone(), to_vector(v)?)))
+                .collect::<Result<_, QdrantError>>()?;
+            VectorsOptions::Vectors(NamedVectors { vectors })
+        }
+        _ => VectorsOptions::Vector(to_vector(json)?),
+    };
+    Ok(Vectors { vectors_options: Some(options) })
+}
+
+fn from_vector(vector: Vector) -> Json {
+    match vector.indices {
+        Some(indices) => json!({ "indices": indices.data, "values": vector.data }),
+        None => json!(vector.data),
+    }
+}
+
+fn from_vectors(vectors: Option<VectorsOutput>) -> Option<Json> {
+    match vectors?.vectors_options? {
+        vectors_output::VectorsOptions::Vector(vector) => Some(from_vector(vector)),
+        vectors_output::VectorsOptions::Vectors(named) => Some(Json::Object(
+            named.vectors.into_iter().map(|(name, v)| (name, from_vector(v))).collect(),
+        )),
+    }
+}
+
+// ---- points ----
+
+// {"id", "vector", "payload"} as sent to PUT /points
+pub fn to_point_struct(json: &Json) -> Result<PointStruct, QdrantError> {
+    Ok(PointStruct {
+        id: Some(to_point_id(&json["id"])?),
+        payload: json.get("payload").and_then(Json::as_object).map(to_payload).unwrap_or_default(),
+        vectors: json.get("vector").map(to_vectors).transpose()?,
+    })
+}
+
+pub fn from_retrieved_point(point: RetrievedPoint) -> Json {
+    let mut json = json!({
+        "id": from_point_id(point.id),
+        "payload": from_payload(point.payload),
+    });
+    if let Some(vector) = from_vectors(point.vectors) {
+        json["vector"] = vector;
+    }
+    json
+}
+
+// A search hit, read like the REST result: `user_id` and `text` must be there
+pub fn from_scored_point(point: ScoredPoint) -> Result<Point, QdrantError> {
+    let id = match point.id.and_then(|id| id.point_id_options) {
+        Some(PointIdOptions::Uuid(uuid)) => uuid,
+        Some(PointIdOptions::Num(n)) => n.to_string(),
+        None => return Err(serde_json::Error::missing_field("id").into()),
+    };
+    let version = u32::try_from(point.version).map_err(|_| serde_json::Error::custom("version out of range"))?;
+    let mut payload = point.payload;
+    let mut string = |key: &str| match payload.remove(key).and_then(|value| value.kind) {
+        Some(Kind::StringValue(s)) => Some(s),
+        _ => None,
+    };
+    let payload = Payload {
+        user_id: string("user_id").ok_or_else(|| serde_json::Error::missing_field("user_id"))?,
+        text: string("text").ok_or_else(|| serde_json::Error::missing_field("text"))?,
+        username: string("username"),
+        tweet_id: string("tweet_id"),
+        saved_at: string("saved_at"),
+        posted_at: string("posted_at"),
+        language: string("language"),
+        url: string("url"),
+    };
+    Ok(Point { id, version, score: point.score, payload })
+}
+
+pub fn to_write_ordering(ordering: WriteOrdering) -> GrpcWriteOrdering {
+    let r#type = match ordering {
+        WriteOrdering::Weak => WriteOrderingType::Weak,
+        WriteOrdering::Medium => WriteOrderingType::Medium,
+        WriteOrdering::Strong => WriteOrderingType::Strong,
+    };
+    GrpcWriteOrdering { r#type: r#type as i32 }
+}
+
+// Shard keys are user ids, so always keywords
+pub fn to_shard_key_selector(shard_key: Option<&str>) -> Option<ShardKeySelector> {
+    shard_key.map(|key| ShardKeySelector { shard_keys: vec![ShardKey { key: Some(Key::Keyword(key.to_string())) }] })
+}
+
+pub fn with_payload(enable: bool) -> Option<WithPayloadSelector> {
+    Some(WithPayloadSelector { selector_options: Some(with_payload_selector::SelectorOptions::Enable(enable)) })
+}
+
+pub fn with_vectors(enable: bool) -> Option<WithVectorsSelector> {
+    Some(WithVectorsSelector { selector_options: Some(with_vectors_selector::SelectorOptions::Enable(enable)) })
+}
+
+// ---- queries ----
+
+fn dense(vector: &[f32]) -> VectorInput {
+    VectorInput { variant: Some(InputVariant::Dense(DenseVector { data: vector.to_vec() })) }
+}
+
+fn to_recommend(recommend: &middleware::RecommendInput) -> RecommendInput {
+    let strategy = match recommend.strategy {
+        middleware::RecommendStrategy::AverageVector => RecommendStrategy::AverageVector,
+        middleware::RecommendStrategy::BestScore => RecommendStrategy::BestScore,
+    };
+    RecommendInput {
+        positive: recommend.positive.iter().map(|v| dense(v)).collect(),
+        negative: recommend.negative.iter().map(|v| dense(v)).collect(),
+        strategy: Some(strategy as i32),
+    }
+}
+
+fn to_query(query: &middleware::Query) -> Result<Query, QdrantError> {
+    let variant = match query {
+        middleware::Query::Dense(vector) => QueryVariant::Nearest(dense(vector)),
+        middleware::Query::Sparse(vector) => QueryVariant::Nearest(VectorInput {
+            variant: Some(InputVariant::Sparse(SparseVector {
+                values: vector.values.clone(),
+                indices: vector.indices.clone(),
+            })),
+        }),
+        middleware::Query::Fusion { fusion } => {
+            let fusion = match fusion.as_str() {
+                "rrf" => Fusion::Rrf,
+                "dbsf" => Fusion::Dbsf,
+                _ => return Err(unsupported("fusion", fusion)),
+            };
+            QueryVariant::Fusion(fusion as i32)
+        }
+        middleware::Query::Recommend { recommend } => QueryVariant::Recommend(to_recommend(recommend)),
+    };
+    Ok(Query { variant: Some(variant) })
+}
+
+fn to_search_params(params: &QueryParams) -> SearchParams {
+    SearchParams {
+        hnsw_ef: None,
+        exact: None,
+        quantization: Some(QuantizationSearchParams {
+            ignore: None,
+            rescore: Some(params.quantization.rescore),
+            oversampling: params.quantization.oversampling.map(f64::from),
+        }),
+    }
+}
+
+fn to_prefetch(prefetch: &middleware::Prefetch) -> Result<PrefetchQuery, QdrantError> {
+    Ok(PrefetchQuery {
+        prefetch: Vec::new(),
+        query: Some(to_query(&prefetch.query)?),
+        using: prefetch.using.clone(),
+        filter: Some(to_filter(&prefetch.filter)?),
+        params: prefetch.params.as_ref().map(to_search_params),
+        score_threshold: prefetch.score_threshold,
+        limit: Some(prefetch.limit as u64),
+    })
+}
+
+// One entry of a query batch
+pub fn to_query_points(collection: &str, search: &PointSearchVectors) -> Result<QueryPoints, QdrantError> {
+    Ok(QueryPoints {
+        collection_name: collection.to_string(),
+        prefetch: search.prefetch.iter().flatten().map(to_prefetch).collect::<Result<_, _>>()?,
+        query: Some(to_query(&search.query)?),
+        using: search.using.clone(),
+        filter: Some(to_filter(&search.filter)?),
+        params: search.params.as_ref().map(to_search_params),
+        score_threshold: search.score_threshold,
+        limit: Some(search.limit as u64),
+        with_payload: with_payload(search.with_payload),
+        shard_key_selector: to_shard_key_selector(search.shard_key.as_deref()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+
+    use super::*;
+    use crate::{
+        models::middleware::{Condition as RestCondition, Query as RestQuery, SearchRequest},
+        qdrant_functions::client::tests::{points, searches, FIRST_ID},
+    };
+
+    fn stored(point: PointStruct) -> RetrievedPoint {
+        let vectors = point.vectors.and_then(|v| v.vectors_options).map(|options| match options {
+            VectorsOptions::Vector(v) => vectors_output::VectorsOptions::Vector(v),
+            VectorsOptions::Vectors(v) => vectors_output::VectorsOptions::Vectors(v),
+        });
+        RetrievedPoint { id: point.id, payload: point.payload, vectors: Some(VectorsOutput { vectors_options: vectors }) }
+    }
+
+    #[test]
+    fn points_read_back_as_the_json_they_were_written_from() {
+        for point in points() {
+            let read_back = from_retrieved_point(stored(to_point_struct(&point).unwrap()));
+            assert_eq!(read_back, point);
+        }
+    }
+
+    #[test]
+    fn scored_points_read_back_as_rest_search_hits() {
+        let hit = json!({
+            "id": FIRST_ID,
+            "version": 3,
+            "score": 0.75,
+            "payload": { "user_id": "user-1", "text": "I love Rust", "username": "ferris" }
+        });
+        let mut payload = hit["payload"].as_object().unwrap().clone();
+        // fields the typed payload does not know are left out, as with REST
+        payload.insert("likes".to_string(), json!(3));
+        let scored = ScoredPoint { id: Some(to_point_id(&hit["id"]).unwrap()), payload: to_payload(&payload), score: 0.75, version: 3 };
+        assert_eq!(serde_json::to_value(from_scored_point(scored).unwrap()).unwrap(), hit);
+
+        // REST would fail to parse a hit without its text as well
+        let scored = ScoredPoint { id: Some(to_point_id(&hit["id"]).unwrap()), ..Default::default() };
+        assert!(from_scored_point(scored).is_err());
+    }
+
+    #[test]
+    fn every_search_of_a_batch_converts() {
+        let request: SearchRequest = serde_json::from_value(searches()).unwrap();
+        for search in &request.searches {
+            let query = to_query_points("tweets", search).unwrap();
+            assert_eq!(query.filter.unwrap().must.len(), search.filter.must.len());
+            assert_eq!(query.limit, Some(search.limit as u64));
+            assert_eq!(query.prefetch.len(), search.prefetch.as_ref().map_or(0, Vec::len));
+        }
+    }
+
+    #[test]
+    fn rejects_what_grpc_cannot_express() {
+        assert!(to_point_id(&json!(true)).is_err());
+        assert!(to_query(&RestQuery::Fusion { fusion: "median".to_string() }).is_err());
+        let range = DateRange { gte: Some("yesterday".to_string()), lte: None };
+        assert!(to_condition(&RestCondition::Range { key: "posted_at".to_string(), range }).is_err());
+    }
+
+    // Field numbers must match Qdrant's points.proto, check the bytes on the wire
+
+    #[test]
+    fn query_points_use_the_upstream_field_numbers() {
+        let query = QueryPoints {
+            collection_name: "c".to_string(),
+            limit: Some(5),
+            score_threshold: Some(0.5),
+            with_payload: with_payload(true),
+            shard_key_selector: to_shard_key_selector(Some("u")),
+            ..Default::default()
+        };
+        let expected = [
+            0x0a, 1, b'c', // collection_name = 1
+            0x3d, 0x00, 0x00, 0x00, 0x3f, // score_threshold = 7, fixed32 0.5
+            0x40, 5, // limit = 8
+            0x5a, 2, 0x08, 1, // with_payload = 11 { enable = 1 }
+            0x6a, 5, 0x0a, 3, 0x0a, 1, b'u', // shard_key_selector = 13 { shard_keys = 1 { keyword = 1 } }
+        ];
+        assert_eq!(query.encode_to_vec(), expected);
+
+        let prefetch = PrefetchQuery {
+            limit: Some(20),
+            using: Some("s".to_string()),
+            score_threshold: Some(0.5),
+            ..Default::default()
+        };
+        // using = 3, score_threshold = 6, limit = 7
+        assert_eq!(prefetch.encode_to_vec(), [0x1a, 1, b's', 0x35, 0x00, 0x00, 0x00, 0x3f, 0x38, 20]);
+    }
+
+    #[test]
+    fn filters_use_the_upstream_field_numbers() {
+        let filter = to_filter(&FilterType {
+            must: vec![
+                RestCondition::HasId { has_id: vec!["u".to_string()] },
+                RestCondition::Range {
+                    key: "k".to_string(),
+                    range: DateRange { gte: Some("1970-01-01T00:00:01Z".to_string()), lte: None },
+                },
+            ],
+        })
+        .unwrap();
+        let expected = [
+            0x12, 7, 0x1a, 5, 0x0a, 3, 0x12, 1, b'u', // must = 2 { has_id = 3 { has_id = 1 { uuid = 2 } } }
+            0x12, 11, 0x0a, 9, 0x0a, 1, b'k', // must = 2 { field = 1 { key = 1
+            0x42, 4, 0x1a, 2, 0x08, 1, // datetime_range = 8 { gte = 3 { seconds = 1 } } } }
+        ];
+        assert_eq!(filter.encode_to_vec(), expected);
+    }
+
+    #[test]
+    fn responses_decode_with_the_upstream_field_numbers() {
+        let bytes = [
+            0x0a, 2, 0x08, 7, // id = 1 { num = 1 }
+            0x1d, 0x00, 0x00, 0x40, 0x3f, // score = 3, fixed32 0.75
+            0x28, 3, // version = 5
+        ];
+        let point = ScoredPoint::decode(&bytes[..]).unwrap();
+        assert_eq!(point.id.unwrap().point_id_options, Some(PointIdOptions::Num(7)));
+        assert_eq!((point.score, point.version), (0.75, 3));
+
+        let bytes = [
+            0x0a, 2, 0x08, 7, // id = 1
+            0x12, 7, 0x0a, 1, b't', 0x12, 2, 0x28, 1, // payload = 2 { "t": bool_value = 5 }
+            0x22, 8, 0x0a, 6, 0x0a, 4, 0x00, 0x00, 0x80, 0x3f, // vectors = 4 { vector = 1 { data = 1 [1.0] } }
+        ];
+        let point = RetrievedPoint::decode(&bytes[..]).unwrap();
+        assert_eq!(from_retrieved_point(point), json!({ "id": 7, "payload": { "t": true }, "vector": [1.0] }));
+    }
+}
@@ -0,0 +1,511 @@
+mod convert;
+mod proto;
+
+use serde_json::Value as Json;
+use tonic::{
+    codec::ProstCodec,
+    codegen::http::uri::PathAndQuery,
+    metadata::MetadataValue,
+    transport::{Channel, ClientTlsConfig, Endpoint},
+};
+
+use crate::{
+    config::WriteOrdering,
+    models::{
+        middleware::{FilterType, SearchRequest},
+        qdrant::{ScrollRequest, ScrollResult, UpdateResult},
+        similarity_result::ResultItem,
+    },
+    qdrant_functions::{
+        error::QdrantError,
+        grpc::proto::{
+            CountPoints, CountResponse, DeletePoints, PointsOperationResponse, QueryBatchPoints,
+            QueryBatchResponse, ScrollPoints, ScrollResponse, UpdateStatus, UpsertPoints,
+        },
+    },
+};
+
+// Point operations over Qdrant's gRPC API (qdrant.Points service). Takes and
+// returns the same typed models as the REST path, converted straight to and
+// from protobuf, so QdrantClient can switch transports without touching them.
+pub struct GrpcTransport {
+    channel: Channel,
+    api_key: Option<MetadataValue<tonic::metadata::Ascii>>,
+}
+
+impl GrpcTransport {
+    // The channel connects lazily and reconnects on its own
+    pub fn new(endpoint: &str, api_key: Option<&str>) -> Result<Self, anyhow::Error> {
+        let mut builder = Endpoint::from_shared(endpoint.to_string())
+            .map_err(|e| anyhow::anyhow!("Invalid QDRANT_GRPC_ENDPOINT {}: {}", endpoint, e))?;
+        if endpoint.starts_with("https://") {
+            builder = builder.tls_config(ClientTlsConfig::new().with_webpki_roots())?;
+        }
+        let api_key = api_key
+            .map(|key| key.parse().map_err(|_| anyhow::anyhow!("QDRANT_API_KEY is not a valid header value")))
+            .transpose()?;
+
+        Ok(GrpcTransport { channel: builder.connect_lazy(), api_key })
+    }
+
+    async fn unary<Req, Resp>(&self, path: &'static str, message: Req) -> Result<Resp, QdrantError>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Resp: prost::Message + Default + Send + Sync + 'static,
+    {
+        let mut client = tonic::client::Grpc::new(self.channel.clone());
+        client
+            .ready()
+            .await
+            .map_err(|e| tonic::Status::unavailable(e.to_string()))?;
+
+        let mut request = tonic::Request::new(message);
+        if let Some(api_key) = &self.api_key {
+            request.metadata_mut().insert("api-key", api_key.clone());
+        }
+
+        let response = client
+            .unary(request, PathAndQuery::from_static(path), ProstCodec::<Req, Resp>::default())
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    fn update_result(response: PointsOperationResponse) -> UpdateResult {
+        let result = response.result.unwrap_or_default();
+        let status = match UpdateStatus::try_from(result.status) {
+            Ok(UpdateStatus::Acknowledged) => "acknowledged",
+            Ok(UpdateStatus::Completed) => "completed",
+            Ok(UpdateStatus::ClockRejected) => "clock_rejected",
+            _ => "unknown",
+        };
+        UpdateResult { operation_id: result.operation_id, status: status.to_string() }
+    }
+
+    // `points` as sent to PUT /points: {"id", "vector", "payload"}
+    pub async fn upsert(
+        &self,
+        collection: &str,
+        shard_key: Option<&str>,
+        points: &[Json],
+        wait: bool,
+        ordering: WriteOrdering,
+    ) -> Result<UpdateResult, QdrantError> {
+        let points = points.iter().map(convert::to_point_struct).collect::<Result<_, _>>()?;
+        let response = self
+            .unary::<_, PointsOperationResponse>(
+                "/qdrant.Points/Upsert",
+                UpsertPoints {
+                    collection_name: collection.to_string(),
+                    wait: Some(wait),
+                    points,
+                    ordering: Some(convert::to_write_ordering(ordering)),
+                    shard_key_selector: convert::to_shard_key_selector(shard_key),
+                },
+            )
+            .await?;
+        Ok(Self::update_result(response))
+    }
+
+    pub async fn delete_by_filter(
+        &self,
+        collection: &str,
+        shard_key: Option<&str>,
+        filter: &FilterType,
+        wait: bool,
+    ) -> Result<UpdateResult, QdrantError> {
+        let response = self
+            .unary::<_, PointsOperationResponse>(
+                "/qdrant.Points/Delete",
+                DeletePoints {
+                    collection_name: collection.to_string(),
+                    wait: Some(wait),
+                    points: Some(convert::to_filter_selector(filter)?),
+                    shard_key_selector: convert::to_shard_key_selector(shard_key),
+                },
+            )
+            .await?;
+        Ok(Self::update_result(response))
+    }
+
+    pub async fn scroll(&self, collection: &str, request: &ScrollRequest) -> Result<ScrollResult<Json>, QdrantError> {
+        let request = ScrollPoints {
+            collection_name: collection.to_string(),
+            filter: Some(convert::to_filter(&request.filter)?),
+            offset: request.offset.as_ref().map(convert::to_offset),
+            limit: Some(request.limit),
+            with_payload: convert::with_payload(request.with_payload),
+            with_vectors: convert::with_vectors(request.with_vector),
+            shard_key_selector: convert::to_shard_key_selector(request.shard_key.as_deref()),
+        };
+
+        let response = self.unary::<_, ScrollResponse>("/qdrant.Points/Scroll", request).await?;
+        Ok(ScrollResult {
+            points: response.result.into_iter().map(convert::from_retrieved_point).collect(),
+            next_page_offset: response.next_page_offset.and_then(convert::from_offset),
+        })
+    }
+
+    pub async fn count(&self, collection: &str, shard_key: Option<&str>, filter: &FilterType, exact: bool) -> Result<u64, QdrantError> {
+        let request = CountPoints {
+            collection_name: collection.to_string(),
+            filter: Some(convert::to_filter(filter)?),
+            exact: Some(exact),
+            shard_key_selector: convert::to_shard_key_selector(shard_key),
+        };
+
+        let response = self.unary::<_, CountResponse>("/qdrant.Points/Count", request).await?;
+        Ok(response.result.unwrap_or_default().count)
+    }
+
+    pub async fn query_batch(&self, collection: &str, request: &SearchRequest) -> Result<Vec<ResultItem>, QdrantError> {
+        let query_points = request
+            .searches
+            .iter()
+            .map(|search| convert::to_query_points(collection, search))
+            .collect::<Result<_, _>>()?;
+
+        let response = self
+            .unary::<_, QueryBatchResponse>(
+                "/qdrant.Points/QueryBatch",
+                QueryBatchPoints { collection_name: collection.to_string(), query_points },
+            )
+            .await?;
+        response
+            .result
+            .into_iter()
+            .map(|batch| {
+                let points = batch.result.into_iter().map(convert::from_scored_point).collect::<Result<_, _>>()?;
+                Ok(ResultItem { points })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        future::{ready, Ready},
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    };
+
+    use tonic::{
+        body::Body,
+        codegen::{http, BoxFuture, Service},
+        server::{Grpc, NamedService, UnaryService},
+        transport::{server::TcpIncoming, Server},
+    };
+
+    use super::{
+        convert::{from_retrieved_point, to_payload, to_point_id, to_point_struct},
+        proto::{
+            condition::ConditionOneOf, point_id::PointIdOptions, points_selector::PointsSelectorOneOf,
+            query::Variant, r#match::MatchValue, shard_key::Key, vector_input::Variant as Input, vectors,
+            vectors_output, with_payload_selector::SelectorOptions, BatchResult, CountResult, Filter, Fusion,
+            PointId, PointStruct, RecommendStrategy, RetrievedPoint, ScoredPoint, UpdateResult, VectorsOutput,
+            WriteOrderingType,
+        },
+        *,
+    };
+    use crate::qdrant_functions::client::{
+        tests::{config, points, query_results, run_point_operations, API_KEY, COLLECTION, FIRST_ID, SECOND_ID},
+        QdrantClient,
+    };
+    use crate::config::QdrantTransport;
+
+    // Every request the stub decoded, in arrival order
+    #[derive(Default)]
+    struct Received {
+        api_keys: Vec<Option<String>>,
+        upserts: Vec<UpsertPoints>,
+        deletes: Vec<DeletePoints>,
+        scrolls: Vec<ScrollPoints>,
+        counts: Vec<CountPoints>,
+        queries: Vec<QueryBatchPoints>,
+    }
+
+    // qdrant.Points with canned answers matching the REST stub's
+    #[derive(Clone, Default)]
+    struct Stub {
+        received: Arc<Mutex<Received>>,
+    }
+
+    impl NamedService for Stub {
+        const NAME: &'static str = "qdrant.Points";
+    }
+
+    struct Unary<F>(F);
+
+    impl<Req, Resp, F> UnaryService<Req> for Unary<F>
+    where
+        F: FnMut(tonic::Request<Req>) -> Resp,
+        Resp: Send + 'static,
+    {
+        type Response = Resp;
+        type Future = Ready<Result<tonic::Response<Resp>, tonic::Status>>;
+
+        fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+            ready(Ok(tonic::Response::new((self.0)(request))))
+        }
+    }
+
+    // What Qdrant hands back for a point it stored
+    fn stored(point: PointStruct) -> RetrievedPoint {
+        let vectors = point.vectors.and_then(|v| v.vectors_options).map(|options| match options {
+            vectors::VectorsOptions::Vector(v) => vectors_output::VectorsOptions::Vector(v),
+            vectors::VectorsOptions::Vectors(v) => vectors_output::VectorsOptions::Vectors(v),
+        });
+        RetrievedPoint {
+            id: point.id,
+            payload: point.payload,
+            vectors: Some(VectorsOutput { vectors_options: vectors }),
+        }
+    }
+
+    fn retrieved(point: &Json) -> RetrievedPoint {
+        stored(to_point_struct(point).unwrap())
+    }
+
+    fn scored(point: &Json) -> ScoredPoint {
+        ScoredPoint {
+            id: Some(to_point_id(&point["id"]).unwrap()),
+            payload: to_payload(point["payload"].as_object().unwrap()),
+            score: point["score"].as_f64().unwrap() as f32,
+            version: point["version"].as_u64().unwrap(),
+        }
+    }
+
+    fn updated(operation_id: u64, status: UpdateStatus) -> PointsOperationResponse {
+        PointsOperationResponse {
+            result: Some(UpdateResult { operation_id: Some(operation_id), status: status as i32 }),
+            time: 0.001,
+        }
+    }
+
+    impl Stub {
+        async fn serve<Req, Resp>(
+            &self,
+            request: http::Request<Body>,
+            record: fn(&mut Received, Req),
+            reply: fn() -> Resp,
+        ) -> http::Response<Body>
+        where
+            Req: prost::Message + Default + Send + 'static,
+            Resp: prost::Message + Send + 'static,
+        {
+            let received = self.received.clone();
+            let service = Unary(move |request: tonic::Request<Req>| {
+                let mut received = received.lock().unwrap();
+                let api_key = request.metadata().get("api-key").and_then(|v| v.to_str().ok()).map(str::to_string);
+                received.api_keys.push(api_key);
+                record(&mut received, request.into_inner());
+                reply()
+            });
+            Grpc::new(ProstCodec::<Resp, Req>::default()).unary(service, request).await
+        }
+    }
+
+    impl Service<http::Request<Body>> for Stub {
+        type Response = http::Response<Body>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+            let stub = self.clone();
+            Box::pin(async move {
+                let response = match request.uri().path() {
+                    "/qdrant.Points/Upsert" => {
+                        stub.serve(request, |r, m| r.upserts.push(m), || updated(7, UpdateStatus::Acknowledged)).await
+                    }
+                    "/qdrant.Points/Delete" => {
+                        stub.serve(request, |r, m| r.deletes.push(m), || updated(8, UpdateStatus::Completed)).await
+                    }
+                    "/qdrant.Points/Scroll" => {
+                        let reply = || ScrollResponse {
+                            next_page_offset: Some(PointId { point_id_options: Some(PointIdOptions::Num(7)) }),
+                            result: points().iter().map(retrieved).collect(),
+                            time: 0.001,
+                        };
+                        stub.serve(request, |r, m| r.scrolls.push(m), reply).await
+                    }
+                    "/qdrant.Points/Count" => {
+                        let reply = || CountResponse { result: Some(CountResult { count: 42 }), time: 0.001 };
+                        stub.serve(request, |r, m| r.counts.push(m), reply).await
+                    }
+                    "/qdrant.Points/QueryBatch" => {
+                        let reply = || QueryBatchResponse {
+                            result: query_results()
+                                .as_array()
+                                .unwrap()
+                                .iter()
+                                .map(|points| BatchResult {
+                                    result: points.as_array().unwrap().iter().map(scored).collect(),
+                                })
+                                .collect(),
+                            time: 0.001,
+                        };
+                        stub.serve(request, |r, m| r.queries.push(m), reply).await
+                    }
+                    // UNIMPLEMENTED
+                    _ => http::Response::builder().header("grpc-status", "12").body(Body::default()).unwrap(),
+                };
+                Ok(response)
+            })
+        }
+    }
+
+    async fn start_grpc_stub() -> (String, Arc<Mutex<Received>>) {
+        let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let address = incoming.local_addr().unwrap();
+        let stub = Stub::default();
+        let received = stub.received.clone();
+        tokio::spawn(Server::builder().add_service(stub).serve_with_incoming(incoming));
+        (format!("http://{}", address), received)
+    }
+
+    fn keyword(condition: &proto::Condition) -> (&str, &str) {
+        match &condition.condition_one_of {
+            Some(ConditionOneOf::Field(field)) => match &field.r#match.as_ref().unwrap().match_value {
+                Some(MatchValue::Keyword(value)) => (field.key.as_str(), value.as_str()),
+                other => panic!("expected a keyword match, got {:?}", other),
+            },
+            other => panic!("expected a field condition, got {:?}", other),
+        }
+    }
+
+    fn user_only(filter: &Option<Filter>) {
+        let filter = filter.as_ref().unwrap();
+        assert_eq!(filter.must.len(), 1);
+        assert_eq!(keyword(&filter.must[0]), ("user_id", "user-1"));
+    }
+
+    fn uuid(id: &str) -> PointId {
+        PointId { point_id_options: Some(PointIdOptions::Uuid(id.to_string())) }
+    }
+
+    fn shard(selector: &Option<proto::ShardKeySelector>) -> Option<&str> {
+        selector.as_ref().map(|selector| match &selector.shard_keys[0].key {
+            Some(Key::Keyword(key)) => key.as_str(),
+            other => panic!("expected a keyword shard key, got {:?}", other),
+        })
+    }
+
+    #[tokio::test]
+    async fn grpc_transport_sends_the_rest_requests_as_protobuf() {
+        let (grpc_endpoint, received) = start_grpc_stub().await;
+        // The REST endpoint is never contacted on the gRPC transport
+        let client = QdrantClient::new(&config(QdrantTransport::Grpc, "http://127.0.0.1:1", &grpc_endpoint)).unwrap();
+        assert_eq!(client.transport(), "grpc");
+
+        run_point_operations(&client).await;
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.api_keys, vec![Some(API_KEY.to_string()); 5]);
+
+        let upsert = &received.upserts[0];
+        assert_eq!(upsert.collection_name, COLLECTION);
+        assert_eq!(upsert.wait, Some(false));
+        assert_eq!(upsert.ordering.as_ref().unwrap().r#type, WriteOrderingType::Medium as i32);
+        assert_eq!(shard(&upsert.shard_key_selector), Some("user-1"));
+        // Reading the stored points back gives the JSON that was sent
+        let read_back: Vec<Json> = upsert.points.iter().cloned().map(stored).map(from_retrieved_point).collect();
+        assert_eq!(read_back, points());
+
+        let delete = &received.deletes[0];
+        assert_eq!(delete.wait, Some(true));
+        assert_eq!(shard(&delete.shard_key_selector), None);
+        match &delete.points.as_ref().unwrap().points_selector_one_of {
+            Some(PointsSelectorOneOf::Filter(filter)) => {
+                assert_eq!(keyword(&filter.must[0]), ("user_id", "user-1"));
+                match &filter.must[1].condition_one_of {
+                    Some(ConditionOneOf::HasId(has_id)) => assert_eq!(has_id.has_id, vec![uuid(FIRST_ID)]),
+                    other => panic!("expected has_id, got {:?}", other),
+                }
+            }
+            other => panic!("expected a filter selector, got {:?}", other),
+        }
+
+        let scroll = &received.scrolls[0];
+        user_only(&scroll.filter);
+        assert_eq!(scroll.offset, Some(uuid(SECOND_ID)));
+        assert_eq!(scroll.limit, Some(2));
+        assert!(scroll.with_payload.is_some());
+        assert!(scroll.with_vectors.is_some());
+
+        let count = &received.counts[0];
+        user_only(&count.filter);
+        assert_eq!(count.exact, Some(true));
+        assert_eq!(shard(&count.shard_key_selector), Some("user-1"));
+
+        let batch = &received.queries[0];
+        assert_eq!(batch.collection_name, COLLECTION);
+        let [dense, hybrid, recommend] = &batch.query_points[..] else {
+            panic!("expected three queries, got {}", batch.query_points.len());
+        };
+
+        user_only(&dense.filter);
+        assert_eq!(dense.limit, Some(3));
+        assert_eq!(dense.score_threshold, Some(0.5));
+        assert_eq!(shard(&dense.shard_key_selector), Some("user-1"));
+        assert_eq!(
+            dense.with_payload.as_ref().unwrap().selector_options,
+            Some(SelectorOptions::Enable(true))
+        );
+        match &dense.query.as_ref().unwrap().variant {
+            Some(Variant::Nearest(input)) => match &input.variant {
+                Some(Input::Dense(vector)) => assert_eq!(vector.data, vec![0.5, -0.25, 1.0]),
+                other => panic!("expected a dense input, got {:?}", other),
+            },
+            other => panic!("expected a nearest query, got {:?}", other),
+        }
+
+        assert_eq!(hybrid.limit, Some(2));
+        assert_eq!(hybrid.query.as_ref().unwrap().variant, Some(Variant::Fusion(Fusion::Rrf as i32)));
+        assert_eq!(hybrid.prefetch.len(), 2);
+        assert_eq!(hybrid.prefetch[0].limit, Some(20));
+        assert_eq!(hybrid.prefetch[0].using, None);
+        assert_eq!(hybrid.prefetch[0].score_threshold, Some(0.5));
+        assert_eq!(hybrid.prefetch[1].score_threshold, None);
+        assert_eq!(hybrid.score_threshold, None);
+        assert_eq!(hybrid.prefetch[1].using.as_deref(), Some("bm25"));
+        match &hybrid.prefetch[1].query.as_ref().unwrap().variant {
+            Some(Variant::Nearest(input)) => match &input.variant {
+                Some(Input::Sparse(vector)) => {
+                    assert_eq!(vector.indices, vec![3]);
+                    assert_eq!(vector.values, vec![1.0]);
+                }
+                other => panic!("expected a sparse input, got {:?}", other),
+            },
+            other => panic!("expected a nearest prefetch, got {:?}", other),
+        }
+        match &hybrid.filter.as_ref().unwrap().must[0].condition_one_of {
+            Some(ConditionOneOf::HasId(has_id)) => assert_eq!(has_id.has_id, vec![uuid(FIRST_ID)]),
+            other => panic!("expected has_id, got {:?}", other),
+        }
+
+        match &recommend.query.as_ref().unwrap().variant {
+            Some(Variant::Recommend(input)) => {
+                assert_eq!(input.positive.len(), 1);
+                assert_eq!(input.negative.len(), 1);
+                assert_eq!(input.strategy, Some(RecommendStrategy::BestScore as i32));
+            }
+            other => panic!("expected a recommend query, got {:?}", other),
+        }
+        match &recommend.filter.as_ref().unwrap().must[0].condition_one_of {
+            Some(ConditionOneOf::Field(field)) => {
+                assert_eq!(field.key, "posted_at");
+                // 2024-01-01T00:00:00Z
+                assert_eq!(field.datetime_range.as_ref().unwrap().gte.as_ref().unwrap().seconds, 1_704_067_200);
+            }
+            other => panic!("expected a datetime range, got {:?}", other),
+        }
+        let quantization = recommend.params.as_ref().unwrap().quantization.as_ref().unwrap();
+        assert_eq!(quantization.rescore, Some(true));
+        assert_eq!(quantization.oversampling, Some(2.0));
+    }
+}
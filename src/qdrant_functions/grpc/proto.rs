@@ -0,0 +1,620 @@
+// Hand written subset of Qdrant's gRPC API (qdrant/lib/api/src/grpc/proto,
+// points.proto and json_with_int.proto). Only the messages and fields this
+// server sends or reads are declared, tags match the upstream definitions.
+
+// variant names follow the proto definitions
+#![allow(clippy::enum_variant_names)]
+
+use std::collections::HashMap;
+
+// ---- json_with_int.proto ----
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Struct {
+    #[prost(map = "string, message", tag = "1")]
+    pub fields: HashMap<String, Value>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Value {
+    #[prost(oneof = "value::Kind", tags = "1, 2, 3, 4, 5, 6, 7")]
+    pub kind: Option<value::Kind>,
+}
+
+pub mod value {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Kind {
+        #[prost(enumeration = "super::NullValue", tag = "1")]
+        NullValue(i32),
+        #[prost(double, tag = "2")]
+        DoubleValue(f64),
+        #[prost(int64, tag = "3")]
+        IntegerValue(i64),
+        #[prost(string, tag = "4")]
+        StringValue(String),
+        #[prost(bool, tag = "5")]
+        BoolValue(bool),
+        #[prost(message, tag = "6")]
+        StructValue(super::Struct),
+        #[prost(message, tag = "7")]
+        ListValue(super::ListValue),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListValue {
+    #[prost(message, repeated, tag = "1")]
+    pub values: Vec<Value>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum NullValue {
+    NullValue = 0,
+}
+
+// ---- ids, vectors ----
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PointId {
+    #[prost(oneof = "point_id::PointIdOptions", tags = "1, 2")]
+    pub point_id_options: Option<point_id::PointIdOptions>,
+}
+
+pub mod point_id {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum PointIdOptions {
+        #[prost(uint64, tag = "1")]
+        Num(u64),
+        #[prost(string, tag = "2")]
+        Uuid(String),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SparseIndices {
+    #[prost(uint32, repeated, tag = "1")]
+    pub data: Vec<u32>,
+}
+
+// Dense vectors only fill `data`, sparse ones fill `data` with the values and `indices`
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Vector {
+    #[prost(float, repeated, tag = "1")]
+    pub data: Vec<f32>,
+    #[prost(message, optional, tag = "2")]
+    pub indices: Option<SparseIndices>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct NamedVectors {
+    #[prost(map = "string, message", tag = "1")]
+    pub vectors: HashMap<String, Vector>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Vectors {
+    #[prost(oneof = "vectors::VectorsOptions", tags = "1, 2")]
+    pub vectors_options: Option<vectors::VectorsOptions>,
+}
+
+pub mod vectors {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum VectorsOptions {
+        #[prost(message, tag = "1")]
+        Vector(super::Vector),
+        #[prost(message, tag = "2")]
+        Vectors(super::NamedVectors),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DenseVector {
+    #[prost(float, repeated, tag = "1")]
+    pub data: Vec<f32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SparseVector {
+    #[prost(float, repeated, tag = "1")]
+    pub values: Vec<f32>,
+    #[prost(uint32, repeated, tag = "2")]
+    pub indices: Vec<u32>,
+}
+
+// ---- filters ----
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Filter {
+    #[prost(message, repeated, tag = "1")]
+    pub should: Vec<Condition>,
+    #[prost(message, repeated, tag = "2")]
+    pub must: Vec<Condition>,
+    #[prost(message, repeated, tag = "3")]
+    pub must_not: Vec<Condition>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Condition {
+    #[prost(oneof = "condition::ConditionOneOf", tags = "1, 3, 4")]
+    pub condition_one_of: Option<condition::ConditionOneOf>,
+}
+
+pub mod condition {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum ConditionOneOf {
+        #[prost(message, tag = "1")]
+        Field(super::FieldCondition),
+        #[prost(message, tag = "3")]
+        HasId(super::HasIdCondition),
+        #[prost(message, tag = "4")]
+        Filter(super::Filter),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HasIdCondition {
+    #[prost(message, repeated, tag = "1")]
+    pub has_id: Vec<PointId>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FieldCondition {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub r#match: Option<Match>,
+    #[prost(message, optional, tag = "3")]
+    pub range: Option<Range>,
+    #[prost(message, optional, tag = "8")]
+    pub datetime_range: Option<DatetimeRange>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Match {
+    #[prost(oneof = "r#match::MatchValue", tags = "1, 2, 3, 4, 5")]
+    pub match_value: Option<r#match::MatchValue>,
+}
+
+pub mod r#match {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum MatchValue {
+        #[prost(string, tag = "1")]
+        Keyword(String),
+        #[prost(int64, tag = "2")]
+        Integer(i64),
+        #[prost(bool, tag = "3")]
+        Boolean(bool),
+        #[prost(string, tag = "4")]
+        Text(String),
+        #[prost(message, tag = "5")]
+        Keywords(super::RepeatedStrings),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RepeatedStrings {
+    #[prost(string, repeated, tag = "1")]
+    pub strings: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Range {
+    #[prost(double, optional, tag = "1")]
+    pub lt: Option<f64>,
+    #[prost(double, optional, tag = "2")]
+    pub gt: Option<f64>,
+    #[prost(double, optional, tag = "3")]
+    pub gte: Option<f64>,
+    #[prost(double, optional, tag = "4")]
+    pub lte: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Timestamp {
+    #[prost(int64, tag = "1")]
+    pub seconds: i64,
+    #[prost(int32, tag = "2")]
+    pub nanos: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DatetimeRange {
+    #[prost(message, optional, tag = "1")]
+    pub lt: Option<Timestamp>,
+    #[prost(message, optional, tag = "2")]
+    pub gt: Option<Timestamp>,
+    #[prost(message, optional, tag = "3")]
+    pub gte: Option<Timestamp>,
+    #[prost(message, optional, tag = "4")]
+    pub lte: Option<Timestamp>,
+}
+
+// ---- write operations ----
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PointStruct {
+    #[prost(message, optional, tag = "1")]
+    pub id: Option<PointId>,
+    #[prost(map = "string, message", tag = "3")]
+    pub payload: HashMap<String, Value>,
+    #[prost(message, optional, tag = "4")]
+    pub vectors: Option<Vectors>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UpsertPoints {
+    #[prost(string, tag = "1")]
+    pub collection_name: String,
+    #[prost(bool, optional, tag = "2")]
+    pub wait: Option<bool>,
+    #[prost(message, repeated, tag = "3")]
+    pub points: Vec<PointStruct>,
+    #[prost(message, optional, tag = "4")]
+    pub ordering: Option<WriteOrdering>,
+    #[prost(message, optional, tag = "5")]
+    pub shard_key_selector: Option<ShardKeySelector>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WriteOrdering {
+    #[prost(enumeration = "WriteOrderingType", tag = "1")]
+    pub r#type: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum WriteOrderingType {
+    Weak = 0,
+    Medium = 1,
+    Strong = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ShardKey {
+    #[prost(oneof = "shard_key::Key", tags = "1, 2")]
+    pub key: Option<shard_key::Key>,
+}
+
+pub mod shard_key {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Key {
+        #[prost(string, tag = "1")]
+        Keyword(String),
+        #[prost(uint64, tag = "2")]
+        Number(u64),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ShardKeySelector {
+    #[prost(message, repeated, tag = "1")]
+    pub shard_keys: Vec<ShardKey>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PointsIdsList {
+    #[prost(message, repeated, tag = "1")]
+    pub ids: Vec<PointId>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PointsSelector {
+    #[prost(oneof = "points_selector::PointsSelectorOneOf", tags = "1, 2")]
+    pub points_selector_one_of: Option<points_selector::PointsSelectorOneOf>,
+}
+
+pub mod points_selector {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum PointsSelectorOneOf {
+        #[prost(message, tag = "1")]
+        Points(super::PointsIdsList),
+        #[prost(message, tag = "2")]
+        Filter(super::Filter),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DeletePoints {
+    #[prost(string, tag = "1")]
+    pub collection_name: String,
+    #[prost(bool, optional, tag = "2")]
+    pub wait: Option<bool>,
+    #[prost(message, optional, tag = "3")]
+    pub points: Option<PointsSelector>,
+    #[prost(message, optional, tag = "5")]
+    pub shard_key_selector: Option<ShardKeySelector>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct UpdateResult {
+    #[prost(uint64, optional, tag = "1")]
+    pub operation_id: Option<u64>,
+    #[prost(enumeration = "UpdateStatus", tag = "2")]
+    pub status: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum UpdateStatus {
+    UnknownUpdateStatus = 0,
+    Acknowledged = 1,
+    Completed = 2,
+    ClockRejected = 3,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PointsOperationResponse {
+    #[prost(message, optional, tag = "1")]
+    pub result: Option<UpdateResult>,
+    #[prost(double, tag = "2")]
+    pub time: f64,
+}
+
+// ---- reads ----
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WithPayloadSelector {
+    #[prost(oneof = "with_payload_selector::SelectorOptions", tags = "1")]
+    pub selector_options: Option<with_payload_selector::SelectorOptions>,
+}
+
+pub mod with_payload_selector {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum SelectorOptions {
+        #[prost(bool, tag = "1")]
+        Enable(bool),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct WithVectorsSelector {
+    #[prost(oneof = "with_vectors_selector::SelectorOptions", tags = "1")]
+    pub selector_options: Option<with_vectors_selector::SelectorOptions>,
+}
+
+pub mod with_vectors_selector {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum SelectorOptions {
+        #[prost(bool, tag = "1")]
+        Enable(bool),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScrollPoints {
+    #[prost(string, tag = "1")]
+    pub collection_name: String,
+    #[prost(message, optional, tag = "2")]
+    pub filter: Option<Filter>,
+    #[prost(message, optional, tag = "3")]
+    pub offset: Option<PointId>,
+    #[prost(uint32, optional, tag = "4")]
+    pub limit: Option<u32>,
+    #[prost(message, optional, tag = "6")]
+    pub with_payload: Option<WithPayloadSelector>,
+    #[prost(message, optional, tag = "7")]
+    pub with_vectors: Option<WithVectorsSelector>,
+    #[prost(message, optional, tag = "9")]
+    pub shard_key_selector: Option<ShardKeySelector>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VectorsOutput {
+    #[prost(oneof = "vectors_output::VectorsOptions", tags = "1, 2")]
+    pub vectors_options: Option<vectors_output::VectorsOptions>,
+}
+
+pub mod vectors_output {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum VectorsOptions {
+        #[prost(message, tag = "1")]
+        Vector(super::Vector),
+        #[prost(message, tag = "2")]
+        Vectors(super::NamedVectors),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RetrievedPoint {
+    #[prost(message, optional, tag = "1")]
+    pub id: Option<PointId>,
+    #[prost(map = "string, message", tag = "2")]
+    pub payload: HashMap<String, Value>,
+    #[prost(message, optional, tag = "4")]
+    pub vectors: Option<VectorsOutput>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScrollResponse {
+    #[prost(message, optional, tag = "1")]
+    pub next_page_offset: Option<PointId>,
+    #[prost(message, repeated, tag = "2")]
+    pub result: Vec<RetrievedPoint>,
+    #[prost(double, tag = "3")]
+    pub time: f64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CountPoints {
+    #[prost(string, tag = "1")]
+    pub collection_name: String,
+    #[prost(message, optional, tag = "2")]
+    pub filter: Option<Filter>,
+    #[prost(bool, optional, tag = "3")]
+    pub exact: Option<bool>,
+    #[prost(message, optional, tag = "5")]
+    pub shard_key_selector: Option<ShardKeySelector>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CountResult {
+    #[prost(uint64, tag = "1")]
+    pub count: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CountResponse {
+    #[prost(message, optional, tag = "1")]
+    pub result: Option<CountResult>,
+    #[prost(double, tag = "2")]
+    pub time: f64,
+}
+
+// ---- universal query ----
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct VectorInput {
+    #[prost(oneof = "vector_input::Variant", tags = "1, 2, 3")]
+    pub variant: Option<vector_input::Variant>,
+}
+
+pub mod vector_input {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Variant {
+        #[prost(message, tag = "1")]
+        Id(super::PointId),
+        #[prost(message, tag = "2")]
+        Dense(super::DenseVector),
+        #[prost(message, tag = "3")]
+        Sparse(super::SparseVector),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Fusion {
+    Rrf = 0,
+    Dbsf = 1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum RecommendStrategy {
+    AverageVector = 0,
+    BestScore = 1,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RecommendInput {
+    #[prost(message, repeated, tag = "1")]
+    pub positive: Vec<VectorInput>,
+    #[prost(message, repeated, tag = "2")]
+    pub negative: Vec<VectorInput>,
+    #[prost(enumeration = "RecommendStrategy", optional, tag = "3")]
+    pub strategy: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Query {
+    #[prost(oneof = "query::Variant", tags = "1, 2, 6")]
+    pub variant: Option<query::Variant>,
+}
+
+pub mod query {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Variant {
+        #[prost(message, tag = "1")]
+        Nearest(super::VectorInput),
+        #[prost(message, tag = "2")]
+        Recommend(super::RecommendInput),
+        #[prost(enumeration = "super::Fusion", tag = "6")]
+        Fusion(i32),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QuantizationSearchParams {
+    #[prost(bool, optional, tag = "1")]
+    pub ignore: Option<bool>,
+    #[prost(bool, optional, tag = "2")]
+    pub rescore: Option<bool>,
+    #[prost(double, optional, tag = "3")]
+    pub oversampling: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SearchParams {
+    #[prost(uint64, optional, tag = "1")]
+    pub hnsw_ef: Option<u64>,
+    #[prost(bool, optional, tag = "2")]
+    pub exact: Option<bool>,
+    #[prost(message, optional, tag = "3")]
+    pub quantization: Option<QuantizationSearchParams>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PrefetchQuery {
+    #[prost(message, repeated, tag = "1")]
+    pub prefetch: Vec<PrefetchQuery>,
+    #[prost(message, optional, tag = "2")]
+    pub query: Option<Query>,
+    #[prost(string, optional, tag = "3")]
+    pub using: Option<String>,
+    #[prost(message, optional, tag = "4")]
+    pub filter: Option<Filter>,
+    #[prost(message, optional, tag = "5")]
+    pub params: Option<SearchParams>,
+    #[prost(float, optional, tag = "6")]
+    pub score_threshold: Option<f32>,
+    #[prost(uint64, optional, tag = "7")]
+    pub limit: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryPoints {
+    #[prost(string, tag = "1")]
+    pub collection_name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub prefetch: Vec<PrefetchQuery>,
+    #[prost(message, optional, tag = "3")]
+    pub query: Option<Query>,
+    #[prost(string, optional, tag = "4")]
+    pub using: Option<String>,
+    #[prost(message, optional, tag = "5")]
+    pub filter: Option<Filter>,
+    #[prost(message, optional, tag = "6")]
+    pub params: Option<SearchParams>,
+    #[prost(float, optional, tag = "7")]
+    pub score_threshold: Option<f32>,
+    #[prost(uint64, optional, tag = "8")]
+    pub limit: Option<u64>,
+    #[prost(message, optional, tag = "11")]
+    pub with_payload: Option<WithPayloadSelector>,
+    #[prost(message, optional, tag = "13")]
+    pub shard_key_selector: Option<ShardKeySelector>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryBatchPoints {
+    #[prost(string, tag = "1")]
+    pub collection_name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub query_points: Vec<QueryPoints>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScoredPoint {
+    #[prost(message, optional, tag = "1")]
+    pub id: Option<PointId>,
+    #[prost(map = "string, message", tag = "2")]
+    pub payload: HashMap<String, Value>,
+    #[prost(float, tag = "3")]
+    pub score: f32,
+    #[prost(uint64, tag = "5")]
+    pub version: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BatchResult {
+    #[prost(message, repeated, tag = "1")]
+    pub result: Vec<ScoredPoint>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryBatchResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub result: Vec<BatchResult>,
+    #[prost(double, tag = "2")]
+    pub time: f64,
+}
@@ -1,118 +1,324 @@
 use chrono::prelude::*;
-use std::{env};
-use crate::{models::{limits::{EntitlementPoint, EntitlementRequest, UserEntitlement, EntitlementRootSearch}, similarity_result::RootSearch}, qdrant_functions::{middleware_conversion::unique_user_id, search::search}};
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use tokio::sync::OwnedMutexGuard;
+use crate::{config::{BudgetConfig, MatchConfig}, embeddings::split::estimate_tokens, models::{limits::{EntitlementPoint, MatchSettings, UserEntitlement}, qdrant::ScrollResult}, qdrant_functions::{middleware_conversion::unique_user_id, search::search}, vector_store::{tenancy::{Tenant, TenantStore}, to_points, Scope, VectorStore, WriteOptions}};
 
+// One lock per user, held across every read-modify-write of their
+// entitlement so concurrent updates can't overwrite each other
+#[derive(Default)]
+pub struct EntitlementLocks {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl EntitlementLocks {
+    pub async fn lock(&self, user_id: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            // drop the locks nobody is holding or waiting for
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(user_id.to_string()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+pub async fn create_user(client: &dyn VectorStore, user_id: String) -> Result<UserEntitlement, anyhow::Error> {
+    let date = Local::now().date_naive();
+    let entitlement = UserEntitlement {
+        user_id,
+        plan: "Free".to_string(),
+        max_tweets: 0,
+        max_searches_per_day: 20,
+        tweet_count: 0,
+        searches_used_today: 0,
+        last_reset_date: date.format("%F").to_string(),
+        valid_until: None,
+        tokens_used_today: 0,
+        tokens_used_month: 0,
+        usage_month: date.format("%Y-%m").to_string(),
+        match_min_score: None,
+        match_top_k: None,
+    };
+
+    save_entitlement(client, &entitlement).await?;
+    Ok(entitlement)
+}
+
+// Overwrites the user's entitlement point with `entitlement`
+pub async fn save_entitlement(client: &dyn VectorStore, entitlement: &UserEntitlement) -> Result<(), anyhow::Error> {
+    let point = EntitlementPoint {
+        id: unique_user_id(&entitlement.user_id),
+        vector: Some([0.0].to_vec()),
+        payload: entitlement.clone(),
+    };
+
+    if let Err(e) = client.upsert(&Scope::collection(client.entitlements()), &to_points(&[point])?, WriteOptions::default()).await {
+        println!("Qdrant upload failed: {}", e);
+        anyhow::bail!("Qdrant upload failed: {}", e);
+    }
+
+    Ok(())
+}
+
+// Callers must hold the user's lock
+async fn load_or_create(client: &dyn VectorStore, user_id: String) -> Result<UserEntitlement, anyhow::Error> {
+    match load(client, user_id.clone()).await? {
+        Some(entitlement) => Ok(entitlement),
+        None => create_user(client, user_id).await,
+    }
+}
+
+// None only when the lookup worked and found nothing. A failed lookup is an
+// error, creating the user then would overwrite their plan and counters.
+async fn load(client: &dyn VectorStore, user_id: String) -> Result<Option<UserEntitlement>, anyhow::Error> {
+    let result: ScrollResult<EntitlementPoint> = search(client, user_id, 1, None, client.entitlements())
+        .await
+        .map_err(|err| anyhow::anyhow!("Entitlement lookup failed: {}", err))?;
+    // Extract UserEntitlement from the point's payload
+    Ok(result.points.into_iter().next().map(|point| point.payload))
+}
 
+pub async fn get_or_create_entitlement(client: &dyn VectorStore, locks: &EntitlementLocks, user_id: String) -> Result<UserEntitlement, anyhow::Error> {
+    if let Some(entitlement) = load(client, user_id.clone()).await? {
+        return Ok(entitlement);
+    }
+    // another request may have created it while we waited
+    let _guard = locks.lock(&user_id).await;
+    load_or_create(client, user_id).await
+}
 
-pub async fn create_user(user_id: String) -> Result<UserEntitlement, anyhow::Error> {
+pub async fn can_save_tweet(client: &dyn VectorStore, locks: &EntitlementLocks, tweets: &TenantStore, tenant: &Tenant) -> Result<bool, anyhow::Error> {
+    can_save_tweets(client, locks, tweets, tenant, 1).await
+}
 
+// Whether `new` more tweets still fit in the user's plan
+pub async fn can_save_tweets(
+    client: &dyn VectorStore,
+    locks: &EntitlementLocks,
+    tweets: &TenantStore,
+    tenant: &Tenant,
+    new: u64,
+) -> Result<bool, anyhow::Error> {
+    let result = get_or_create_entitlement(client, locks, tenant.user_id.clone()).await?;
 
+    let saved = tweets.count(tenant).await.map_err(|err| {
+        println!("Error in count(): {err}");
+        anyhow::anyhow!("Search failed: {}", err)
+    })?;
 
-    let api_key = env::var("QDRANT_API_KEY").expect("QDRANT_API_KEY must be set in environment");
-    let endpoint = env::var("QDRANT_ENDPOINT").expect("QDRANT_ENDPOINT must be set in environment");
+    Ok(saved + new <= result.max_tweets as u64)
+}
 
+// pub async fn increment_tweet_count(saved_count: usize, user_id: String ) {
+
+// }
+
+// Starts a fresh day / month of usage counters when the stored period is over
+fn roll_usage_period(entitlement: &mut UserEntitlement) {
     let date = Local::now().date_naive();
-    let payload = EntitlementPoint {
-        id: unique_user_id(&user_id),
-        vector: Some([0.0].to_vec()),
-        payload: UserEntitlement { 
-            user_id: user_id, 
-            plan: "Free".to_string(), 
-            max_tweets: 0, 
-            max_searches_per_day: 20, 
-            tweet_count: 0, 
-            searches_used_today: 0, 
-            last_reset_date: date.format("%F").to_string(), 
-            valid_until: None,
-        },
-    };
-    let arr_payload = {
-        EntitlementRequest {
-            points: vec![payload.clone()],
-            next_page_offset: None,
-        }
-    };
+    let today = date.format("%F").to_string();
+    let month = date.format("%Y-%m").to_string();
+
+    if entitlement.last_reset_date != today {
+        entitlement.last_reset_date = today;
+        entitlement.searches_used_today = 0;
+        entitlement.tokens_used_today = 0;
+    }
+    if entitlement.usage_month != month {
+        entitlement.usage_month = month;
+        entitlement.tokens_used_month = 0;
+    }
+}
 
+// False once the user's plan budget for today or this month is used up
+pub async fn can_spend_tokens(client: &dyn VectorStore, locks: &EntitlementLocks, user_id: String, budgets: &BudgetConfig) -> Result<bool, anyhow::Error> {
+    let mut entitlement = get_or_create_entitlement(client, locks, user_id).await?;
+    roll_usage_period(&mut entitlement);
 
-    let client = reqwest::Client::new();
-    let response = client.put(format!("{}/collections/user_entitlement/points", endpoint))
-        .header("api-key", api_key)
-        .header("Content-Type", "application/json")
-        .json(&arr_payload)
-        .send()
-        .await?;
+    let Some(budget) = budgets.plans.get(&entitlement.plan) else {
+        return Ok(true);
+    };
+    let daily_ok = budget.daily.is_none_or(|limit| entitlement.tokens_used_today < limit);
+    let monthly_ok = budget.monthly.is_none_or(|limit| entitlement.tokens_used_month < limit);
+    Ok(daily_ok && monthly_ok)
+}
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let text = response.text().await.unwrap_or_default();
-        println!("Qdrant upload failed ({}): {}", status, text);
-        anyhow::bail!("Qdrant upload failed ({}): {}", status, text);
+// The user's overrides on top of the deployment defaults
+pub fn effective_match(entitlement: &UserEntitlement, defaults: &MatchConfig) -> MatchConfig {
+    MatchConfig {
+        min_score: entitlement.match_min_score.unwrap_or(defaults.min_score),
+        top_k: entitlement.match_top_k.unwrap_or(defaults.top_k),
     }
+}
 
-    Ok(payload.payload)
+pub async fn match_settings(client: &dyn VectorStore, locks: &EntitlementLocks, user_id: String, defaults: &MatchConfig) -> Result<MatchConfig, anyhow::Error> {
+    let entitlement = get_or_create_entitlement(client, locks, user_id).await?;
+    Ok(effective_match(&entitlement, defaults))
+}
 
+pub async fn save_match_settings(client: &dyn VectorStore, locks: &EntitlementLocks, user_id: String, settings: &MatchSettings) -> Result<UserEntitlement, anyhow::Error> {
+    let _guard = locks.lock(&user_id).await;
+    let mut entitlement = load_or_create(client, user_id).await?;
+    entitlement.match_min_score = settings.min_score;
+    entitlement.match_top_k = settings.top_k;
+    save_entitlement(client, &entitlement).await?;
+    Ok(entitlement)
+}
 
+// Whether the user has searches left today. Nothing is counted, searches
+// are charged with `record_search` once they succeeded, so concurrent
+// requests can overshoot the allowance by the number in flight.
+pub async fn can_search(client: &dyn VectorStore, locks: &EntitlementLocks, user_id: String) -> Result<bool, anyhow::Error> {
+    let mut entitlement = get_or_create_entitlement(client, locks, user_id).await?;
+    roll_usage_period(&mut entitlement);
+    Ok(entitlement.searches_used_today < entitlement.max_searches_per_day)
 }
 
-pub async fn get_or_create_entitlement(user_id: String) -> Result<UserEntitlement, anyhow::Error> {
+// Counts one successful search against the user's daily allowance
+pub async fn record_search(client: &dyn VectorStore, locks: &EntitlementLocks, user_id: String) -> Result<UserEntitlement, anyhow::Error> {
+    let _guard = locks.lock(&user_id).await;
+    let mut entitlement = load_or_create(client, user_id).await?;
+    roll_usage_period(&mut entitlement);
+
+    entitlement.searches_used_today += 1;
+    save_entitlement(client, &entitlement).await?;
+    Ok(entitlement)
+}
 
+pub async fn record_token_usage(client: &dyn VectorStore, locks: &EntitlementLocks, user_id: String, tokens: u64) -> Result<UserEntitlement, anyhow::Error> {
+    let _guard = locks.lock(&user_id).await;
+    let mut entitlement = load_or_create(client, user_id).await?;
+    roll_usage_period(&mut entitlement);
 
-    let collection = "user_entitlement".to_string();
-    let result = match search(user_id.clone(), 1, collection).await {
-        Ok(r) => {
-            let search_result: EntitlementRootSearch = r.json()
-            .await
-            .map_err(|e| {
-                println!("Error parsing JSON: {e}");
-                anyhow::anyhow!("Failed to parse Qdrant response: {}", e)
-            })?;
+    entitlement.tokens_used_today += tokens;
+    entitlement.tokens_used_month += tokens;
+    save_entitlement(client, &entitlement).await?;
 
-            // Extract UserEntitlement from the point's payload, or create if not found
-            if search_result.result.points.len() != 0 {
-                search_result.result.points[0].payload.clone()
-            } else {
-                create_user(user_id).await?
-            }
+    Ok(entitlement)
+}
 
+// Splits the token usage of a mixed batch between its users, proportional
+// to the estimated size of each user's inputs
+pub fn prorate_tokens(user_ids: &[String], texts: &[String], total_tokens: u32) -> HashMap<String, u64> {
+    let mut weights: HashMap<String, u64> = HashMap::new();
+    for (user_id, text) in user_ids.iter().zip(texts.iter()) {
+        *weights.entry(user_id.clone()).or_default() += estimate_tokens(text) as u64;
+    }
+
+    let weight_sum: u64 = weights.values().sum();
+    if weight_sum == 0 {
+        return HashMap::new();
+    }
+
+    weights
+        .into_iter()
+        .map(|(user_id, weight)| (user_id, (total_tokens as u64 * weight).div_ceil(weight_sum)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use async_trait::async_trait;
+    use serde_json::Value as Json;
+
+    use super::*;
+    use crate::{
+        config::{QdrantTransport, TenancyMode},
+        models::{
+            middleware::{FilterType, SearchRequest},
+            qdrant::{ScrollRequest, UpdateResult},
+            similarity_result::ResultItem,
         },
-        Err(err) => {
-            println!("No user found, creating a new entry {}", err);
-            create_user(user_id).await?
-        }
+        qdrant_functions::{client::tests::config, error::QdrantError},
+        vector_store::memory::MemoryStore,
     };
-    
-    Ok(result)
-}
 
-pub async fn can_save_tweet(user_id: String) -> Result<bool, anyhow::Error> {
-    let result = get_or_create_entitlement(user_id.clone()).await?;
+    // Memory store whose reads can be made to fail, like a Qdrant timeout
+    struct Flaky {
+        inner: MemoryStore,
+        failing: AtomicBool,
+    }
 
-    let collection = "tweet_userid".to_string();
-    let resp = match search(user_id, 100, collection).await {
-        Ok(r) => r,
-        Err(err) => {
-            println!("Error in search(): {err}");
-            anyhow::bail!("Search failed: {}", err);
+    #[async_trait]
+    impl VectorStore for Flaky {
+        fn backend(&self) -> &'static str {
+            "flaky"
         }
-    };
 
-    // Convert reqwest error to anyhow error
-    let parsed: RootSearch = resp.json()
-        .await
-        .map_err(|e| {
-            println!("Error parsing JSON: {e}");
-            anyhow::anyhow!("Failed to parse Qdrant response: {}", e)
-        })?;
+        fn tweets(&self) -> &str {
+            self.inner.tweets()
+        }
 
-    
+        fn entitlements(&self) -> &str {
+            self.inner.entitlements()
+        }
 
-    if result.max_tweets <= parsed.result.points.len() as u32 {
-        return Ok(false);
-    } else {
-        return Ok(true);
+        async fn upsert(&self, scope: &Scope, points: &[Json], options: WriteOptions) -> Result<UpdateResult, QdrantError> {
+            self.inner.upsert(scope, points, options).await
+        }
+
+        async fn delete_by_filter(&self, scope: &Scope, filter: &FilterType) -> Result<UpdateResult, QdrantError> {
+            self.inner.delete_by_filter(scope, filter).await
+        }
+
+        async fn scroll(&self, scope: &Scope, request: &ScrollRequest) -> Result<ScrollResult<Json>, QdrantError> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(QdrantError::Unavailable);
+            }
+            self.inner.scroll(scope, request).await
+        }
+
+        async fn count(&self, scope: &Scope, filter: &FilterType) -> Result<u64, QdrantError> {
+            self.inner.count(scope, filter).await
+        }
+
+        async fn query_batch(&self, scope: &Scope, request: &SearchRequest) -> Result<Vec<ResultItem>, QdrantError> {
+            self.inner.query_batch(scope, request).await
+        }
     }
-}
 
-// pub async fn increment_tweet_count(saved_count: usize, user_id: String ) {
+    #[tokio::test]
+    async fn a_failed_lookup_never_resets_the_entitlement() {
+        let store = Flaky {
+            inner: MemoryStore::new(&config(QdrantTransport::Rest, "", "")),
+            failing: AtomicBool::new(false),
+        };
+        let locks = EntitlementLocks::default();
+        let mut pro = create_user(&store, "alice".to_string()).await.unwrap();
+        pro.plan = "Pro".to_string();
+        pro.searches_used_today = 7;
+        save_entitlement(&store, &pro).await.unwrap();
+
+        store.failing.store(true, Ordering::SeqCst);
+        assert!(get_or_create_entitlement(&store, &locks, "alice".to_string()).await.is_err());
+
+        store.failing.store(false, Ordering::SeqCst);
+        let kept = get_or_create_entitlement(&store, &locks, "alice".to_string()).await.unwrap();
+        assert_eq!((kept.plan.as_str(), kept.searches_used_today), ("Pro", 7));
 
-// }
\ No newline at end of file
+        // a user that is really missing is still created
+        let created = get_or_create_entitlement(&store, &locks, "bob".to_string()).await.unwrap();
+        assert_eq!(created.plan, "Free");
+    }
+
+    #[tokio::test]
+    async fn several_tweets_must_fit_in_the_plan_together() {
+        let store: Arc<dyn VectorStore> = Arc::new(MemoryStore::new(&config(QdrantTransport::Rest, "", "")));
+        let tweets = TenantStore::new(store.clone(), TenancyMode::Filter);
+        let tenant = Tenant { user_id: "alice".to_string(), team_id: None };
+        let locks = EntitlementLocks::default();
+        let mut entitlement = create_user(store.as_ref(), "alice".to_string()).await.unwrap();
+        entitlement.max_tweets = 3;
+        save_entitlement(store.as_ref(), &entitlement).await.unwrap();
+        let saved: Vec<Json> = (1..=2u64)
+            .map(|id| serde_json::json!({ "id": id, "vector": [1.0, 0.0], "payload": { "user_id": "alice", "text": "t" } }))
+            .collect();
+        store.upsert(&tweets.scope(&tenant), &saved, WriteOptions::default()).await.unwrap();
+
+        assert!(can_save_tweet(store.as_ref(), &locks, &tweets, &tenant).await.unwrap());
+        assert!(can_save_tweets(store.as_ref(), &locks, &tweets, &tenant, 1).await.unwrap());
+        assert!(!can_save_tweets(store.as_ref(), &locks, &tweets, &tenant, 2).await.unwrap());
+    }
+}
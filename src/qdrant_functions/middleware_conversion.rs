@@ -4,13 +4,16 @@
 
 use std::collections::HashMap;
 
-use crate::models::{
-    internal::TweetPayload,
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::{config::{MatchConfig, Quantization, VectorConfig}, embeddings::sparse, models::{
+    internal::{Tweet, TweetPayload, UserData},
     //middleware::{Condition, Filter, MatchValue, PointSearchVectors, SearchRequest},
-    middleware::{FilterType, KeyValue, Must, PointSearchVectors, SearchRequest},
+    middleware::{FilterType, NamedVector, PointSearchVectors, Prefetch, QuantizationParams, Query, QueryParams, SearchRequest, StoredVector},
+    recommend::RecommendProfile,
     response::EmbeddingResponse,
-    similarity_result::{Root, SimilarityResult},
-};
+    similarity_result::{Point, ResultItem, SemanticHit, SimilarityResult},
+}, qdrant_functions::{error::QdrantError, recommend::profile_query}};
 use uuid::Uuid;
 
 pub fn unique_point_id(user_id: &str, tweet_id: &str) -> String {
@@ -29,55 +32,234 @@ pub fn unique_user_id(user_id: &str) -> String {
 }
 
 
-pub fn into_compatible(payload: &TweetPayload, response: &EmbeddingResponse) -> SearchRequest {
-    let e_limit: u8 = 1;
-    let e_key: String = "user_id".to_string();
-
-    let points: Vec<PointSearchVectors> = payload
-        .tweets
-        .iter()
-        .zip(response.data.iter())
-        .map(|(tweet, embedding)| PointSearchVectors {
-            query: embedding.embedding.clone(),
-            filter: FilterType {
-                must: vec![Must {
-                    key: e_key.clone(),
-                    r#match: KeyValue {
-                        value: tweet.user_id.clone(),
-                    },
-                }],
+// `value` as an RFC 3339 UTC timestamp, None when it is not RFC 3339
+pub fn rfc3339_utc(value: &str) -> Option<String> {
+    let parsed = DateTime::parse_from_rfc3339(value.trim()).ok()?;
+    Some(parsed.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+// Payload of a saved tweet. `saved_at` is shared by the whole request.
+pub fn tweet_payload(user_id: &str, tweet: &Tweet, saved_at: &str) -> UserData {
+    let username = Some(tweet.username.trim().trim_start_matches('@').to_string()).filter(|name| !name.is_empty());
+    let url = tweet.url.clone().or_else(|| match (&username, &tweet.id) {
+        (Some(username), Some(id)) => Some(format!("https://x.com/{}/status/{}", username, id)),
+        _ => None,
+    });
+    UserData {
+        user_id: user_id.to_string(),
+        text: tweet.text.clone(),
+        username,
+        tweet_id: tweet.id.clone(),
+        saved_at: Some(saved_at.to_string()),
+        // unparseable times are dropped rather than breaking date filters
+        posted_at: tweet.created_at.as_deref().and_then(rfc3339_utc),
+        language: tweet.lang.clone().filter(|lang| !lang.is_empty()),
+        url,
+    }
+}
+
+// Rescoring / oversampling for quantized collections, None keeps Qdrant's defaults
+pub fn query_params(vectors: &VectorConfig) -> Option<QueryParams> {
+    if vectors.quantization == Quantization::None {
+        return None;
+    }
+    Some(QueryParams {
+        quantization: QuantizationParams {
+            rescore: vectors.rescore,
+            oversampling: vectors.oversampling,
+        },
+    })
+}
+
+// Vector(s) saved for a tweet, adds the sparse term vector in hybrid mode
+pub fn stored_vector(dense: Vec<f32>, text: &str, vectors: &VectorConfig) -> StoredVector {
+    if !vectors.hybrid {
+        return StoredVector::Dense(dense);
+    }
+    let mut named = HashMap::new();
+    named.insert(String::new(), NamedVector::Dense(dense));
+    named.insert(
+        vectors.sparse_vector_name.clone(),
+        NamedVector::Sparse(sparse::document_vector(text)),
+    );
+    StoredVector::Named(named)
+}
+
+// Cosine query on the dense vector only
+fn dense_query(dense: Vec<f32>, filter: FilterType, limit: u8, min_score: Option<f32>, vectors: &VectorConfig) -> PointSearchVectors {
+    PointSearchVectors {
+        prefetch: None,
+        query: Query::Dense(dense),
+        using: None,
+        filter,
+        with_payload: true,
+        limit,
+        params: query_params(vectors),
+        score_threshold: min_score,
+        shard_key: None,
+    }
+}
+
+// Candidates fetched from each of the dense / sparse searches before fusion
+fn prefetch_limit(limit: u8, vectors: &VectorConfig) -> u32 {
+    vectors.hybrid_prefetch.max(limit as u32)
+}
+
+// One query against a user's saved tweets. Dense only by default, in hybrid
+// mode the dense and sparse candidates are fused with reciprocal rank fusion.
+// `min_score` is a cosine similarity, so it only applies to the dense
+// candidates, never to the fused RRF scores.
+pub fn build_query(
+    dense: Vec<f32>,
+    text: &str,
+    filter: FilterType,
+    limit: u8,
+    min_score: Option<f32>,
+    vectors: &VectorConfig,
+) -> PointSearchVectors {
+    if !vectors.hybrid {
+        return dense_query(dense, filter, limit, min_score, vectors);
+    }
+
+    let params = query_params(vectors);
+    let prefetch_limit = prefetch_limit(limit, vectors);
+    PointSearchVectors {
+        prefetch: Some(vec![
+            Prefetch {
+                query: Query::Dense(dense),
+                using: None,
+                filter: filter.clone(),
+                limit: prefetch_limit,
+                params,
+                score_threshold: min_score,
             },
-            with_payload: true,
-            limit: e_limit
-        })
-        .collect();
+            Prefetch {
+                query: Query::Sparse(sparse::query_vector(text)),
+                using: Some(vectors.sparse_vector_name.clone()),
+                filter: filter.clone(),
+                limit: prefetch_limit,
+                params: None,
+                score_threshold: None,
+            },
+        ]),
+        query: Query::Fusion { fusion: "rrf".to_string() },
+        using: None,
+        filter,
+        with_payload: true,
+        limit,
+        params: None,
+        score_threshold: None,
+        shard_key: None,
+    }
+}
 
-    let result: SearchRequest = SearchRequest { searches: points };
+// Match settings of every user of a batch, users missing from the map get
+// `defaults`. Users with a recommend profile are scored through it.
+pub struct BatchMatching<'a> {
+    pub users: &'a HashMap<String, MatchConfig>,
+    pub defaults: &'a MatchConfig,
+    pub profiles: &'a HashMap<String, RecommendProfile>,
+}
 
-    result
+impl BatchMatching<'_> {
+    fn of(&self, user_id: &str) -> &MatchConfig {
+        self.users.get(user_id).unwrap_or(self.defaults)
+    }
 }
 
+// Searches of a batch of feed tweets. In hybrid mode a feed tweet gets a
+// second, dense only search under the user's threshold: its fused hits only
+// count as matches when they are among these cosine matches.
+pub struct MatchQueries {
+    pub request: SearchRequest,
+    // index of the feed tweet every search belongs to, in search order
+    pub tweets: Vec<usize>,
+}
 
+pub fn into_compatible(
+    payload: &TweetPayload,
+    response: &EmbeddingResponse,
+    vectors: &VectorConfig,
+    matching: &BatchMatching,
+) -> MatchQueries {
+    let mut searches = Vec::new();
+    let mut tweets = Vec::new();
+    for (index, (tweet, embedding)) in payload.tweets.iter().zip(response.data.iter()).enumerate() {
+        // the vector store layer scopes every search to the tweet's user
+        let settings = matching.of(&tweet.user_id);
+        if let Some(profile) = matching.profiles.get(&tweet.user_id) {
+            searches.push(profile_query(profile, embedding.embedding.clone(), settings.top_k, settings.min_score, vectors));
+            tweets.push(index);
+            continue;
+        }
+        searches.push(build_query(
+            embedding.embedding.clone(),
+            &tweet.text,
+            FilterType::default(),
+            settings.top_k,
+            Some(settings.min_score),
+            vectors,
+        ));
+        tweets.push(index);
+        if vectors.hybrid {
+            // same candidates as the fused query's dense prefetch
+            let limit = prefetch_limit(settings.top_k, vectors).min(u8::MAX as u32) as u8;
+            searches.push(dense_query(embedding.embedding.clone(), FilterType::default(), limit, Some(settings.min_score), vectors));
+            tweets.push(index);
+        }
+    }
+
+    MatchQueries { request: SearchRequest { searches }, tweets }
+}
+
+fn hit(point: &Point, score: f32) -> SemanticHit {
+    SemanticHit { id: point.id.clone(), score, text: point.payload.text.clone() }
+}
+
+
+// `searches` is `MatchQueries::tweets` of the batch the results are for
 pub fn hashmap_score_user(
-    payload: Result<Root, anyhow::Error>,
+    payload: Result<Vec<ResultItem>, QdrantError>,
+    searches: &[usize],
     user_payload: TweetPayload,
-) -> Result<HashMap<String, Vec<SimilarityResult>>, anyhow::Error> {
+) -> Result<HashMap<String, Vec<SimilarityResult>>, QdrantError> {
     match payload {
         Ok(payload) => {
+            let mut per_tweet: Vec<Vec<&ResultItem>> = vec![Vec::new(); user_payload.tweets.len()];
+            for (result, &tweet) in payload.iter().zip(searches) {
+                per_tweet[tweet].push(result);
+            }
+
             let mut hash_score: HashMap<String, Vec<SimilarityResult>> = HashMap::new();
-            payload
-                .result
+            user_payload
+                .tweets
                 .iter()
-                .zip(user_payload.tweets.iter())
-                .for_each(|(result, tweet)| {
-                    if let Some(first_point) = result.points.first() {
+                .zip(per_tweet)
+                .for_each(|(tweet, results)| {
+                    // points come best first, feed tweets without a match are not delivered
+                    let matches: Vec<SemanticHit> = match results[..] {
+                        [ranked] => ranked.points.iter().map(|point| hit(point, point.score)).collect(),
+                        // hybrid: keep the fused order, but only hits above the
+                        // user's threshold, and report their cosine score
+                        [fused, dense] => fused
+                            .points
+                            .iter()
+                            .filter_map(|point| {
+                                let cosine = dense.points.iter().find(|candidate| candidate.id == point.id)?;
+                                Some(hit(point, cosine.score))
+                            })
+                            .collect(),
+                        _ => Vec::new(),
+                    };
+                    if let Some(best) = matches.first() {
                         hash_score
                             .entry(tweet.user_id.clone())
-                            .or_insert_with(Vec::new)
+                            .or_default()
                             .push(SimilarityResult {
                                 id: tweet.id.clone().unwrap_or("Default_ID_Value".into()),
                                 text: tweet.text.clone(),
-                                score: first_point.score,
+                                score: best.score,
+                                matches,
                             });
                     }
                 });
@@ -86,3 +268,124 @@ pub fn hashmap_score_user(
         Err(e) => Err(e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        config::MatchConfig,
+        models::{
+            middleware::RecommendStrategy,
+            response::{EmbeddingData, Tokens},
+        },
+        vector_store::{
+            local::{run_query, LocalPoint},
+            memory::MemoryCollection,
+        },
+    };
+
+    fn hybrid() -> VectorConfig {
+        VectorConfig {
+            truncate_dimensions: None,
+            quantization: Quantization::None,
+            always_ram: false,
+            rescore: false,
+            oversampling: None,
+            hybrid: true,
+            sparse_vector_name: "bm25".to_string(),
+            hybrid_prefetch: 20,
+        }
+    }
+
+    fn saved(id: u64, dense: Vec<f32>, text: &str, vectors: &VectorConfig) -> LocalPoint {
+        LocalPoint::from_json(&json!({
+            "id": id,
+            "vector": stored_vector(dense, text, vectors),
+            "payload": { "user_id": "alice", "text": text }
+        }))
+        .unwrap()
+    }
+
+    fn feed(text: &str) -> Tweet {
+        Tweet {
+            user_id: "alice".to_string(),
+            id: Some("feed-1".to_string()),
+            text: text.to_string(),
+            username: "bob".to_string(),
+            created_at: None,
+            lang: None,
+            url: None,
+            team_id: None,
+        }
+    }
+
+    fn collection(vectors: &VectorConfig) -> MemoryCollection {
+        let mut collection = MemoryCollection::default();
+        collection
+            .upsert(vec![
+                saved(1, vec![1.0, 0.0], "borrow checker tips", vectors),
+                // shares the keyword, but means something else
+                saved(2, vec![0.0, 1.0], "rust on my old bike", vectors),
+            ])
+            .unwrap();
+        collection
+    }
+
+    fn embedded(vector: Vec<f32>) -> EmbeddingResponse {
+        EmbeddingResponse {
+            object: String::new(),
+            data: vec![EmbeddingData { object: String::new(), embedding: vector, index: 0 }],
+            model: "test".to_string(),
+            usage: Tokens::default(),
+        }
+    }
+
+    #[test]
+    fn hybrid_matches_need_a_dense_score_above_the_threshold() {
+        let vectors = hybrid();
+        let collection = collection(&vectors);
+        let payload = TweetPayload { tweets: vec![feed("rust")] };
+        let response = embedded(vec![1.0, 0.1]);
+        let defaults = MatchConfig { min_score: 0.5, top_k: 3 };
+        let matching = BatchMatching { users: &HashMap::new(), defaults: &defaults, profiles: &HashMap::new() };
+
+        let queries = into_compatible(&payload, &response, &vectors, &matching);
+        assert_eq!(queries.tweets, vec![0, 0]);
+        let results: Result<Vec<ResultItem>, QdrantError> =
+            queries.request.searches.iter().map(|search| run_query(&collection, search)).collect();
+        // the fused query alone also returns the keyword only hit
+        let fused: Vec<&str> = results.as_ref().unwrap()[0].points.iter().map(|p| p.id.as_str()).collect();
+        assert_eq!(fused, vec!["1", "2"]);
+
+        let delivered = hashmap_score_user(results, &queries.tweets, payload).unwrap();
+        let matches = &delivered["alice"][0].matches;
+        assert_eq!(matches.iter().map(|hit| hit.id.as_str()).collect::<Vec<_>>(), vec!["1"]);
+        // the cosine score, not the RRF one
+        let cosine = 1.0 / 1.01f32.sqrt();
+        assert!((matches[0].score - cosine).abs() < 1e-6);
+        assert!((delivered["alice"][0].score - cosine).abs() < 1e-6);
+    }
+
+    #[test]
+    fn profile_matches_need_a_score_above_the_threshold() {
+        let vectors = hybrid();
+        let collection = collection(&vectors);
+        let payload = TweetPayload { tweets: vec![feed("rust")] };
+        let response = embedded(vec![1.0, 0.1]);
+        let defaults = MatchConfig { min_score: 0.5, top_k: 3 };
+        let profile = RecommendProfile { positive_ids: Vec::new(), negative: Vec::new(), strategy: RecommendStrategy::AverageVector };
+        let profiles = HashMap::from([("alice".to_string(), profile)]);
+        let matching = BatchMatching { users: &HashMap::new(), defaults: &defaults, profiles: &profiles };
+
+        let queries = into_compatible(&payload, &response, &vectors, &matching);
+        // profile queries are dense only, there is no second search
+        assert_eq!(queries.tweets, vec![0]);
+        let results = queries.request.searches.iter().map(|search| run_query(&collection, search)).collect();
+
+        let delivered = hashmap_score_user(results, &queries.tweets, payload).unwrap();
+        let matches = &delivered["alice"][0].matches;
+        assert_eq!(matches.iter().map(|hit| hit.id.as_str()).collect::<Vec<_>>(), vec!["1"]);
+    }
+}
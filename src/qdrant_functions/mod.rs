@@ -1,4 +1,9 @@
 pub mod search;
 pub mod store;
+pub mod backup;
+pub mod recommend;
 pub mod middleware_conversion;
-pub mod limits;
\ No newline at end of file
+pub mod limits;
+pub mod collections;
+pub mod client;
+pub mod error;pub mod grpc;
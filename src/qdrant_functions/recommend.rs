@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use crate::{
+    config::VectorConfig,
+    models::{
+        middleware::{Condition, FilterType, PointSearchVectors, Query, RecommendInput, SearchRequest},
+        recommend::RecommendProfile,
+        similarity_result::SemanticHit,
+    },
+    qdrant_functions::{backup::archived_point, error::QdrantError, middleware_conversion::query_params},
+    vector_store::tenancy::{Tenant, TenantStore},
+};
+
+// Dense vectors of the tenant's saved tweets among `ids`. Ids saved by
+// someone else, or by nobody, are missing from the map.
+pub async fn saved_vectors(tweets: &TenantStore, tenant: &Tenant, ids: &[String]) -> Result<HashMap<String, Vec<f32>>, QdrantError> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let points = tweets.retrieve(tenant, ids, true).await?;
+    Ok(points
+        .into_iter()
+        .map(archived_point)
+        .filter_map(|point| point.vector.map(|vector| (point.id, vector)))
+        .collect())
+}
+
+// Recommend queries always run on the dense vector, also in hybrid mode
+fn recommend_query(
+    input: RecommendInput,
+    filter: FilterType,
+    limit: u8,
+    score_threshold: Option<f32>,
+    vectors: &VectorConfig,
+) -> PointSearchVectors {
+    PointSearchVectors {
+        prefetch: None,
+        query: Query::Recommend { recommend: input },
+        using: None,
+        filter,
+        with_payload: true,
+        limit,
+        params: query_params(vectors),
+        score_threshold,
+        shard_key: None,
+    }
+}
+
+// The tenant's saved tweets ranked by `input`, best first. The example
+// tweets in `exclude` are left out of the results.
+pub async fn recommend(
+    tweets: &TenantStore,
+    tenant: &Tenant,
+    input: RecommendInput,
+    filter: FilterType,
+    exclude: &[String],
+    top_k: u8,
+    vectors: &VectorConfig,
+) -> Result<Vec<SemanticHit>, QdrantError> {
+    let limit = top_k.saturating_add(exclude.len().min(u8::MAX as usize) as u8);
+    let search = recommend_query(input, filter, limit, None, vectors);
+    let mut results = tweets.query_batch(std::slice::from_ref(tenant), SearchRequest { searches: vec![search] }).await?;
+
+    let points = results.pop().map(|result| result.points).unwrap_or_default();
+    Ok(points
+        .into_iter()
+        .filter(|point| !exclude.contains(&point.id))
+        .take(top_k as usize)
+        .map(|point| SemanticHit { id: point.id, score: point.score, text: point.payload.text })
+        .collect())
+}
+
+// Query of one feed tweet of a session with a recommend profile. Feed tweets
+// are not stored, so the feed tweet is the positive example and the profile's
+// positive saved tweets are the candidates it is scored against. Both
+// strategies score by cosine, so the user's `min_score` applies as it is.
+pub fn profile_query(profile: &RecommendProfile, feed: Vec<f32>, top_k: u8, min_score: f32, vectors: &VectorConfig) -> PointSearchVectors {
+    let filter = if profile.positive_ids.is_empty() {
+        FilterType::default()
+    } else {
+        FilterType { must: vec![Condition::HasId { has_id: profile.positive_ids.clone() }] }
+    };
+    let input = RecommendInput {
+        positive: vec![feed],
+        negative: profile.negative.clone(),
+        strategy: profile.strategy,
+    };
+    recommend_query(input, filter, top_k, Some(min_score), vectors)
+}
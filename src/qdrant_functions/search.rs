@@ -1,79 +1,125 @@
-use anyhow::Ok;
-
-use crate::models::{middleware::SearchRequest, similarity_result::{Root}};
-use std::env;
-
-pub async fn similarity(payload: SearchRequest) -> Result<Root, anyhow::Error> {
-    let api_key = env::var("QDRANT_API_KEY").expect("QDRANT_API_KEY must be set in environment");
-    let endpoint = env::var("QDRANT_ENDPOINT").expect("QDRANT_ENDPOINT must be set in environment");
-
-    let client = reqwest::Client::new();
-    let response = client.post(format!("{}/collections/tweet_userid/points/query/batch", endpoint))
-        .header("api-key", api_key)
-        .header("Content-Type", "application/json")
-        .json(&payload)
-        .send()
-        .await?;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    config::VectorConfig,
+    models::{
+        middleware::{Condition, DateRange, FilterType, KeyValue, Must, SearchRequest},
+        qdrant::{PointId, ScrollRequest, ScrollResult},
+        similarity_result::{PayloadFilter, ResultItem, SemanticHit},
+    },
+    qdrant_functions::{error::QdrantError, middleware_conversion::{build_query, rfc3339_utc}},
+    vector_store::{from_scroll, tenancy::{Tenant, TenantStore}, Scope, VectorStore},
+};
+
+fn keyword(key: &str, value: &str) -> Condition {
+    Condition::Field(Must {
+        key: key.to_string(),
+        r#match: KeyValue {
+            value: value.to_string(),
+        },
+    })
+}
 
-    println!("Response status: {}", response.status());
+// Only points saved by `user_id`
+pub fn user_filter(user_id: &str) -> FilterType {
+    FilterType {
+        must: vec![keyword("user_id", user_id)],
+    }
+}
 
-    if !response.status().is_success() {
-        // Print the body if something goes wrong
-        let status = response.status();
-        let text = response.text().await.unwrap_or_default();
-        anyhow::bail!("Qdrant search failed ({}): {}", status, text);
+// `filter` as payload conditions, Err says which date is not RFC 3339
+pub fn payload_filter(filter: &PayloadFilter) -> Result<FilterType, String> {
+    let mut must = Vec::new();
+    let author = filter.author.as_deref().map(|author| author.trim().trim_start_matches('@'));
+    if let Some(author) = author.filter(|author| !author.is_empty()) {
+        must.push(keyword("username", author));
+    }
+    if let Some(language) = filter.language.as_deref().map(str::trim).filter(|language| !language.is_empty()) {
+        must.push(keyword("language", language));
     }
-    let response: Root = response.json().await?;
 
-    println!("Search successful ! {}", response.result.len());
-    Ok(response)
+    let dates = [
+        ("posted_at", ("posted_after", &filter.posted_after), ("posted_before", &filter.posted_before)),
+        ("saved_at", ("saved_after", &filter.saved_after), ("saved_before", &filter.saved_before)),
+    ];
+    for (key, after, before) in dates {
+        let range = DateRange { gte: date(after)?, lte: date(before)? };
+        if range.gte.is_some() || range.lte.is_some() {
+            must.push(Condition::Range { key: key.to_string(), range });
+        }
+    }
+    Ok(FilterType { must })
 }
 
+fn date((name, value): (&str, &Option<String>)) -> Result<Option<String>, String> {
+    match value.as_deref() {
+        None | Some("") => Ok(None),
+        Some(value) => rfc3339_utc(value)
+            .map(Some)
+            .ok_or_else(|| format!("{} is not an RFC 3339 date: {}", name, value)),
+    }
+}
 
-pub async fn search(user_id: String, limit: u32, collection: String) -> Result<reqwest::Response, anyhow::Error> {
+// `tenants[i]` is the owner of `payload.searches[i]`
+pub async fn similarity(tweets: &TenantStore, tenants: &[Tenant], payload: SearchRequest) -> Result<Vec<ResultItem>, QdrantError> {
+    let response = tweets.query_batch(tenants, payload).await?;
 
+    println!("Search successful ! {}", response.len());
+    Ok(response)
+}
 
-    let payload = serde_json::json!({
-        "filter":{
-                    "must": [
-                    {
-                        "key": "user_id",
-                        "match": {
-                        "value": user_id
-                        }
-                    }
-                    ]
-                },
-                "limit": limit,
-                "with_payload": true
-    });
+// The tenant's saved tweets closest in meaning to `query`, best first
+pub async fn semantic(
+    tweets: &TenantStore,
+    tenant: &Tenant,
+    dense: Vec<f32>,
+    query: &str,
+    filter: FilterType,
+    top_k: u8,
+    vectors: &VectorConfig,
+) -> Result<Vec<SemanticHit>, QdrantError> {
+    let search = build_query(dense, query, filter, top_k, None, vectors);
+    let mut results = tweets.query_batch(std::slice::from_ref(tenant), SearchRequest { searches: vec![search] }).await?;
+
+    let points = results.pop().map(|result| result.points).unwrap_or_default();
+    Ok(points
+        .into_iter()
+        .map(|point| SemanticHit { id: point.id, score: point.score, text: point.payload.text })
+        .collect())
+}
 
+// One page of a user's points in ascending point id order, which stays
+// stable while pages are fetched. `offset` is the `next_page_offset` of the
+// previous page.
+pub async fn search<P: DeserializeOwned>(
+    client: &dyn VectorStore,
+    user_id: String,
+    limit: u32,
+    offset: Option<PointId>,
+    collection: &str,
+) -> Result<ScrollResult<P>, QdrantError> {
     println!("Sending the search request");
 
-    let api_key = env::var("QDRANT_API_KEY").expect("QDRANT_API_KEY must be set in environment");
-    let endpoint = env::var("QDRANT_ENDPOINT").expect("QDRANT_ENDPOINT must be set in environment");
-
-    let client = reqwest::Client::new();
-    let response = client.post(format!("{}/collections/{}/points/scroll", endpoint, collection))
-        .header("api-key", api_key)
-        .header("Content-Type", "application/json")
-        .json(&payload)
-        .send()
-        .await?;
-
-    println!("Response status: {}", response.status());
-
-    // if !response.status().is_success() {
-    //     // Print the body if something goes wrong
-    //     let status = response.status();
-    //     let text = response.text().await.unwrap_or_default();
-    //     anyhow::bail!("Qdrant search failed ({}): {}", status, text);
-    // }
-
-    // let response: RootSearch = response.json().await?;
-
-    //println!("Search successful: {}", response.result.len());
+    let request = ScrollRequest {
+        filter: user_filter(&user_id),
+        limit,
+        with_payload: true,
+        with_vector: false,
+        offset,
+        shard_key: None,
+    };
+    from_scroll(client.scroll(&Scope::collection(collection), &request).await?)
+}
 
-    Ok(response)
+// Opaque page cursor handed to clients, wraps Qdrant's scroll offset
+pub fn encode_cursor(offset: &PointId) -> String {
+    let json = serde_json::to_vec(offset).unwrap_or_default();
+    URL_SAFE_NO_PAD.encode(json)
 }
 
+// None for anything that was not produced by `encode_cursor`
+pub fn decode_cursor(cursor: &str) -> Option<PointId> {
+    let json = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    serde_json::from_slice(&json).ok()
+}
@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 pub mod routes;
 pub mod sockets;
\ No newline at end of file
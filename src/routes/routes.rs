@@ -1,12 +1,17 @@
 use crate::{
-    embeddings::embed,
-    models::{internal::{AppState, PointVector, QdrantReqeust, TweetPayload, UserData}, similarity_result::{RootSearch, SearchParams}},
-    qdrant_functions::{limits::{can_save_tweet}, middleware_conversion::{unique_custom_id, unique_point_id}, search::search, store::{delete_all, delete_pointid, upsert}},
+    models::{backup::{Archive, BackupParams}, limits::{MatchSettings, UserEntitlement}, middleware::{RecommendInput, RecommendStrategy}, recommend::{Examples, RecommendParams}, internal::{AppState, PointVector, QdrantReqeust, Tweet, TweetPayload}, qdrant::UpsertReport, similarity_result::{RootSearch, SearchParams, SemanticSearchParams}},
+    qdrant_functions::{backup::{export, restored_points, reusable_vectors, texts, validate, ArchiveProblem}, limits::{can_save_tweet, can_save_tweets, can_search, can_spend_tokens, effective_match, get_or_create_entitlement, record_search, record_token_usage, save_match_settings}, middleware_conversion::{stored_vector, tweet_payload, unique_custom_id, unique_point_id}, recommend::{recommend, saved_vectors}, search::{decode_cursor, encode_cursor, payload_filter, semantic}, store::{delete_all, delete_pointid, upsert}},
+    vector_store::tenancy::Tenant,
 };
 
 use crate::auth::extractor::AuthUser;
-use actix_web::{error::ErrorInternalServerError};
+use crate::config::MAX_MATCH_TOP_K;
+use crate::embeddings::EmbeddingError;
+use crate::qdrant_functions::error::QdrantError;
+use crate::save_queue::QueuedSave;
+use actix_web::{error::{ErrorBadRequest, ErrorInternalServerError}};
 use actix_web::{get, post, web, Error, HttpResponse, Responder};
+use futures_util::StreamExt as _;
 use std::time::Instant;
 
 #[get("/health")]
@@ -17,11 +22,22 @@ pub async fn health() -> impl Responder {
 #[post("/embed")]
 async fn handle_embed(
     payload: web::Json<TweetPayload>,
+    user: AuthUser,
     data: web::Data<AppState>,
 ) -> impl Responder {
+    // nothing could score the tweets and no session would hear about it, let the extension retry
+    if !data.breakers.accepting() {
+        return HttpResponse::ServiceUnavailable().json(error_json("Scoring is unavailable, try again later"));
+    }
+    // tweets are scored for the signed-in user whatever the body claims, like over /ws
+    let tweets = payload.tweets.iter().map(|tweet| Tweet {
+        user_id: user.user_id.clone(),
+        team_id: user.team_id.clone(),
+        ..tweet.clone()
+    });
     {
         let mut buffer = data.buffer.lock().await;
-        buffer.tweets.extend(payload.tweets.clone());
+        buffer.tweets.extend(tweets);
     }
     // for tweet in &payload.tweets {
     //     println!("ID: {}, Text: {}", tweet.id, tweet.text);
@@ -34,40 +50,50 @@ async fn handle_embed(
     HttpResponse::Ok().json({
         serde_json::json!({
             "status": "success",
-            "received": payload.tweets.len()
+            "received": payload.tweets.len(),
+            "scoring": "available"
         })
     })
 }
 
-#[post("/save")]
-async fn handle_save(
-    payload: web::Json<TweetPayload>, user: AuthUser
-) -> Result<HttpResponse, Box<dyn std::error::Error>> {
-    let start_time = Instant::now();
+// Result of the save pipeline, shared by /save and the save queue
+pub enum SaveOutcome {
+    Saved { report: UpsertReport, total: usize },
+    LimitExceeded,
+    BudgetExceeded,
+}
 
-    let can_save = can_save_tweet(user.user_id.clone())
-        .await
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+// Embeds and stores `payload` for `user`, after the save limit and token budget checks
+pub async fn save_tweets(
+    data: &AppState, user: &AuthUser, payload: &TweetPayload
+) -> Result<SaveOutcome, Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+    let tenant = Tenant::from(user);
 
+    let can_save = can_save_tweet(data.store.as_ref(), &data.entitlement_locks, &data.tweets, &tenant).await?;
     if !can_save {
-        return Ok(HttpResponse::TooManyRequests().json(
-            serde_json::json!({
-                "status": "error",
-                "message": "Tweet save limit exceeded"
-            })
-        ));
+        return Ok(SaveOutcome::LimitExceeded);
     }
 
+    let can_spend = can_spend_tokens(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone(), &data.config.budgets).await?;
+    if !can_spend {
+        return Ok(SaveOutcome::BudgetExceeded);
+    }
 
-    let embedded = embed(&payload).await?;
+    let embedded = data.embedder.embed(&data.normalizer.texts(payload)).await?;
     println!("Token Usage : {}", embedded.usage.total_tokens);
 
+    if let Err(e) = record_token_usage(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone(), embedded.usage.total_tokens as u64).await {
+        eprintln!("Recording token usage failed for {}: {}", user.user_id, e);
+    }
+
     if payload.tweets.len() != embedded.data.len() {
         return Err("Mismatch between tweets and embeddings".into());
     }
 
     // convert the embedded into QdrantRequest struct and then pass it to upsert function in store.rs
 
+    let saved_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
     let points: Vec<PointVector> = payload
         .tweets
         .iter()
@@ -77,29 +103,25 @@ async fn handle_save(
                 // Case 1: It's a Tweet -> Use UserID + TweetID
                 Some(tid) => unique_point_id(&user.user_id, tid),
                 // Case 2: It's Custom Text -> Use UserID + Text Content
-                None => unique_custom_id(&tweet.user_id, &tweet.text),
+                None => unique_custom_id(&user.user_id, &tweet.text),
             };
             // let uuid = format!("{}{}", tweet.user_id, tweet.id.clone());
             PointVector {
                 id: Some(final_id),
-                vector: embedding.embedding.clone(),
-                payload: UserData {
-                    user_id: user.user_id.clone(),
-                    text: tweet.text.clone(),
-                },
+                vector: stored_vector(embedding.embedding.clone(), &tweet.text, &data.config.vectors),
+                payload: tweet_payload(&user.user_id, tweet, &saved_at),
             }
         })
         .collect();
 
-    let processed_payload: QdrantReqeust = QdrantReqeust { points: points };
+    let processed_payload: QdrantReqeust = QdrantReqeust { points };
     let processed_len = processed_payload.points.len();
 
     //println!("{:?}", processed_payload);
 
-    let debug = upsert(processed_payload).await?;
+    let report = upsert(&data.tweets, &tenant, processed_payload, &data.config.upsert).await?;
 
-    println!("Debug : {:#?}", debug);
-    println!("Vectors saved to db: {}", processed_len);
+    println!("Vectors saved to db: {}/{}", processed_len - report.failed.len(), processed_len);
 
     // increment_tweet_count(processed_len, user.user_id.clone()).await;
 
@@ -107,33 +129,184 @@ async fn handle_save(
     let elapsed_time = start_time.elapsed();
     println!("Time taken to save to DB : {}", elapsed_time.as_millis());
 
-    Ok(HttpResponse::Ok().json({
-        serde_json::json!({
-            "status": "success",
-            "saved to database": payload.tweets.len()
-        })
+    Ok(SaveOutcome::Saved { report, total: processed_len })
+}
+
+#[post("/save")]
+async fn handle_save(
+    payload: web::Json<TweetPayload>, user: AuthUser, data: web::Data<AppState>
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    // don't even try while a dependency is known to be down
+    if !data.breakers.accepting() {
+        return Ok(queue_save(&data, user, payload.into_inner()));
+    }
+
+    match save_tweets(&data, &user, &payload).await {
+        // points are upserted by id, saving the whole payload again is safe
+        Ok(SaveOutcome::Saved { report, .. }) if !report.failed.is_empty() && data.breakers.degraded() => {
+            Ok(queue_save(&data, user, payload.into_inner()))
+        }
+        Ok(SaveOutcome::Saved { report, total }) => Ok(upsert_response(report, total)),
+        Ok(SaveOutcome::LimitExceeded) => Ok(HttpResponse::TooManyRequests().json(error_json("Tweet save limit exceeded"))),
+        Ok(SaveOutcome::BudgetExceeded) => Ok(HttpResponse::TooManyRequests().json(error_json("Token budget exceeded"))),
+        Err(e) if data.breakers.degraded() || is_unavailable(e.as_ref()) => {
+            eprintln!("Save failed, queueing: {}", e);
+            Ok(queue_save(&data, user, payload.into_inner()))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Whether `e` means a dependency is down rather than a bad request
+pub fn is_unavailable(e: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(e) = e.downcast_ref::<QdrantError>() {
+        return e.is_unavailable();
+    }
+    if let Some(e) = e.downcast_ref::<EmbeddingError>() {
+        return e.is_retryable() || matches!(e, EmbeddingError::Unavailable);
+    }
+    false
+}
+
+fn queue_save(data: &AppState, user: AuthUser, payload: TweetPayload) -> HttpResponse {
+    let received = payload.tweets.len();
+    if !data.save_queue.push(QueuedSave { user, payload }) {
+        return HttpResponse::ServiceUnavailable().json(error_json("Saving is unavailable, try again later"));
+    }
+    HttpResponse::Accepted().json(serde_json::json!({
+        "status": "queued",
+        "received": received,
+        "message": "Saving is delayed, the tweets will be saved once the service recovers"
     }))
 }
 
+// The ids tell the client exactly what was saved after a partial failure
+fn upsert_response(report: UpsertReport, total: usize) -> HttpResponse {
+    let (mut response, status) = if report.failed.is_empty() {
+        (HttpResponse::Ok(), "success")
+    } else if report.failed.len() == total {
+        (HttpResponse::InternalServerError(), "error")
+    } else {
+        (HttpResponse::MultiStatus(), "partial")
+    };
+    response.json(serde_json::json!({
+        "status": status,
+        "saved to database": total - report.failed.len(),
+        "stored": report.stored,
+        "acknowledged": report.acknowledged,
+        "failed": report.failed
+    }))
+}
+
+fn error_json(message: impl std::fmt::Display) -> serde_json::Value {
+    serde_json::json!({
+        "status": "error",
+        "message": message.to_string()
+    })
+}
+
+#[get("/backup")]
+async fn backup(params: web::Query<BackupParams>, user: AuthUser, data: web::Data<AppState>) -> Result<impl Responder, Error> {
+    let archive = export(&data.tweets, &Tenant::from(&user), data.embedder.model(), params.vectors)
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    println!("Backup of {} points for {}", archive.points.len(), user.user_id);
+    let filename = format!("tweets-backup-{}.json", chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+    Ok(HttpResponse::Ok()
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .json(archive))
+}
+
+// Largest archive /restore accepts
+const MAX_ARCHIVE_BYTES: usize = 64 * 1024 * 1024;
+
+// Read by hand, the default JSON extractor stops at 32 KiB
+async fn read_archive(mut body: web::Payload) -> Result<Archive, String> {
+    let mut bytes = web::BytesMut::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        if bytes.len() + chunk.len() > MAX_ARCHIVE_BYTES {
+            return Err(format!("Archive larger than {} bytes", MAX_ARCHIVE_BYTES));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    serde_json::from_slice(&bytes).map_err(|e| format!("Invalid archive: {}", e))
+}
+
+#[post("/restore")]
+async fn restore(
+    body: web::Payload, user: AuthUser, data: web::Data<AppState>
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    let archive = match read_archive(body).await {
+        Ok(archive) => archive,
+        Err(message) => return Ok(HttpResponse::BadRequest().json(error_json(message))),
+    };
+    match validate(&archive, &user.user_id) {
+        Ok(()) => {}
+        Err(ArchiveProblem::NotOwned(message)) => return Ok(HttpResponse::Forbidden().json(error_json(message))),
+        Err(ArchiveProblem::Invalid(message)) => return Ok(HttpResponse::BadRequest().json(error_json(message))),
+    }
+    let total = archive.points.len();
+    if total == 0 {
+        return Ok(upsert_response(UpsertReport::default(), 0));
+    }
+
+    // ids are taken from the archive, never let them land on another user's points
+    let tenant = Tenant::from(&user);
+    let ids: Vec<String> = archive.points.iter().map(|point| point.id.clone()).collect();
+    let foreign = data.tweets.foreign_ids(&tenant, &ids).await?;
+    if !foreign.is_empty() {
+        return Ok(HttpResponse::Forbidden().json(error_json(format!("{} points belong to another user", foreign.len()))));
+    }
+
+    // points the user already has are overwritten, only the others count against the plan
+    let mut unique = ids.clone();
+    unique.sort();
+    unique.dedup();
+    let owned = data.tweets.retrieve(&tenant, &unique, false).await?.len();
+    let new = unique.len().saturating_sub(owned) as u64;
+    if !can_save_tweets(data.store.as_ref(), &data.entitlement_locks, &data.tweets, &tenant, new).await? {
+        return Ok(HttpResponse::TooManyRequests().json(error_json(format!("Tweet save limit exceeded: the archive adds {} tweets", new))));
+    }
+
+    let (dense, re_embedded) = match reusable_vectors(&archive, data.embedder.model(), data.dimensions) {
+        Some(dense) => (dense, false),
+        None => {
+            if !can_spend_tokens(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone(), &data.config.budgets).await? {
+                return Ok(HttpResponse::TooManyRequests().json(error_json("Token budget exceeded")));
+            }
+            let normalized: Vec<String> = texts(&archive).iter().map(|text| data.normalizer.normalize(text)).collect();
+            let embedded = data.embedder.embed(&normalized).await?;
+            if let Err(e) = record_token_usage(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone(), embedded.usage.total_tokens as u64).await {
+                eprintln!("Recording token usage failed for {}: {}", user.user_id, e);
+            }
+            if embedded.data.len() != total {
+                return Err("Mismatch between tweets and embeddings".into());
+            }
+            (embedded.data.into_iter().map(|d| d.embedding).collect(), true)
+        }
+    };
+    println!("Restoring {} points for {} (re-embedded: {})", total, user.user_id, re_embedded);
+
+    let points = restored_points(&archive, dense, &data.config.vectors);
+    let report = data.tweets.upsert(&tenant, &points, &data.config.upsert).await?;
+    Ok(upsert_response(report, total))
+}
+
 #[post("/reset_qdrant")]
-async fn reset_qdrant(user: AuthUser) -> Result<impl Responder, Error> {
+async fn reset_qdrant(user: AuthUser, data: web::Data<AppState>) -> Result<impl Responder, Error> {
     println!("{:?}", user);
-    let resp = delete_all(user.user_id.clone()).await.map_err(ErrorInternalServerError)?;
-    if !resp.status().is_success() {
-        return Err(ErrorInternalServerError("Qdrant reset failed"));
-    }
+    delete_all(&data.tweets, &Tenant::from(&user)).await.map_err(ErrorInternalServerError)?;
 
     println!(" Qdrant delete response: {}", user.user_id  );
     Ok(HttpResponse::Ok().body("Qdrant reset successful"))
 }
 
 #[post("/delete_points")]
-async fn delete_points(point_id: web::Json<Vec<String>>, _user: AuthUser) -> Result<impl Responder, Error> {
-    
-    let resp = delete_pointid(&point_id).await.map_err(ErrorInternalServerError)?;
-    if !resp.status().is_success() {
-        return Err(ErrorInternalServerError("Qdrant reset failed"));
-    }
+async fn delete_points(point_id: web::Json<Vec<String>>, user: AuthUser, data: web::Data<AppState>) -> Result<impl Responder, Error> {
+    // only the caller's own points are deleted
+    delete_pointid(&data.tweets, &Tenant::from(&user), &point_id).await.map_err(ErrorInternalServerError)?;
 
     println!(" Qdrant delete response: {:?}", point_id  );
     Ok(HttpResponse::Ok().json(serde_json::json!({
@@ -142,14 +315,223 @@ async fn delete_points(point_id: web::Json<Vec<String>>, _user: AuthUser) -> Res
     })))
 }
 
+// Largest top_k /search accepts, bigger values are clamped
+const MAX_TOP_K: u32 = 100;
+
+#[post("/search")]
+async fn semantic_search(
+    params: web::Json<SemanticSearchParams>, user: AuthUser, data: web::Data<AppState>
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+    let query = params.query.trim();
+    if query.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(error_json("Empty query")));
+    }
+    let filter = match payload_filter(&params.filter) {
+        Ok(filter) => filter,
+        Err(message) => return Ok(HttpResponse::BadRequest().json(error_json(message))),
+    };
+    if !data.breakers.accepting() {
+        return Ok(HttpResponse::ServiceUnavailable().json(error_json("Search is unavailable, try again later")));
+    }
+
+    // charged once the search succeeded
+    if !can_search(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone()).await? {
+        return Ok(HttpResponse::TooManyRequests().json(error_json("Daily search limit exceeded")));
+    }
+    let can_spend = can_spend_tokens(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone(), &data.config.budgets).await?;
+    if !can_spend {
+        return Ok(HttpResponse::TooManyRequests().json(error_json("Token budget exceeded")));
+    }
+
+    let embedded = data.embedder.embed(&[data.normalizer.normalize(query)]).await?;
+    if let Err(e) = record_token_usage(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone(), embedded.usage.total_tokens as u64).await {
+        eprintln!("Recording token usage failed for {}: {}", user.user_id, e);
+    }
+    let Some(dense) = embedded.data.into_iter().next().map(|d| d.embedding) else {
+        return Err("Empty embedding response".into());
+    };
+
+    let top_k = params.top_k.clamp(1, MAX_TOP_K) as u8;
+    let results = semantic(&data.tweets, &Tenant::from(&user), dense, query, filter, top_k, &data.config.vectors).await?;
+    if let Err(e) = record_search(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone()).await {
+        eprintln!("Recording search failed for {}: {}", user.user_id, e);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "results": results,
+        "time": start_time.elapsed().as_secs_f64()
+    })))
+}
+
+// Effective match settings and which of them the user overrides
+fn match_settings_json(entitlement: &UserEntitlement, data: &AppState) -> serde_json::Value {
+    let effective = effective_match(entitlement, &data.config.matching);
+    serde_json::json!({
+        "status": "success",
+        "min_score": effective.min_score,
+        "top_k": effective.top_k,
+        "overrides": MatchSettings { min_score: entitlement.match_min_score, top_k: entitlement.match_top_k }
+    })
+}
+
+#[get("/match_settings")]
+async fn get_match_settings(user: AuthUser, data: web::Data<AppState>) -> Result<impl Responder, Error> {
+    let entitlement = get_or_create_entitlement(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone())
+        .await
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(match_settings_json(&entitlement, &data)))
+}
+
+#[post("/match_settings")]
+async fn set_match_settings(
+    settings: web::Json<MatchSettings>, user: AuthUser, data: web::Data<AppState>
+) -> Result<impl Responder, Error> {
+    if settings.min_score.is_some_and(|score| !score.is_finite()) {
+        return Ok(HttpResponse::BadRequest().json(error_json("min_score must be a number")));
+    }
+    if settings.top_k.is_some_and(|k| k == 0 || k > MAX_MATCH_TOP_K) {
+        return Ok(HttpResponse::BadRequest().json(error_json(format!("top_k must be between 1 and {}", MAX_MATCH_TOP_K))));
+    }
+
+    let entitlement = save_match_settings(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone(), &settings)
+        .await
+        .map_err(ErrorInternalServerError)?;
+    println!("Match settings of {} set to {:?}", user.user_id, settings);
+    Ok(HttpResponse::Ok().json(match_settings_json(&entitlement, &data)))
+}
+
+// Why recommend examples could not be turned into vectors
+pub enum ExampleError {
+    // ids that are not among the caller's saved tweets
+    Missing(Vec<String>),
+    BudgetExceeded,
+    Failed(Box<dyn std::error::Error>),
+}
+
+// Most examples accepted on each side of a recommend query
+pub const MAX_EXAMPLES: usize = 20;
+
+// Vectors of every group of examples, in order. Ids must be the user's saved
+// tweets, texts are embedded in one request and charged to the user.
+pub async fn example_vectors(
+    data: &AppState, user: &AuthUser, groups: &[&Examples]
+) -> Result<Vec<Vec<Vec<f32>>>, ExampleError> {
+    let tenant = Tenant::from(user);
+    let ids: Vec<String> = groups.iter().flat_map(|group| group.ids.iter().cloned()).collect();
+    let saved = saved_vectors(&data.tweets, &tenant, &ids).await.map_err(|e| ExampleError::Failed(e.into()))?;
+    let missing: Vec<String> = ids.iter().filter(|id| !saved.contains_key(*id)).cloned().collect();
+    if !missing.is_empty() {
+        return Err(ExampleError::Missing(missing));
+    }
+
+    let texts: Vec<String> = groups
+        .iter()
+        .flat_map(|group| group.texts.iter().map(|text| data.normalizer.normalize(text)))
+        .collect();
+    let mut embedded = Vec::new().into_iter();
+    if !texts.is_empty() {
+        let can_spend = can_spend_tokens(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone(), &data.config.budgets)
+            .await
+            .map_err(|e| ExampleError::Failed(e.into()))?;
+        if !can_spend {
+            return Err(ExampleError::BudgetExceeded);
+        }
+        let response = data.embedder.embed(&texts).await.map_err(|e| ExampleError::Failed(e.into()))?;
+        if let Err(e) = record_token_usage(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone(), response.usage.total_tokens as u64).await {
+            eprintln!("Recording token usage failed for {}: {}", user.user_id, e);
+        }
+        if response.data.len() != texts.len() {
+            return Err(ExampleError::Failed("Mismatch between examples and embeddings".into()));
+        }
+        embedded = response.data.into_iter().map(|d| d.embedding).collect::<Vec<_>>().into_iter();
+    }
+
+    Ok(groups
+        .iter()
+        .map(|group| {
+            let mut vectors: Vec<Vec<f32>> = group.ids.iter().map(|id| saved[id].clone()).collect();
+            vectors.extend(embedded.by_ref().take(group.texts.len()));
+            vectors
+        })
+        .collect())
+}
+
+#[post("/recommend")]
+async fn recommend_tweets(
+    params: web::Json<RecommendParams>, user: AuthUser, data: web::Data<AppState>
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+    if params.positive.is_empty() && (params.negative.is_empty() || params.strategy == RecommendStrategy::AverageVector) {
+        return Ok(HttpResponse::BadRequest().json(error_json("At least one positive example is needed")));
+    }
+    if params.positive.len() > MAX_EXAMPLES || params.negative.len() > MAX_EXAMPLES {
+        return Ok(HttpResponse::BadRequest().json(error_json(format!("At most {} examples per side", MAX_EXAMPLES))));
+    }
+    let filter = match payload_filter(&params.filter) {
+        Ok(filter) => filter,
+        Err(message) => return Ok(HttpResponse::BadRequest().json(error_json(message))),
+    };
+    if !data.breakers.accepting() {
+        return Ok(HttpResponse::ServiceUnavailable().json(error_json("Search is unavailable, try again later")));
+    }
+
+    // charged once the search succeeded
+    if !can_search(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone()).await? {
+        return Ok(HttpResponse::TooManyRequests().json(error_json("Daily search limit exceeded")));
+    }
+
+    let (positive, negative) = match example_vectors(&data, &user, &[&params.positive, &params.negative]).await {
+        Ok(mut groups) => {
+            let negative = groups.pop().unwrap_or_default();
+            (groups.pop().unwrap_or_default(), negative)
+        }
+        Err(ExampleError::Missing(ids)) => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "status": "error",
+                "message": "Examples not found among your saved tweets",
+                "missing": ids
+            })));
+        }
+        Err(ExampleError::BudgetExceeded) => {
+            return Ok(HttpResponse::TooManyRequests().json(error_json("Token budget exceeded")));
+        }
+        Err(ExampleError::Failed(e)) => return Err(e),
+    };
+
+    let input = RecommendInput { positive, negative, strategy: params.strategy };
+    let exclude: Vec<String> = params.positive.ids.iter().chain(&params.negative.ids).cloned().collect();
+    let top_k = params.top_k.clamp(1, MAX_TOP_K) as u8;
+    let results = recommend(&data.tweets, &Tenant::from(&user), input, filter, &exclude, top_k, &data.config.vectors).await?;
+    if let Err(e) = record_search(data.store.as_ref(), &data.entitlement_locks, user.user_id.clone()).await {
+        eprintln!("Recording search failed for {}: {}", user.user_id, e);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "results": results,
+        "time": start_time.elapsed().as_secs_f64()
+    })))
+}
+
+// Largest page /search_payload returns, bigger limits are clamped
+const MAX_PAGE_SIZE: u32 = 500;
+
 #[post("/search_payload")]
 async fn search_payload(
-    params: web::Json<SearchParams>, user: AuthUser
+    params: web::Json<SearchParams>, user: AuthUser, data: web::Data<AppState>
 ) -> Result<impl Responder, actix_web::Error> 
 {
-    let collection = "tweet_userid".to_string();
-    // search() returns Result<reqwest::Response, reqwest::Error>
-    let resp = match search(user.user_id.clone(), params.limit, collection).await {
+    let start_time = Instant::now();
+    let offset = match params.cursor.as_deref() {
+        None | Some("") => None,
+        Some(cursor) => Some(decode_cursor(cursor).ok_or_else(|| ErrorBadRequest("Invalid cursor"))?),
+    };
+    let limit = params.limit.clamp(1, MAX_PAGE_SIZE);
+    let filter = payload_filter(&params.filter).map_err(ErrorBadRequest)?;
+
+    let result = match data.tweets.scroll(&Tenant::from(&user), &filter, limit, offset, false).await {
         Ok(r) => r,
         Err(err) => {
             println!("Error in search(): {err}");
@@ -157,17 +539,17 @@ async fn search_payload(
         }
     };
 
-    // Convert reqwest error to Actix error explicitly
-    let parsed: RootSearch = resp.json()
-        .await
-        .map_err(|e| {
-            println!("Error parsing JSON: {e}");
-            ErrorInternalServerError("Failed to parse Qdrant response")
-        })?;
+    // same shape as Qdrant's scroll response, the extension reads payload.result.points
+    let next_cursor = result.next_page_offset.as_ref().map(encode_cursor);
+    let parsed = RootSearch {
+        result,
+        status: "ok".to_string(),
+        time: start_time.elapsed().as_secs_f64(),
+        next_cursor,
+    };
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "status": "success",
         "payload": parsed
     })))
 }
-
@@ -5,7 +5,57 @@ use serde_json;
 use tokio::sync::RwLock;
 
 use crate::models::internal::{AppState, Tweet, TweetPayload};
-use crate::auth::verify::verify_ws_request;
+use crate::models::recommend::{Examples, ProfileRequest, RecommendProfile};
+use crate::auth::{extractor::AuthUser, verify::verify_ws_request};
+use crate::routes::routes::{example_vectors, ExampleError, MAX_EXAMPLES};
+
+fn error_reply(message: impl std::fmt::Display) -> serde_json::Value {
+    serde_json::json!({
+        "status": "error",
+        "message": message.to_string()
+    })
+}
+
+// Sets, or with null clears, the user's recommend profile. Returns the reply
+// for the session.
+async fn update_profile(data: &AppState, user: &AuthUser, request: serde_json::Value) -> serde_json::Value {
+    if request.is_null() {
+        data.recommend_profiles.set(&user.user_id, None).await;
+        return serde_json::json!({ "status": "success", "recommend_profile": "cleared" });
+    }
+    let request: ProfileRequest = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(e) => return error_reply(format!("Invalid recommend profile: {}", e)),
+    };
+    if request.positive_ids.len() > MAX_EXAMPLES || request.negative.len() > MAX_EXAMPLES {
+        return error_reply(format!("At most {} examples per side", MAX_EXAMPLES));
+    }
+    if !data.breakers.accepting() {
+        return error_reply("Scoring unavailable");
+    }
+
+    // positives are only checked for ownership, they are matched by id
+    let positive = Examples { ids: request.positive_ids.clone(), texts: Vec::new() };
+    let negative = match example_vectors(data, user, &[&positive, &request.negative]).await {
+        Ok(mut groups) => groups.pop().unwrap_or_default(),
+        Err(ExampleError::Missing(ids)) => {
+            return serde_json::json!({
+                "status": "error",
+                "message": "Examples not found among your saved tweets",
+                "missing": ids
+            });
+        }
+        Err(ExampleError::BudgetExceeded) => return error_reply("Token budget exceeded"),
+        Err(ExampleError::Failed(e)) => {
+            eprintln!("Recommend profile of {} failed: {}", user.user_id, e);
+            return error_reply("Recommend profile could not be set");
+        }
+    };
+
+    let profile = RecommendProfile { positive_ids: request.positive_ids, negative, strategy: request.strategy };
+    data.recommend_profiles.set(&user.user_id, Some(profile)).await;
+    serde_json::json!({ "status": "success", "recommend_profile": "set" })
+}
 
 #[get("/ws")]
 pub async fn ws(
@@ -14,12 +64,14 @@ pub async fn ws(
     data: web::Data<AppState>,
 ) -> actix_web::Result<impl Responder> {
 
-    let _user_id = match verify_ws_request(&req).await {
+    let auth = match verify_ws_request(&req).await {
         Ok(u) => u,
         Err(err) => return Err(err),
     };
+    let _user_id = auth.user_id.clone();
 
     let (response, mut session, mut msg_stream) = handle(&req, body)?;
+    data.recommend_profiles.open(&_user_id).await;
 
     // let data_resp = data.clone();
     // let mut session_result = session.clone();
@@ -38,17 +90,51 @@ pub async fn ws(
         
                     *user_id.write().await = _user_id.clone();
 
-                    let payload: TweetPayload = serde_json::from_str(&payload).unwrap();
+                    // a bad message is answered, it must not take the session down
+                    let message: serde_json::Value = match serde_json::from_str(&payload) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            let _ = session.text(format!("{}", error_reply(format!("Invalid JSON: {}", e)))).await;
+                            continue;
+                        }
+                    };
+                    if let Some(profile) = message.get("recommend_profile") {
+                        let reply = update_profile(&data, &auth, profile.clone()).await;
+                        let _ = session.text(format!("{}", reply)).await;
+                        continue;
+                    }
+
+                    let payload: TweetPayload = match serde_json::from_value(message) {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            let _ = session.text(format!("{}", error_reply(format!("Invalid tweets: {}", e)))).await;
+                            continue;
+                        }
+                    };
 
                     let tweets: Vec<Tweet> = payload.tweets.iter().map(|t| Tweet {
                         user_id: _user_id.clone(),
                         id: t.id.clone(),
                         text: t.text.clone(),
                         username: t.username.clone(),
+                        created_at: t.created_at.clone(),
+                        lang: t.lang.clone(),
+                        url: t.url.clone(),
+                        team_id: auth.team_id.clone(),
                     } ).collect();
 
-                    let final_payload: TweetPayload = TweetPayload { tweets: tweets };
-
+                    let final_payload: TweetPayload = TweetPayload { tweets };
+
+                    // nothing could score these, ack right away instead of waiting on a batch
+                    if !data.breakers.accepting() {
+                        let reply = serde_json::json!({
+                            "status": "success",
+                            "received": payload.tweets.len(),
+                            "scoring": "unavailable"
+                        });
+                        let _ = session.text(format!("{}", reply)).await;
+                        continue;
+                    }
 
                     println!("User ID: {}", user_id.read().await);
                     {
@@ -59,7 +145,8 @@ pub async fn ws(
 
                     let reply = serde_json::json!({
                         "status": "success",
-                        "received": payload.tweets.len()
+                        "received": payload.tweets.len(),
+                        "scoring": "available"
                     });
                     let _ = session.text(format!("{}", reply)).await;
 
@@ -70,9 +157,20 @@ pub async fn ws(
                         let mut resp = data.hashset.write().await;
                         let temp = resp.remove(&u);
                         match temp {
-                            Some(value) => {
+                            Some(Ok(results)) => {
                                 println!("Sending data");
-                                let _ = session.text(format!("{:?}", value)).await;
+                                let reply = serde_json::json!({
+                                    "status": "success",
+                                    "results": results
+                                });
+                                let _ = session.text(format!("{}", reply)).await;
+                            }
+                            Some(Err(message)) => {
+                                let reply = serde_json::json!({
+                                    "status": "error",
+                                    "message": message
+                                });
+                                let _ = session.text(format!("{}", reply)).await;
                             }
                             _ => {
                                 println!("Result loop broken:(");
@@ -84,6 +182,8 @@ pub async fn ws(
                 _ => break,
             }
         }
+        // profiles live as long as the user's last session
+        data.recommend_profiles.close(&_user_id).await;
     });
 
     // actix_web::rt::spawn(async move {
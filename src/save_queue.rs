@@ -0,0 +1,90 @@
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use actix_web::web;
+
+use crate::{
+    auth::extractor::AuthUser,
+    models::internal::{AppState, TweetPayload},
+    routes::routes::{is_unavailable, save_tweets, SaveOutcome},
+};
+
+// How often the worker checks whether queued saves can be replayed
+const REPLAY_EVERY: Duration = Duration::from_secs(5);
+
+// A /save request accepted while the vector store or the embedding
+// provider was down
+pub struct QueuedSave {
+    pub user: AuthUser,
+    pub payload: TweetPayload,
+}
+
+// In-memory FIFO of delayed saves, lost on restart
+pub struct SaveQueue {
+    items: Mutex<VecDeque<QueuedSave>>,
+    capacity: usize,
+}
+
+impl SaveQueue {
+    pub fn new(capacity: usize) -> Self {
+        SaveQueue { items: Mutex::new(VecDeque::new()), capacity }
+    }
+
+    // false when the queue is full
+    pub fn push(&self, save: QueuedSave) -> bool {
+        let mut items = self.items.lock().unwrap();
+        if items.len() >= self.capacity {
+            return false;
+        }
+        items.push_back(save);
+        true
+    }
+
+    fn pop(&self) -> Option<QueuedSave> {
+        self.items.lock().unwrap().pop_front()
+    }
+
+    // back to the head of the queue, keeps saves in arrival order
+    fn requeue(&self, save: QueuedSave) {
+        self.items.lock().unwrap().push_front(save);
+    }
+}
+
+// Replays queued saves in order once both circuits let calls through again.
+// The first replay doubles as the half open probe. Runs on the actix
+// runtime like the WS sessions, the save pipeline is not Send.
+pub fn spawn_worker(app_state: web::Data<AppState>) {
+    actix_web::rt::spawn(async move {
+        loop {
+            tokio::time::sleep(REPLAY_EVERY).await;
+
+            while app_state.breakers.accepting() {
+                let Some(save) = app_state.save_queue.pop() else {
+                    break;
+                };
+                let user_id = save.user.user_id.clone();
+                let retry = match save_tweets(&app_state, &save.user, &save.payload).await {
+                    Ok(SaveOutcome::Saved { report, total }) if !report.failed.is_empty() => {
+                        eprintln!("Queued save for {}: {}/{} points failed", user_id, report.failed.len(), total);
+                        !app_state.breakers.accepting()
+                    }
+                    Ok(SaveOutcome::Saved { total, .. }) => {
+                        println!("Queued save for {} done, {} points", user_id, total);
+                        false
+                    }
+                    Ok(SaveOutcome::LimitExceeded | SaveOutcome::BudgetExceeded) => {
+                        eprintln!("Queued save for {} dropped, over its limits", user_id);
+                        false
+                    }
+                    Err(e) => {
+                        eprintln!("Queued save for {} failed: {}", user_id, e);
+                        !app_state.breakers.accepting() || is_unavailable(e.as_ref())
+                    }
+                };
+                if retry {
+                    app_state.save_queue.requeue(save);
+                    break;
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use serde_json::Value as Json;
+use std::sync::Arc;
+
+use crate::{
+    breaker::CircuitBreaker,
+    models::{
+        middleware::{FilterType, SearchRequest},
+        qdrant::{ScrollRequest, ScrollResult, UpdateResult},
+        similarity_result::ResultItem,
+    },
+    qdrant_functions::error::QdrantError,
+    vector_store::{Scope, VectorStore, WriteOptions},
+};
+
+// Refuses calls with QdrantError::Unavailable while the circuit is open,
+// so callers fail fast instead of waiting on connection timeouts
+pub struct BreakerStore {
+    inner: Arc<dyn VectorStore>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl BreakerStore {
+    pub fn new(inner: Arc<dyn VectorStore>, breaker: Arc<CircuitBreaker>) -> Self {
+        BreakerStore { inner, breaker }
+    }
+
+    fn check(&self) -> Result<(), QdrantError> {
+        if self.breaker.allow() {
+            Ok(())
+        } else {
+            Err(QdrantError::Unavailable)
+        }
+    }
+
+    fn record<T>(&self, result: Result<T, QdrantError>) -> Result<T, QdrantError> {
+        match &result {
+            Err(e) if e.is_unavailable() => self.breaker.failure(),
+            _ => self.breaker.success(),
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl VectorStore for BreakerStore {
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+
+    fn tweets(&self) -> &str {
+        self.inner.tweets()
+    }
+
+    fn entitlements(&self) -> &str {
+        self.inner.entitlements()
+    }
+
+    async fn prepare(&self, scope: &Scope) -> Result<(), QdrantError> {
+        self.check()?;
+        self.record(self.inner.prepare(scope).await)
+    }
+
+    async fn upsert(&self, scope: &Scope, points: &[Json], options: WriteOptions) -> Result<UpdateResult, QdrantError> {
+        self.check()?;
+        self.record(self.inner.upsert(scope, points, options).await)
+    }
+
+    async fn delete_by_filter(&self, scope: &Scope, filter: &FilterType) -> Result<UpdateResult, QdrantError> {
+        self.check()?;
+        self.record(self.inner.delete_by_filter(scope, filter).await)
+    }
+
+    async fn scroll(&self, scope: &Scope, request: &ScrollRequest) -> Result<ScrollResult<Json>, QdrantError> {
+        self.check()?;
+        self.record(self.inner.scroll(scope, request).await)
+    }
+
+    async fn count(&self, scope: &Scope, filter: &FilterType) -> Result<u64, QdrantError> {
+        self.check()?;
+        self.record(self.inner.count(scope, filter).await)
+    }
+
+    async fn query_batch(&self, scope: &Scope, request: &SearchRequest) -> Result<Vec<ResultItem>, QdrantError> {
+        self.check()?;
+        self.record(self.inner.query_batch(scope, request).await)
+    }
+}
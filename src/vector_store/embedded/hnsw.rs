@@ -0,0 +1,426 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+};
+
+use rand::Rng;
+
+// Hierarchical navigable small world graph over unit length vectors, so the
+// dot product is the cosine similarity. Deleted nodes stay in the graph as
+// tombstones to keep it navigable, they are dropped when the index is rebuilt.
+// The graph is saved with `encode` so a restart does not have to rebuild it.
+
+// max neighbours per node on the upper layers, layer 0 keeps twice as many
+const M: usize = 16;
+const EF_CONSTRUCTION: usize = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+struct Scored(f32, usize);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then_with(|| self.1.cmp(&other.1))
+    }
+}
+
+struct Node {
+    vector: Vec<f32>,
+    // neighbours per layer, index 0 is the bottom layer
+    links: Vec<Vec<usize>>,
+    deleted: bool,
+}
+
+#[derive(Default)]
+pub struct Hnsw {
+    nodes: Vec<Node>,
+    entry: Option<usize>,
+    live: usize,
+}
+
+pub fn normalized(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|x| x / norm).collect()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+impl Hnsw {
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    // Number of nodes, tombstones included
+    pub fn nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Stored (normalized) vector of a node that is not deleted
+    pub fn live_vector(&self, node: usize) -> Option<&[f32]> {
+        self.nodes.get(node).filter(|n| !n.deleted).map(|n| n.vector.as_slice())
+    }
+
+    fn similarity(&self, query: &[f32], node: usize) -> f32 {
+        dot(query, &self.nodes[node].vector)
+    }
+
+    fn random_level() -> usize {
+        let level_mult = 1.0 / (M as f64).ln();
+        let uniform: f64 = rand::rng().random_range(f64::EPSILON..1.0);
+        (-uniform.ln() * level_mult).floor() as usize
+    }
+
+    fn max_links(layer: usize) -> usize {
+        if layer == 0 { 2 * M } else { M }
+    }
+
+    // Adds `vector` and returns its node number
+    pub fn insert(&mut self, vector: &[f32]) -> usize {
+        let vector = normalized(vector);
+        let level = Self::random_level();
+        let node = self.nodes.len();
+        self.nodes.push(Node { vector, links: vec![Vec::new(); level + 1], deleted: false });
+        self.live += 1;
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return node;
+        };
+
+        let query = self.nodes[node].vector.clone();
+        let top = self.nodes[entry].links.len() - 1;
+        let mut nearest = entry;
+        for layer in (level + 1..=top).rev() {
+            nearest = self.greedy(&query, nearest, layer);
+        }
+
+        let mut entries = vec![nearest];
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&query, &entries, EF_CONSTRUCTION, layer, |_| true);
+            let neighbours: Vec<usize> = found.iter().take(Self::max_links(layer)).map(|s| s.1).collect();
+            for &neighbour in &neighbours {
+                self.link(neighbour, node, layer);
+            }
+            self.nodes[node].links[layer] = neighbours;
+            entries = found.into_iter().map(|s| s.1).collect();
+        }
+
+        if level > top {
+            self.entry = Some(node);
+        }
+        node
+    }
+
+    // Adds `to` to the links of `from`, keeping only the closest ones
+    fn link(&mut self, from: usize, to: usize, layer: usize) {
+        self.nodes[from].links[layer].push(to);
+        if self.nodes[from].links[layer].len() <= Self::max_links(layer) {
+            return;
+        }
+        let base = self.nodes[from].vector.clone();
+        let mut scored: Vec<Scored> = self.nodes[from].links[layer]
+            .iter()
+            .map(|&n| Scored(dot(&base, &self.nodes[n].vector), n))
+            .collect();
+        scored.sort_by(|a, b| b.cmp(a));
+        scored.truncate(Self::max_links(layer));
+        self.nodes[from].links[layer] = scored.into_iter().map(|s| s.1).collect();
+    }
+
+    pub fn delete(&mut self, node: usize) {
+        if !self.nodes[node].deleted {
+            self.nodes[node].deleted = true;
+            self.live -= 1;
+        }
+    }
+
+    fn greedy(&self, query: &[f32], start: usize, layer: usize) -> usize {
+        let mut current = start;
+        let mut best = self.similarity(query, current);
+        loop {
+            let mut moved = false;
+            for &neighbour in &self.nodes[current].links[layer] {
+                let score = self.similarity(query, neighbour);
+                if score > best {
+                    best = score;
+                    current = neighbour;
+                    moved = true;
+                }
+            }
+            if !moved {
+                return current;
+            }
+        }
+    }
+
+    // Best first search of one layer. Every node is used for navigation, only
+    // nodes passing `accept` end up in the result, best first.
+    fn search_layer<F: Fn(usize) -> bool>(
+        &self,
+        query: &[f32],
+        entries: &[usize],
+        ef: usize,
+        layer: usize,
+        accept: F,
+    ) -> Vec<Scored> {
+        let mut visited: HashSet<usize> = entries.iter().copied().collect();
+        let mut candidates: BinaryHeap<Scored> = BinaryHeap::new();
+        let mut results: BinaryHeap<Reverse<Scored>> = BinaryHeap::new();
+
+        for &entry in entries {
+            let scored = Scored(self.similarity(query, entry), entry);
+            candidates.push(scored);
+            if accept(entry) {
+                results.push(Reverse(scored));
+            }
+        }
+
+        while let Some(candidate) = candidates.pop() {
+            if results.len() >= ef && results.peek().is_some_and(|worst| candidate.0 < worst.0 .0) {
+                break;
+            }
+            for &neighbour in &self.nodes[candidate.1].links[layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let scored = Scored(self.similarity(query, neighbour), neighbour);
+                let worst = results.peek().map(|r| r.0 .0);
+                if results.len() < ef || worst.is_some_and(|w| scored.0 > w) {
+                    candidates.push(scored);
+                    if accept(neighbour) {
+                        results.push(Reverse(scored));
+                        if results.len() > ef {
+                            results.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut found: Vec<Scored> = results.into_iter().map(|r| r.0).collect();
+        found.sort_by(|a, b| b.cmp(a));
+        found
+    }
+
+    // Up to `limit` live nodes passing `accept`, most similar first
+    pub fn search<F: Fn(usize) -> bool>(&self, query: &[f32], limit: usize, ef: usize, accept: F) -> Vec<(usize, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let query = normalized(query);
+        let mut nearest = entry;
+        for layer in (1..self.nodes[entry].links.len()).rev() {
+            nearest = self.greedy(&query, nearest, layer);
+        }
+        self.search_layer(&query, &[nearest], ef.max(limit), 0, |n| !self.nodes[n].deleted && accept(n))
+            .into_iter()
+            .take(limit)
+            .map(|s| (s.1, s.0))
+            .collect()
+    }
+}
+
+// Little endian layout: entry node (u64::MAX when empty), node count, then per
+// node the tombstone flag, the vector and the links of every layer
+impl Hnsw {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.entry.map_or(u64::MAX, |e| e as u64).to_le_bytes());
+        out.extend_from_slice(&(self.nodes.len() as u64).to_le_bytes());
+        for node in &self.nodes {
+            out.push(node.deleted as u8);
+            out.extend_from_slice(&(node.vector.len() as u32).to_le_bytes());
+            for x in &node.vector {
+                out.extend_from_slice(&x.to_le_bytes());
+            }
+            out.extend_from_slice(&(node.links.len() as u32).to_le_bytes());
+            for layer in &node.links {
+                out.extend_from_slice(&(layer.len() as u32).to_le_bytes());
+                for &neighbour in layer {
+                    out.extend_from_slice(&(neighbour as u32).to_le_bytes());
+                }
+            }
+        }
+    }
+
+    pub fn decode(input: &mut Reader) -> std::io::Result<Hnsw> {
+        let entry = input.u64()?;
+        let count = input.u64()? as usize;
+        let mut nodes = Vec::new();
+        for _ in 0..count {
+            let deleted = input.u8()? != 0;
+            let dims = input.u32()? as usize;
+            let vector = (0..dims).map(|_| input.f32()).collect::<Result<_, _>>()?;
+            let layers = input.u32()? as usize;
+            let mut links = Vec::new();
+            for _ in 0..layers {
+                let len = input.u32()? as usize;
+                links.push((0..len).map(|_| input.u32().map(|n| n as usize)).collect::<Result<Vec<_>, _>>()?);
+            }
+            nodes.push(Node { vector, links, deleted });
+        }
+
+        // a damaged file must not send a search out of bounds: every node has
+        // a bottom layer and every link points to a node present on that layer
+        let entry = (entry != u64::MAX).then_some(entry as usize);
+        let linked = nodes.iter().all(|node| {
+            !node.links.is_empty()
+                && node.links.iter().enumerate().all(|(layer, links)| {
+                    links.iter().all(|&l| nodes.get(l).is_some_and(|n| n.links.len() > layer))
+                })
+        });
+        if !linked || entry.is_some() == nodes.is_empty() || entry.is_some_and(|e| e >= nodes.len()) {
+            return Err(invalid("inconsistent graph"));
+        }
+        let live = nodes.iter().filter(|n| !n.deleted).count();
+        Ok(Hnsw { nodes, entry, live })
+    }
+}
+
+pub fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Cursor over an encoded graph
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn take(&mut self, len: usize) -> std::io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(invalid("truncated graph"));
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> std::io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> std::io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("4 bytes")))
+    }
+
+    pub fn u64(&mut self) -> std::io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("8 bytes")))
+    }
+
+    fn f32(&mut self) -> std::io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().expect("4 bytes")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_vectors(count: usize, dimensions: usize) -> Vec<Vec<f32>> {
+        let mut rng = rand::rng();
+        (0..count).map(|_| (0..dimensions).map(|_| rng.random_range(-1.0..1.0)).collect()).collect()
+    }
+
+    fn exact(vectors: &[Vec<f32>], query: &[f32], limit: usize, accept: impl Fn(usize) -> bool) -> Vec<usize> {
+        let query = normalized(query);
+        let mut scored: Vec<Scored> = vectors
+            .iter()
+            .enumerate()
+            .filter(|(node, _)| accept(*node))
+            .map(|(node, vector)| Scored(dot(&query, &normalized(vector)), node))
+            .collect();
+        scored.sort_by(|a, b| b.cmp(a));
+        scored.into_iter().take(limit).map(|s| s.1).collect()
+    }
+
+    fn index(vectors: &[Vec<f32>]) -> Hnsw {
+        let mut index = Hnsw::default();
+        for (node, vector) in vectors.iter().enumerate() {
+            assert_eq!(index.insert(vector), node);
+        }
+        index
+    }
+
+    #[test]
+    fn recall_is_close_to_the_exact_scan() {
+        let vectors = random_vectors(1_000, 16);
+        let index = index(&vectors);
+        assert_eq!(index.len(), vectors.len());
+
+        let (limit, mut found, mut expected) = (10, 0, 0);
+        for query in random_vectors(20, 16) {
+            let hits: HashSet<usize> = index.search(&query, limit, 128, |_| true).into_iter().map(|(node, _)| node).collect();
+            let truth = exact(&vectors, &query, limit, |_| true);
+            found += truth.iter().filter(|node| hits.contains(node)).count();
+            expected += truth.len();
+        }
+        let recall = found as f32 / expected as f32;
+        assert!(recall >= 0.95, "recall {} below 0.95", recall);
+    }
+
+    #[test]
+    fn scores_are_cosine_similarities_best_first() {
+        let vectors = random_vectors(200, 8);
+        let index = index(&vectors);
+        let query = &vectors[17];
+        let hits = index.search(query, 5, 64, |_| true);
+        assert_eq!(hits[0].0, 17);
+        assert!((hits[0].1 - 1.0).abs() < 1e-5);
+        assert!(hits.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    }
+
+    #[test]
+    fn deleted_and_rejected_nodes_are_never_returned() {
+        let vectors = random_vectors(500, 8);
+        let mut index = index(&vectors);
+        for node in (0..500).step_by(2) {
+            index.delete(node);
+        }
+        assert_eq!(index.len(), 250);
+
+        let hits = index.search(&vectors[0], 20, 64, |node| node % 3 != 0);
+        assert_eq!(hits.len(), 20);
+        assert!(hits.iter().all(|(node, _)| node % 2 == 1 && node % 3 != 0));
+    }
+
+    #[test]
+    fn a_decoded_graph_answers_like_the_original() {
+        let vectors = random_vectors(300, 8);
+        let mut index = index(&vectors);
+        index.delete(5);
+
+        let mut body = Vec::new();
+        index.encode(&mut body);
+        let mut reader = Reader::new(&body);
+        let decoded = Hnsw::decode(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!((decoded.len(), decoded.nodes()), (299, 300));
+        assert!(decoded.live_vector(5).is_none());
+        for query in random_vectors(10, 8) {
+            assert_eq!(decoded.search(&query, 10, 64, |_| true), index.search(&query, 10, 64, |_| true));
+        }
+
+        // a truncated file is an error, not a panic
+        assert!(Hnsw::decode(&mut Reader::new(&body[..body.len() - 3])).is_err());
+    }
+}
@@ -0,0 +1,598 @@
+mod hnsw;
+mod storage;
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use serde_json::Value as Json;
+use tokio::{fs, sync::RwLock};
+
+use crate::{
+    config::{EmbeddedConfig, QdrantConfig},
+    models::{
+        middleware::{FilterType, SearchRequest},
+        qdrant::{PointId, ScrollRequest, ScrollResult, UpdateResult},
+        similarity_result::ResultItem,
+    },
+    qdrant_functions::error::QdrantError,
+    vector_store::{
+        embedded::{
+            hnsw::{invalid, normalized, Hnsw, Reader},
+            storage::{write_atomic, CollectionFiles, WalOp, GRAPH_FILE, POINTS_FILE, WAL_FILE},
+        },
+        local::{brute_force_dense, dimension_error, run_query, LocalCollection, LocalPoint},
+        memory::{completed, MemoryCollection},
+        Scope, VectorStore, WriteOptions,
+    },
+};
+
+// below this many vectors an exact scan is as fast as the graph
+const EXACT_SEARCH_THRESHOLD: usize = 2_000;
+const EF_SEARCH: usize = 128;
+// log entries after which a collection is compacted
+const COMPACT_AFTER: usize = 1_000;
+const SNAPSHOTS_DIR: &str = "snapshots";
+
+fn io_error(e: std::io::Error) -> QdrantError {
+    QdrantError::Status { status: 500, body: format!("Embedded store I/O failed: {}", e) }
+}
+
+// Points and payloads of one collection plus an HNSW index over the unnamed
+// dense vector. The index is saved next to the points on every compaction.
+struct EmbeddedCollection {
+    points: MemoryCollection,
+    index: Hnsw,
+    // point of every index node, tombstoned nodes keep their old id
+    node_ids: Vec<PointId>,
+    nodes: HashMap<PointId, usize>,
+    files: CollectionFiles,
+    logged: usize,
+}
+
+impl LocalCollection for EmbeddedCollection {
+    fn points(&self) -> Box<dyn Iterator<Item = &LocalPoint> + Send + '_> {
+        self.points.points()
+    }
+
+    fn get(&self, id: &PointId) -> Option<&LocalPoint> {
+        self.points.get(id)
+    }
+
+    fn nearest_dense(
+        &self,
+        using: Option<&str>,
+        vector: &[f32],
+        filter: &FilterType,
+        limit: usize,
+    ) -> Result<Vec<(PointId, f32)>, QdrantError> {
+        let named = using.is_some_and(|name| !name.is_empty());
+        if named || self.index.len() < EXACT_SEARCH_THRESHOLD {
+            return brute_force_dense(self.points(), using, vector, filter, limit);
+        }
+        if let Some(size) = self.points.dimensions().filter(|size| *size != vector.len()) {
+            return Err(dimension_error(size, vector.len()));
+        }
+
+        let accept = |node: usize| self.points.get(&self.node_ids[node]).is_some_and(|p| p.matches(filter));
+        let hits = self.index.search(vector, limit, EF_SEARCH, accept);
+        // a selective filter (a user with few tweets) can starve the graph walk,
+        // the exact scan then returns everything that matches
+        if hits.len() < limit {
+            return brute_force_dense(self.points(), using, vector, filter, limit);
+        }
+        Ok(hits.into_iter().map(|(node, score)| (self.node_ids[node].clone(), score)).collect())
+    }
+}
+
+impl EmbeddedCollection {
+    fn new(files: CollectionFiles) -> Self {
+        EmbeddedCollection {
+            points: MemoryCollection::default(),
+            index: Hnsw::default(),
+            node_ids: Vec::new(),
+            nodes: HashMap::new(),
+            files,
+            logged: 0,
+        }
+    }
+
+    fn unindex(&mut self, id: &PointId) {
+        if let Some(node) = self.nodes.remove(id) {
+            self.index.delete(node);
+        }
+    }
+
+    fn index_point(&mut self, point: &LocalPoint) {
+        self.unindex(&point.id);
+        if let Some(dense) = point.dense(None) {
+            let node = self.index.insert(dense);
+            self.node_ids.push(point.id.clone());
+            self.nodes.insert(point.id.clone(), node);
+        }
+    }
+
+    fn apply(&mut self, op: WalOp) -> Result<(), QdrantError> {
+        match op {
+            WalOp::Upsert { points } => {
+                self.points.check(&points)?;
+                for point in &points {
+                    self.index_point(point);
+                }
+                self.points.upsert(points)?;
+            }
+            WalOp::DeleteFilter { filter } => {
+                for id in self.points.delete_matching(&filter) {
+                    self.unindex(&id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Folds the log into the points file and saves the graph, dropping index
+    // tombstones first once they outnumber the live vectors
+    async fn compact(&mut self) -> Result<(), QdrantError> {
+        self.files.compact(self.points.points()).await.map_err(io_error)?;
+        self.logged = 0;
+
+        if self.node_ids.len() > 2 * self.index.len().max(1) {
+            self.reindex();
+        }
+        self.files.save_graph(&self.encode_graph()).await.map_err(io_error)
+    }
+
+    fn reindex(&mut self) {
+        let points: Vec<LocalPoint> = self.points.points().cloned().collect();
+        self.index = Hnsw::default();
+        self.node_ids.clear();
+        self.nodes.clear();
+        for point in &points {
+            self.index_point(point);
+        }
+    }
+
+    // The graph followed by the point id of every node
+    fn encode_graph(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        self.index.encode(&mut body);
+        for id in &self.node_ids {
+            match id {
+                PointId::Num(n) => {
+                    body.push(0);
+                    body.extend_from_slice(&n.to_le_bytes());
+                }
+                PointId::Uuid(uuid) => {
+                    body.push(1);
+                    body.extend_from_slice(&(uuid.len() as u32).to_le_bytes());
+                    body.extend_from_slice(uuid.as_bytes());
+                }
+            }
+        }
+        body
+    }
+
+    // Takes a saved graph if it indexes exactly the loaded points. A crash
+    // between writing the points and the graph leaves a stale one behind.
+    fn restore_graph(&mut self, body: &[u8]) -> std::io::Result<()> {
+        let mut reader = Reader::new(body);
+        let index = Hnsw::decode(&mut reader)?;
+        let mut node_ids = Vec::with_capacity(index.nodes());
+        for _ in 0..index.nodes() {
+            node_ids.push(match reader.u8()? {
+                0 => PointId::Num(reader.u64()?),
+                _ => {
+                    let len = reader.u32()? as usize;
+                    let uuid = std::str::from_utf8(reader.take(len)?).map_err(|_| invalid("bad point id"))?;
+                    PointId::Uuid(uuid.to_string())
+                }
+            });
+        }
+        if !reader.is_empty() {
+            return Err(invalid("trailing bytes after the graph"));
+        }
+
+        let mut nodes = HashMap::new();
+        for (node, id) in node_ids.iter().enumerate() {
+            let Some(vector) = index.live_vector(node) else { continue };
+            let stored = self.points.get(id).and_then(|p| p.dense(None)).map(normalized);
+            if stored.as_deref() != Some(vector) || nodes.insert(id.clone(), node).is_some() {
+                return Err(invalid("graph does not match the points"));
+            }
+        }
+        if nodes.len() != self.points.points().filter(|p| p.dense(None).is_some()).count() {
+            return Err(invalid("graph does not match the points"));
+        }
+
+        self.index = index;
+        self.node_ids = node_ids;
+        self.nodes = nodes;
+        Ok(())
+    }
+
+    async fn load(files: CollectionFiles) -> Result<Self, QdrantError> {
+        let (points, ops) = files.load().await.map_err(io_error)?;
+        let graph = files.load_graph().await.map_err(io_error)?;
+        let replayed = ops.len();
+
+        let mut collection = EmbeddedCollection::new(files);
+        collection.points.check(&points)?;
+        collection.points.upsert(points)?;
+        let restored = match graph.map(|body| collection.restore_graph(&body)) {
+            Some(Ok(())) => true,
+            Some(Err(e)) => {
+                eprintln!("Rebuilding the HNSW graph in {}: {}", collection.files.dir().display(), e);
+                false
+            }
+            None => false,
+        };
+        if !restored {
+            collection.reindex();
+        }
+        for op in ops {
+            collection.apply(op)?;
+        }
+        // saves the rebuilt graph too, so the next start can skip it
+        if replayed > 0 || !restored {
+            collection.compact().await?;
+        }
+        Ok(collection)
+    }
+}
+
+struct Inner {
+    root: PathBuf,
+    collections: RwLock<HashMap<String, EmbeddedCollection>>,
+    tweets: String,
+    entitlements: String,
+}
+
+// Single node store on local disk: payloads and vectors in an append-only
+// log with periodic compaction, HNSW for the dense kNN, exact scan fallback
+// for per-user filters. Cheap to clone, clones share the same collections.
+#[derive(Clone)]
+pub struct EmbeddedStore {
+    inner: Arc<Inner>,
+}
+
+impl EmbeddedStore {
+    pub async fn open(config: &EmbeddedConfig, qdrant: &QdrantConfig) -> Result<Self, anyhow::Error> {
+        let root = config.dir.clone();
+        fs::create_dir_all(&root).await?;
+        if let Some(snapshot) = &config.restore_from {
+            restore(&root, snapshot).await?;
+        }
+
+        let mut collections = HashMap::new();
+        let mut entries = fs::read_dir(&root).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == SNAPSHOTS_DIR || !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            let collection = EmbeddedCollection::load(CollectionFiles::new(entry.path()))
+                .await
+                .map_err(|e| anyhow::anyhow!("Loading collection {} failed: {}", name, e))?;
+            println!("Loaded {} points into {}", collection.points.points().count(), name);
+            collections.insert(name, collection);
+        }
+
+        Ok(EmbeddedStore {
+            inner: Arc::new(Inner {
+                root,
+                collections: RwLock::new(collections),
+                tweets: qdrant.tweets_collection.clone(),
+                entitlements: qdrant.entitlements_collection.clone(),
+            }),
+        })
+    }
+
+    async fn write(&self, collection: &str, op: WalOp, create: bool) -> Result<UpdateResult, QdrantError> {
+        let mut collections = self.inner.collections.write().await;
+        if !collections.contains_key(collection) {
+            if !create {
+                return Ok(completed());
+            }
+            let files = CollectionFiles::new(self.inner.root.join(collection));
+            collections.insert(collection.to_string(), EmbeddedCollection::new(files));
+        }
+        let target = collections.get_mut(collection).expect("collection exists");
+
+        // validate before logging so the log only holds operations that apply
+        if let WalOp::Upsert { points } = &op {
+            target.points.check(points)?;
+        }
+        target.files.append(&op).await.map_err(io_error)?;
+        target.apply(op)?;
+
+        target.logged += 1;
+        if target.logged >= COMPACT_AFTER {
+            target.compact().await?;
+        }
+        Ok(completed())
+    }
+
+    // Compacts every collection and copies it to snapshots/<timestamp>/,
+    // keeping the newest `keep` snapshots
+    pub async fn snapshot(&self, keep: usize) -> Result<PathBuf, anyhow::Error> {
+        let snapshots = self.inner.root.join(SNAPSHOTS_DIR);
+        let dir = snapshots.join(chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string());
+        fs::create_dir_all(&dir).await?;
+
+        // the write lock keeps the copies consistent with each other
+        let mut collections = self.inner.collections.write().await;
+        for (name, collection) in collections.iter_mut() {
+            collection.compact().await?;
+            fs::copy(collection.files.points_path(), dir.join(format!("{}.jsonl", name))).await?;
+            fs::copy(collection.files.graph_path(), dir.join(format!("{}.hnsw", name))).await?;
+        }
+        drop(collections);
+
+        let mut existing = Vec::new();
+        let mut entries = fs::read_dir(&snapshots).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            existing.push(entry.path());
+        }
+        existing.sort();
+        for old in existing.iter().rev().skip(keep.max(1)) {
+            fs::remove_dir_all(old).await?;
+        }
+        Ok(dir)
+    }
+
+    pub fn spawn_snapshots(&self, every: std::time::Duration, keep: usize) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(every).await;
+                match store.snapshot(keep).await {
+                    Ok(dir) => println!("Vector store snapshot written to {}", dir.display()),
+                    Err(e) => eprintln!("Vector store snapshot failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+// Replaces the collections found in `snapshot` (files named <collection>.jsonl,
+// with the graph in <collection>.hnsw), collections missing from the snapshot
+// are left alone
+async fn restore(root: &Path, snapshot: &Path) -> Result<(), anyhow::Error> {
+    let mut entries = fs::read_dir(snapshot)
+        .await
+        .map_err(|e| anyhow::anyhow!("Cannot read snapshot {}: {}", snapshot.display(), e))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).await?;
+        write_atomic(&dir.join(POINTS_FILE), &fs::read(&path).await?).await?;
+        // the log belongs to the state being replaced
+        write_atomic(&dir.join(WAL_FILE), &[]).await?;
+        // so does the graph, it is rebuilt if the snapshot has none
+        match fs::read(path.with_extension("hnsw")).await {
+            Ok(graph) => write_atomic(&dir.join(GRAPH_FILE), &graph).await?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => match fs::remove_file(dir.join(GRAPH_FILE)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            },
+            Err(e) => return Err(e.into()),
+        }
+        println!("Restored {} from {}", name, snapshot.display());
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl VectorStore for EmbeddedStore {
+    fn backend(&self) -> &'static str {
+        "embedded"
+    }
+
+    fn tweets(&self) -> &str {
+        &self.inner.tweets
+    }
+
+    fn entitlements(&self) -> &str {
+        &self.inner.entitlements
+    }
+
+    async fn upsert(&self, scope: &Scope, points: &[Json], _options: WriteOptions) -> Result<UpdateResult, QdrantError> {
+        let points = points.iter().map(LocalPoint::from_json).collect::<Result<_, _>>()?;
+        self.write(&scope.collection, WalOp::Upsert { points }, true).await
+    }
+
+    async fn delete_by_filter(&self, scope: &Scope, filter: &FilterType) -> Result<UpdateResult, QdrantError> {
+        self.write(&scope.collection, WalOp::DeleteFilter { filter: filter.clone() }, false).await
+    }
+
+    async fn scroll(&self, scope: &Scope, request: &ScrollRequest) -> Result<ScrollResult<Json>, QdrantError> {
+        Ok(match self.inner.collections.read().await.get(&scope.collection) {
+            Some(target) => target.points.scroll(request),
+            None => ScrollResult { points: Vec::new(), next_page_offset: None },
+        })
+    }
+
+    async fn count(&self, scope: &Scope, filter: &FilterType) -> Result<u64, QdrantError> {
+        Ok(self.inner.collections.read().await.get(&scope.collection).map_or(0, |target| target.points.count(filter)))
+    }
+
+    async fn query_batch(&self, scope: &Scope, request: &SearchRequest) -> Result<Vec<ResultItem>, QdrantError> {
+        let collections = self.inner.collections.read().await;
+        let Some(target) = collections.get(&scope.collection) else {
+            return Ok(request.searches.iter().map(|_| ResultItem { points: Vec::new() }).collect());
+        };
+        request.searches.iter().map(|search| run_query(target, search)).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{config::QdrantTransport, models::middleware::Condition, qdrant_functions::search::user_filter};
+
+    fn point(id: u64, user_id: &str, vector: &[f32]) -> Json {
+        json!({ "id": id, "vector": vector, "payload": { "user_id": user_id, "text": id.to_string() } })
+    }
+
+    fn qdrant_config() -> QdrantConfig {
+        QdrantConfig {
+            endpoint: String::new(),
+            api_key: None,
+            tweets_collection: "tweets".to_string(),
+            entitlements_collection: "entitlements".to_string(),
+            distance: "Cosine".to_string(),
+            transport: QdrantTransport::Rest,
+            grpc_endpoint: String::new(),
+        }
+    }
+
+    fn embedded_config(dir: &Path, restore_from: Option<PathBuf>) -> EmbeddedConfig {
+        EmbeddedConfig { dir: dir.to_path_buf(), snapshot_interval: None, snapshot_keep: 3, restore_from }
+    }
+
+    async fn open(dir: &Path) -> EmbeddedStore {
+        EmbeddedStore::open(&embedded_config(dir, None), &qdrant_config()).await.unwrap()
+    }
+
+    fn tweets() -> Scope {
+        Scope::collection("tweets")
+    }
+
+    #[test]
+    fn a_selective_filter_falls_back_to_the_exact_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collection = EmbeddedCollection::new(CollectionFiles::new(dir.path().join("tweets")));
+
+        // enough points for the graph to be used, almost all of them bob's
+        let mut rng = rand::rng();
+        let mut points: Vec<Json> = (0..EXACT_SEARCH_THRESHOLD as u64)
+            .map(|id| point(id, "bob", &[rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0)]))
+            .collect();
+        points.push(point(10_000, "alice", &[1.0, 0.0, 0.0]));
+        points.push(point(10_001, "alice", &[0.0, 1.0, 0.0]));
+        points.push(point(10_002, "alice", &[0.0, 0.0, 1.0]));
+        let points = points.iter().map(LocalPoint::from_json).collect::<Result<_, _>>().unwrap();
+        collection.apply(WalOp::Upsert { points }).unwrap();
+        assert!(collection.index.len() >= EXACT_SEARCH_THRESHOLD);
+
+        let query = [0.9, 0.1, 0.0];
+        let hits = collection.nearest_dense(None, &query, &user_filter("alice"), 10).unwrap();
+        let exact = brute_force_dense(collection.points(), None, &query, &user_filter("alice"), 10).unwrap();
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits, exact);
+        assert_eq!(hits[0].0, PointId::Num(10_000));
+    }
+
+    #[tokio::test]
+    async fn reopening_replays_the_log_and_drops_a_torn_last_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open(dir.path()).await;
+        store
+            .upsert(&tweets(), &[point(1, "alice", &[1.0, 0.0]), point(2, "bob", &[0.0, 1.0])], WriteOptions::default())
+            .await
+            .unwrap();
+        store.upsert(&tweets(), &[point(3, "alice", &[0.6, 0.8])], WriteOptions::default()).await.unwrap();
+        store.delete_by_filter(&tweets(), &user_filter("bob")).await.unwrap();
+        drop(store);
+
+        let wal = dir.path().join("tweets").join(WAL_FILE);
+        let mut log = fs::read(&wal).await.unwrap();
+        log.extend_from_slice(br#"{"op":"delete_filter","filter":{"mu"#);
+        fs::write(&wal, log).await.unwrap();
+
+        let store = open(dir.path()).await;
+        assert_eq!(store.count(&tweets(), &FilterType::default()).await.unwrap(), 2);
+        assert_eq!(store.count(&tweets(), &user_filter("alice")).await.unwrap(), 2);
+        // the replayed log was compacted away
+        assert!(fs::read(&wal).await.unwrap().is_empty());
+
+        let search: SearchRequest = serde_json::from_value(json!({
+            "searches": [{ "query": [0.0, 1.0], "filter": user_filter("alice"), "with_payload": true, "limit": 1 }]
+        }))
+        .unwrap();
+        let results = store.query_batch(&tweets(), &search).await.unwrap();
+        assert_eq!(results[0].points[0].id, "3");
+    }
+
+    async fn saved_graph(store: &EmbeddedStore) -> Vec<u8> {
+        store.inner.collections.read().await.get("tweets").unwrap().encode_graph()
+    }
+
+    #[tokio::test]
+    async fn reopening_loads_the_saved_graph_instead_of_rebuilding_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open(dir.path()).await;
+        let mut rng = rand::rng();
+        let points: Vec<Json> = (0..300)
+            .map(|id| point(id, "alice", &[rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0), rng.random_range(-1.0..1.0)]))
+            .collect();
+        store.upsert(&tweets(), &points, WriteOptions::default()).await.unwrap();
+        store.delete_by_filter(&tweets(), &FilterType { must: vec![Condition::HasId { has_id: vec!["7".to_string()] }] }).await.unwrap();
+        drop(store);
+
+        // replaying the log compacts the collection, which saves its graph
+        let store = open(dir.path()).await;
+        let graph = dir.path().join("tweets").join(GRAPH_FILE);
+        let saved = fs::read(&graph).await.unwrap();
+        assert_eq!(saved_graph(&store).await, saved);
+        drop(store);
+
+        // random node levels make a rebuilt graph differ from the saved one
+        let store = open(dir.path()).await;
+        assert_eq!(saved_graph(&store).await, saved);
+        drop(store);
+
+        // a graph that does not fit the points is rebuilt and saved again
+        fs::write(&graph, b"not a graph").await.unwrap();
+        let store = open(dir.path()).await;
+        let rebuilt = fs::read(&graph).await.unwrap();
+        assert_ne!(rebuilt, saved);
+        assert_eq!(saved_graph(&store).await, rebuilt);
+        let collections = store.inner.collections.read().await;
+        let collection = collections.get("tweets").unwrap();
+        assert_eq!((collection.index.len(), collection.nodes.len()), (299, 299));
+    }
+
+    #[tokio::test]
+    async fn a_snapshot_restores_the_state_it_was_taken_from() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open(dir.path()).await;
+        store.upsert(&tweets(), &[point(1, "alice", &[1.0, 0.0])], WriteOptions::default()).await.unwrap();
+        let snapshot = store.snapshot(3).await.unwrap();
+        assert!(snapshot.join("tweets.jsonl").exists());
+        assert!(snapshot.join("tweets.hnsw").exists());
+
+        store.upsert(&tweets(), &[point(2, "alice", &[0.0, 1.0])], WriteOptions::default()).await.unwrap();
+        store.delete_by_filter(&tweets(), &FilterType { must: vec![Condition::HasId { has_id: vec!["1".to_string()] }] }).await.unwrap();
+        drop(store);
+
+        let restored = EmbeddedStore::open(&embedded_config(dir.path(), Some(snapshot)), &qdrant_config()).await.unwrap();
+        let page = restored
+            .scroll(
+                &tweets(),
+                &ScrollRequest {
+                    filter: FilterType::default(),
+                    limit: 10,
+                    with_payload: true,
+                    with_vector: true,
+                    offset: None,
+                    shard_key: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.points, vec![point(1, "alice", &[1.0, 0.0])]);
+    }
+}
@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{models::middleware::FilterType, vector_store::local::LocalPoint};
+
+// On-disk layout of one collection:
+//   <root>/<collection>/points.jsonl  last compacted state, one point per line
+//   <root>/<collection>/wal.jsonl     operations since then, one per line
+//   <root>/<collection>/graph.hnsw    HNSW graph over the compacted points
+// Every operation is appended and synced before it is applied in memory.
+// Replaying the log is idempotent, so a crash during compaction is harmless.
+// A graph that does not match the points is rebuilt on load.
+
+pub const POINTS_FILE: &str = "points.jsonl";
+pub const WAL_FILE: &str = "wal.jsonl";
+pub const GRAPH_FILE: &str = "graph.hnsw";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum WalOp {
+    Upsert { points: Vec<LocalPoint> },
+    DeleteFilter { filter: FilterType },
+}
+
+pub struct CollectionFiles {
+    dir: PathBuf,
+}
+
+impl CollectionFiles {
+    pub fn new(dir: PathBuf) -> Self {
+        CollectionFiles { dir }
+    }
+
+    pub fn points_path(&self) -> PathBuf {
+        self.dir.join(POINTS_FILE)
+    }
+
+    fn wal_path(&self) -> PathBuf {
+        self.dir.join(WAL_FILE)
+    }
+
+    pub fn graph_path(&self) -> PathBuf {
+        self.dir.join(GRAPH_FILE)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Compacted points followed by the logged operations. A line that does not
+    // parse at the end of the log is a write torn by a crash and is dropped.
+    pub async fn load(&self) -> std::io::Result<(Vec<LocalPoint>, Vec<WalOp>)> {
+        let points = read_lines(&self.points_path())
+            .await?
+            .iter()
+            .map(|line| serde_json::from_str(line).map_err(std::io::Error::other))
+            .collect::<Result<_, _>>()?;
+
+        let mut ops = Vec::new();
+        for line in read_lines(&self.wal_path()).await? {
+            match serde_json::from_str(&line) {
+                Ok(op) => ops.push(op),
+                Err(e) => {
+                    eprintln!("Dropping unreadable log entry in {}: {}", self.dir.display(), e);
+                    break;
+                }
+            }
+        }
+        Ok((points, ops))
+    }
+
+    pub async fn append(&self, op: &WalOp) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        let mut line = serde_json::to_vec(op).map_err(std::io::Error::other)?;
+        line.push(b'\n');
+
+        let mut wal = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.wal_path())
+            .await?;
+        wal.write_all(&line).await?;
+        wal.sync_data().await
+    }
+
+    // Replaces the compacted points with `points` and empties the log
+    pub async fn compact<'a>(&self, points: impl Iterator<Item = &'a LocalPoint>) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        let mut body = Vec::new();
+        for point in points {
+            serde_json::to_writer(&mut body, point).map_err(std::io::Error::other)?;
+            body.push(b'\n');
+        }
+        write_atomic(&self.points_path(), &body).await?;
+        write_atomic(&self.wal_path(), &[]).await
+    }
+
+    pub async fn save_graph(&self, graph: &[u8]) -> std::io::Result<()> {
+        write_atomic(&self.graph_path(), graph).await
+    }
+
+    pub async fn load_graph(&self) -> std::io::Result<Option<Vec<u8>>> {
+        match fs::read(self.graph_path()).await {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Write to a temporary file, sync, then rename over the target
+pub async fn write_atomic(path: &Path, body: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = fs::File::create(&tmp).await?;
+    file.write_all(body).await?;
+    file.sync_all().await?;
+    fs::rename(&tmp, path).await
+}
+
+async fn read_lines(path: &Path) -> std::io::Result<Vec<String>> {
+    match fs::read_to_string(path).await {
+        Ok(text) => Ok(text.lines().filter(|l| !l.trim().is_empty()).map(str::to_string).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::qdrant_functions::search::user_filter;
+
+    fn upsert(id: u64) -> WalOp {
+        let point = LocalPoint::from_json(&json!({ "id": id, "vector": [1.0, 0.0], "payload": { "user_id": "alice" } })).unwrap();
+        WalOp::Upsert { points: vec![point] }
+    }
+
+    #[tokio::test]
+    async fn a_torn_last_log_line_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = CollectionFiles::new(dir.path().join("tweets"));
+        files.append(&upsert(1)).await.unwrap();
+        files.append(&WalOp::DeleteFilter { filter: user_filter("bob") }).await.unwrap();
+
+        // a crash halfway through the third append
+        let mut wal = fs::OpenOptions::new().append(true).open(files.wal_path()).await.unwrap();
+        wal.write_all(br#"{"op":"upsert","points":[{"id":2,"vec"#).await.unwrap();
+
+        let (points, ops) = files.load().await.unwrap();
+        assert!(points.is_empty());
+        assert_eq!(ops.len(), 2);
+        assert!(matches!(&ops[0], WalOp::Upsert { points } if points.len() == 1));
+        assert!(matches!(&ops[1], WalOp::DeleteFilter { .. }));
+    }
+
+    #[tokio::test]
+    async fn compaction_moves_the_log_into_the_points_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = CollectionFiles::new(dir.path().join("tweets"));
+        files.append(&upsert(1)).await.unwrap();
+
+        let WalOp::Upsert { points } = upsert(2) else { unreachable!() };
+        files.compact(points.iter()).await.unwrap();
+
+        let (points, ops) = files.load().await.unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].id, crate::models::qdrant::PointId::Num(2));
+        assert!(ops.is_empty());
+    }
+}
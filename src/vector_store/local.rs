@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as Json};
+
+use crate::{
+    models::{
+        middleware::{Condition, FilterType, NamedVector, Prefetch, PointSearchVectors, Query, RecommendInput, RecommendStrategy, SparseVector, StoredVector},
+        qdrant::PointId,
+        similarity_result::{Point, ResultItem},
+    },
+    qdrant_functions::error::QdrantError,
+};
+
+// Query evaluation shared by the in-process backends. Follows Qdrant's
+// semantics closely enough that callers can't tell the difference: cosine
+// for dense vectors, dot product for sparse ones, rank fusion over prefetches.
+
+// Qdrant's default `k` of reciprocal rank fusion
+const RRF_K: f32 = 2.0;
+
+// A point as kept in memory / on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalPoint {
+    pub id: PointId,
+    #[serde(default)]
+    pub vector: Option<StoredVector>,
+    #[serde(default)]
+    pub payload: Json,
+}
+
+impl LocalPoint {
+    pub fn from_json(json: &Json) -> Result<LocalPoint, QdrantError> {
+        serde_json::from_value(json.clone()).map_err(|e| bad_request(format!("Invalid point: {}", e)))
+    }
+
+    // Qdrant's scroll shape
+    pub fn to_json(&self, with_payload: bool, with_vector: bool) -> Json {
+        let mut json = json!({ "id": self.id });
+        if with_payload {
+            json["payload"] = self.payload.clone();
+        }
+        if let (true, Some(vector)) = (with_vector, &self.vector) {
+            json["vector"] = json!(vector);
+        }
+        json
+    }
+
+    // `using` None or "" is the unnamed dense vector
+    pub fn dense(&self, using: Option<&str>) -> Option<&[f32]> {
+        let name = using.unwrap_or("");
+        match self.vector.as_ref()? {
+            StoredVector::Dense(vector) if name.is_empty() => Some(vector),
+            StoredVector::Dense(_) => None,
+            StoredVector::Named(named) => match named.get(name)? {
+                NamedVector::Dense(vector) => Some(vector),
+                NamedVector::Sparse(_) => None,
+            },
+        }
+    }
+
+    pub fn sparse(&self, name: &str) -> Option<&SparseVector> {
+        match self.vector.as_ref()? {
+            StoredVector::Named(named) => match named.get(name)? {
+                NamedVector::Sparse(vector) => Some(vector),
+                NamedVector::Dense(_) => None,
+            },
+            StoredVector::Dense(_) => None,
+        }
+    }
+
+    pub fn matches(&self, filter: &FilterType) -> bool {
+        matches(filter, &self.id, &self.payload)
+    }
+
+    fn to_scored(&self, score: f32, with_payload: bool) -> Result<Point, QdrantError> {
+        let id = match &self.id {
+            PointId::Num(n) => n.to_string(),
+            PointId::Uuid(uuid) => uuid.clone(),
+        };
+        let payload = if with_payload { self.payload.clone() } else { Json::Null };
+        Ok(Point {
+            id,
+            version: 0,
+            score,
+            payload: serde_json::from_value(payload)?,
+        })
+    }
+}
+
+pub fn bad_request(message: String) -> QdrantError {
+    QdrantError::Status { status: 400, body: message }
+}
+
+// Every `must` condition holds; a keyword matches a payload value equal to it
+// or an array containing it, like Qdrant's match
+pub fn matches(filter: &FilterType, id: &PointId, payload: &Json) -> bool {
+    filter.must.iter().all(|condition| match condition {
+        Condition::Field(field) => match payload.get(&field.key) {
+            Some(Json::String(value)) => *value == field.r#match.value,
+            Some(Json::Array(values)) => values.iter().any(|v| v.as_str() == Some(&field.r#match.value)),
+            _ => false,
+        },
+        Condition::HasId { has_id } => has_id.iter().any(|candidate| match id {
+            PointId::Uuid(uuid) => candidate == uuid,
+            PointId::Num(num) => *candidate == num.to_string(),
+        }),
+        // points without the field, or with something that is not a datetime, never match
+        Condition::Range { key, range } => {
+            let Some(value) = payload.get(key).and_then(Json::as_str).and_then(datetime) else {
+                return false;
+            };
+            range.gte.as_deref().and_then(datetime).is_none_or(|gte| value >= gte)
+                && range.lte.as_deref().and_then(datetime).is_none_or(|lte| value <= lte)
+        }
+    })
+}
+
+fn datetime(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value).ok()
+}
+
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+pub fn sparse_dot(a: &SparseVector, b: &SparseVector) -> f32 {
+    let weights: HashMap<u32, f32> = a.indices.iter().copied().zip(a.values.iter().copied()).collect();
+    b.indices
+        .iter()
+        .zip(&b.values)
+        .filter_map(|(index, value)| weights.get(index).map(|w| w * value))
+        .sum()
+}
+
+pub fn dimension_error(expected: usize, got: usize) -> QdrantError {
+    bad_request(format!("Wrong input: Vector dimension error: expected dim: {}, got {}", expected, got))
+}
+
+// Highest scores first, ties broken by id so results are deterministic
+pub fn top(mut scored: Vec<(PointId, f32)>, limit: usize) -> Vec<(PointId, f32)> {
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    scored.truncate(limit);
+    scored
+}
+
+// Exact cosine search over `points`
+pub fn brute_force_dense<'a>(
+    points: impl Iterator<Item = &'a LocalPoint>,
+    using: Option<&str>,
+    vector: &[f32],
+    filter: &FilterType,
+    limit: usize,
+) -> Result<Vec<(PointId, f32)>, QdrantError> {
+    let mut scored = Vec::new();
+    for point in points.filter(|p| p.matches(filter)) {
+        let Some(stored) = point.dense(using) else { continue };
+        if stored.len() != vector.len() {
+            return Err(dimension_error(stored.len(), vector.len()));
+        }
+        scored.push((point.id.clone(), cosine(stored, vector)));
+    }
+    Ok(top(scored, limit))
+}
+
+// The single query vector of the average_vector strategy
+pub fn average_vector(input: &RecommendInput) -> Result<Vec<f32>, QdrantError> {
+    let positive = mean(&input.positive)?.ok_or_else(|| bad_request("No positive examples".to_string()))?;
+    match mean(&input.negative)? {
+        None => Ok(positive),
+        Some(negative) => Ok(positive.iter().zip(&negative).map(|(p, n)| p + p - n).collect()),
+    }
+}
+
+fn mean(vectors: &[Vec<f32>]) -> Result<Option<Vec<f32>>, QdrantError> {
+    let Some(first) = vectors.first() else { return Ok(None) };
+    let mut sum = vec![0.0; first.len()];
+    for vector in vectors {
+        if vector.len() != sum.len() {
+            return Err(dimension_error(sum.len(), vector.len()));
+        }
+        sum.iter_mut().zip(vector).for_each(|(s, v)| *s += v);
+    }
+    Ok(Some(sum.into_iter().map(|s| s / vectors.len() as f32).collect()))
+}
+
+// best_score strategy: the best positive similarity when it beats the best
+// negative one, otherwise a negative score growing with the negative similarity
+pub fn best_score(stored: &[f32], input: &RecommendInput) -> Result<f32, QdrantError> {
+    let best = |examples: &[Vec<f32>]| -> Result<Option<f32>, QdrantError> {
+        let mut best: Option<f32> = None;
+        for example in examples {
+            if example.len() != stored.len() {
+                return Err(dimension_error(stored.len(), example.len()));
+            }
+            let score = cosine(stored, example);
+            best = Some(best.map_or(score, |b| b.max(score)));
+        }
+        Ok(best)
+    };
+    match (best(&input.positive)?, best(&input.negative)?) {
+        (Some(positive), Some(negative)) if positive > negative => Ok(positive),
+        (Some(positive), None) => Ok(positive),
+        (_, Some(negative)) => Ok(-(negative * negative)),
+        (None, None) => Err(bad_request("No recommend examples".to_string())),
+    }
+}
+
+// A collection of an in-process backend. The default nearest neighbour search
+// is brute force, indexed backends override `nearest_dense`.
+pub trait LocalCollection {
+    fn points(&self) -> Box<dyn Iterator<Item = &LocalPoint> + Send + '_>;
+    fn get(&self, id: &PointId) -> Option<&LocalPoint>;
+
+    fn nearest_dense(
+        &self,
+        using: Option<&str>,
+        vector: &[f32],
+        filter: &FilterType,
+        limit: usize,
+    ) -> Result<Vec<(PointId, f32)>, QdrantError> {
+        brute_force_dense(self.points(), using, vector, filter, limit)
+    }
+
+    fn nearest_sparse(&self, using: &str, vector: &SparseVector, filter: &FilterType, limit: usize) -> Vec<(PointId, f32)> {
+        let scored = self
+            .points()
+            .filter(|p| p.matches(filter))
+            .filter_map(|p| p.sparse(using).map(|stored| (p.id.clone(), sparse_dot(stored, vector))))
+            .collect();
+        top(scored, limit)
+    }
+}
+
+// Scores `query` against the whole collection, or only against the
+// `candidates` of its prefetches when there are any (Qdrant re-scores them)
+fn evaluate<C: LocalCollection + ?Sized>(
+    collection: &C,
+    query: &Query,
+    using: Option<&str>,
+    filter: &FilterType,
+    limit: usize,
+    candidates: Option<Vec<Vec<(PointId, f32)>>>,
+) -> Result<Vec<(PointId, f32)>, QdrantError> {
+    match (query, candidates) {
+        (Query::Fusion { fusion }, Some(lists)) => {
+            if fusion != "rrf" {
+                return Err(bad_request(format!("Unsupported fusion: {}", fusion)));
+            }
+            let mut fused: HashMap<PointId, f32> = HashMap::new();
+            for list in lists {
+                for (rank, (id, _)) in list.into_iter().enumerate() {
+                    *fused.entry(id).or_default() += 1.0 / (RRF_K + rank as f32);
+                }
+            }
+            Ok(top(fused.into_iter().collect(), limit))
+        }
+        (Query::Fusion { .. }, None) => Err(bad_request("Fusion query needs prefetches".to_string())),
+        (Query::Dense(vector), None) => collection.nearest_dense(using, vector, filter, limit),
+        (Query::Sparse(vector), None) => Ok(collection.nearest_sparse(using.unwrap_or(""), vector, filter, limit)),
+        (Query::Recommend { recommend }, None) => match recommend.strategy {
+            RecommendStrategy::AverageVector => collection.nearest_dense(using, &average_vector(recommend)?, filter, limit),
+            RecommendStrategy::BestScore => {
+                let mut scored = Vec::new();
+                for point in collection.points().filter(|p| p.matches(filter)) {
+                    let Some(stored) = point.dense(using) else { continue };
+                    scored.push((point.id.clone(), best_score(stored, recommend)?));
+                }
+                Ok(top(scored, limit))
+            }
+        },
+        (query, Some(lists)) => {
+            // the recommend query vector is the same for every candidate
+            let average = match query {
+                Query::Recommend { recommend } if recommend.strategy == RecommendStrategy::AverageVector => {
+                    Some(average_vector(recommend)?)
+                }
+                _ => None,
+            };
+            let mut scored = Vec::new();
+            let mut ids: Vec<PointId> = lists.into_iter().flatten().map(|(id, _)| id).collect();
+            ids.sort();
+            ids.dedup();
+            for id in ids {
+                let Some(point) = collection.get(&id).filter(|p| p.matches(filter)) else { continue };
+                let score = match query {
+                    Query::Dense(vector) => match point.dense(using) {
+                        Some(stored) if stored.len() == vector.len() => cosine(stored, vector),
+                        Some(stored) => return Err(dimension_error(stored.len(), vector.len())),
+                        None => continue,
+                    },
+                    Query::Sparse(vector) => match point.sparse(using.unwrap_or("")) {
+                        Some(stored) => sparse_dot(stored, vector),
+                        None => continue,
+                    },
+                    Query::Recommend { recommend } => match (point.dense(using), &average) {
+                        (Some(stored), Some(average)) if stored.len() == average.len() => cosine(stored, average),
+                        (Some(stored), Some(average)) => return Err(dimension_error(stored.len(), average.len())),
+                        (Some(stored), None) => best_score(stored, recommend)?,
+                        (None, _) => continue,
+                    },
+                    Query::Fusion { .. } => unreachable!(),
+                };
+                scored.push((id, score));
+            }
+            Ok(top(scored, limit))
+        }
+    }
+}
+
+// Qdrant's score_threshold, keeping the best first order
+fn above(mut scored: Vec<(PointId, f32)>, threshold: Option<f32>) -> Vec<(PointId, f32)> {
+    if let Some(threshold) = threshold {
+        scored.retain(|(_, score)| *score >= threshold);
+    }
+    scored
+}
+
+fn evaluate_prefetch<C: LocalCollection + ?Sized>(collection: &C, prefetch: &Prefetch) -> Result<Vec<(PointId, f32)>, QdrantError> {
+    let scored = evaluate(
+        collection,
+        &prefetch.query,
+        prefetch.using.as_deref(),
+        &prefetch.filter,
+        prefetch.limit as usize,
+        None,
+    )?;
+    Ok(above(scored, prefetch.score_threshold))
+}
+
+// One entry of a query batch
+pub fn run_query<C: LocalCollection + ?Sized>(collection: &C, search: &PointSearchVectors) -> Result<ResultItem, QdrantError> {
+    let candidates = search
+        .prefetch
+        .as_ref()
+        .map(|prefetches| prefetches.iter().map(|p| evaluate_prefetch(collection, p)).collect::<Result<Vec<_>, _>>())
+        .transpose()?;
+
+    let scored = evaluate(
+        collection,
+        &search.query,
+        search.using.as_deref(),
+        &search.filter,
+        search.limit as usize,
+        candidates,
+    )?;
+
+    let points = above(scored, search.score_threshold)
+        .into_iter()
+        .filter_map(|(id, score)| collection.get(&id).map(|point| point.to_scored(score, search.with_payload)))
+        .collect::<Result<_, _>>()?;
+    Ok(ResultItem { points })
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+};
+
+use async_trait::async_trait;
+use serde_json::Value as Json;
+use tokio::sync::RwLock;
+
+use crate::{
+    config::QdrantConfig,
+    models::{
+        middleware::{FilterType, SearchRequest},
+        qdrant::{PointId, ScrollRequest, ScrollResult, UpdateResult},
+        similarity_result::ResultItem,
+    },
+    qdrant_functions::error::QdrantError,
+    vector_store::{
+        local::{dimension_error, run_query, LocalCollection, LocalPoint},
+        Scope, VectorStore, WriteOptions,
+    },
+};
+
+// Points of one collection, kept in id order for scrolling
+#[derive(Default)]
+pub struct MemoryCollection {
+    points: BTreeMap<PointId, LocalPoint>,
+}
+
+impl LocalCollection for MemoryCollection {
+    fn points(&self) -> Box<dyn Iterator<Item = &LocalPoint> + Send + '_> {
+        Box::new(self.points.values())
+    }
+
+    fn get(&self, id: &PointId) -> Option<&LocalPoint> {
+        self.points.get(id)
+    }
+}
+
+impl MemoryCollection {
+    // Size of the unnamed dense vector, taken from any stored point
+    pub fn dimensions(&self) -> Option<usize> {
+        self.points.values().find_map(|p| p.dense(None)).map(<[f32]>::len)
+    }
+
+    // Rejects the whole batch when a vector does not fit, like Qdrant
+    pub fn check(&self, points: &[LocalPoint]) -> Result<(), QdrantError> {
+        let mut expected = self.dimensions();
+        for point in points {
+            if let Some(dense) = point.dense(None) {
+                match expected {
+                    Some(size) if size != dense.len() => return Err(dimension_error(size, dense.len())),
+                    _ => expected = Some(dense.len()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn upsert(&mut self, points: Vec<LocalPoint>) -> Result<(), QdrantError> {
+        self.check(&points)?;
+        for point in points {
+            self.points.insert(point.id.clone(), point);
+        }
+        Ok(())
+    }
+
+    // Returns the ids of the removed points
+    pub fn delete_matching(&mut self, filter: &FilterType) -> Vec<PointId> {
+        let ids: Vec<PointId> = self
+            .points
+            .values()
+            .filter(|point| point.matches(filter))
+            .map(|point| point.id.clone())
+            .collect();
+        for id in &ids {
+            self.points.remove(id);
+        }
+        ids
+    }
+
+    pub fn scroll(&self, request: &ScrollRequest) -> ScrollResult<Json> {
+        let start = match &request.offset {
+            Some(offset) => Bound::Included(offset.clone()),
+            None => Bound::Unbounded,
+        };
+        let mut matching = self
+            .points
+            .range((start, Bound::Unbounded))
+            .map(|(_, point)| point)
+            .filter(|point| point.matches(&request.filter));
+
+        let points = matching
+            .by_ref()
+            .take(request.limit as usize)
+            .map(|point| point.to_json(request.with_payload, request.with_vector))
+            .collect();
+        // Qdrant's offset is the first id of the next page
+        let next_page_offset = matching.next().map(|point| point.id.clone());
+        ScrollResult { points, next_page_offset }
+    }
+
+    pub fn count(&self, filter: &FilterType) -> u64 {
+        self.points.values().filter(|point| point.matches(filter)).count() as u64
+    }
+}
+
+pub fn completed() -> UpdateResult {
+    UpdateResult { operation_id: None, status: "completed".to_string() }
+}
+
+// Brute-force cosine search over points held in process memory. Nothing is
+// persisted, meant for local development, tests and very small deployments.
+// Shard keys are ignored, the tenant filter alone keeps users apart.
+pub struct MemoryStore {
+    collections: RwLock<HashMap<String, MemoryCollection>>,
+    tweets: String,
+    entitlements: String,
+}
+
+impl MemoryStore {
+    pub fn new(config: &QdrantConfig) -> Self {
+        MemoryStore {
+            collections: RwLock::new(HashMap::new()),
+            tweets: config.tweets_collection.clone(),
+            entitlements: config.entitlements_collection.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl VectorStore for MemoryStore {
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+
+    fn tweets(&self) -> &str {
+        &self.tweets
+    }
+
+    fn entitlements(&self) -> &str {
+        &self.entitlements
+    }
+
+    async fn upsert(&self, scope: &Scope, points: &[Json], _options: WriteOptions) -> Result<UpdateResult, QdrantError> {
+        let points = points.iter().map(LocalPoint::from_json).collect::<Result<_, _>>()?;
+        let mut collections = self.collections.write().await;
+        collections.entry(scope.collection.clone()).or_default().upsert(points)?;
+        Ok(completed())
+    }
+
+    async fn delete_by_filter(&self, scope: &Scope, filter: &FilterType) -> Result<UpdateResult, QdrantError> {
+        if let Some(points) = self.collections.write().await.get_mut(&scope.collection) {
+            points.delete_matching(filter);
+        }
+        Ok(completed())
+    }
+
+    async fn scroll(&self, scope: &Scope, request: &ScrollRequest) -> Result<ScrollResult<Json>, QdrantError> {
+        Ok(match self.collections.read().await.get(&scope.collection) {
+            Some(points) => points.scroll(request),
+            None => ScrollResult { points: Vec::new(), next_page_offset: None },
+        })
+    }
+
+    async fn count(&self, scope: &Scope, filter: &FilterType) -> Result<u64, QdrantError> {
+        Ok(self.collections.read().await.get(&scope.collection).map_or(0, |points| points.count(filter)))
+    }
+
+    async fn query_batch(&self, scope: &Scope, request: &SearchRequest) -> Result<Vec<ResultItem>, QdrantError> {
+        let collections = self.collections.read().await;
+        let Some(points) = collections.get(&scope.collection) else {
+            return Ok(request.searches.iter().map(|_| ResultItem { points: Vec::new() }).collect());
+        };
+        request.searches.iter().map(|search| run_query(points, search)).collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{models::middleware::PointSearchVectors, qdrant_functions::search::user_filter};
+
+    fn point(id: u64, user_id: &str, vector: &[f32]) -> LocalPoint {
+        LocalPoint::from_json(&json!({ "id": id, "vector": vector, "payload": { "user_id": user_id, "text": id.to_string() } }))
+            .unwrap()
+    }
+
+    fn collection() -> MemoryCollection {
+        let mut collection = MemoryCollection::default();
+        collection
+            .upsert(vec![
+                point(1, "alice", &[1.0, 0.0]),
+                point(2, "alice", &[0.6, 0.8]),
+                point(3, "alice", &[0.0, 1.0]),
+                // closest to the query, but not alice's
+                point(4, "bob", &[0.99, 0.1]),
+            ])
+            .unwrap();
+        collection
+    }
+
+    #[test]
+    fn nearest_dense_ranks_only_the_filtered_points_by_cosine() {
+        let hits = collection().nearest_dense(None, &[2.0, 0.0], &user_filter("alice"), 2).unwrap();
+        assert_eq!(hits, vec![(PointId::Num(1), 1.0), (PointId::Num(2), 0.6)]);
+
+        let hits = collection().nearest_dense(None, &[1.0, 0.0], &FilterType::default(), 1).unwrap();
+        assert_eq!(hits[0].0, PointId::Num(1));
+        assert!(collection().nearest_dense(None, &[1.0, 0.0], &user_filter("carol"), 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn wrong_vector_sizes_are_rejected() {
+        let mut collection = collection();
+        assert!(collection.nearest_dense(None, &[1.0, 0.0, 0.0], &FilterType::default(), 1).is_err());
+        // the whole batch is refused, not only the bad point
+        assert!(collection.upsert(vec![point(5, "alice", &[1.0, 0.0]), point(6, "alice", &[1.0])]).is_err());
+        assert_eq!(collection.count(&FilterType::default()), 4);
+    }
+
+    #[test]
+    fn scroll_pages_through_matching_points_in_id_order() {
+        let collection = collection();
+        let mut request = ScrollRequest {
+            filter: user_filter("alice"),
+            limit: 2,
+            with_payload: true,
+            with_vector: false,
+            offset: None,
+            shard_key: None,
+        };
+        let first = collection.scroll(&request);
+        assert_eq!(first.points.iter().map(|p| p["id"].clone()).collect::<Vec<_>>(), vec![json!(1), json!(2)]);
+        assert_eq!(first.next_page_offset, Some(PointId::Num(3)));
+        assert!(first.points[0].get("vector").is_none());
+
+        request.offset = first.next_page_offset;
+        let last = collection.scroll(&request);
+        assert_eq!(last.points.len(), 1);
+        assert_eq!(last.next_page_offset, None);
+    }
+
+    #[test]
+    fn delete_matching_only_removes_the_filtered_points() {
+        let mut collection = collection();
+        assert_eq!(collection.delete_matching(&user_filter("alice")), vec![PointId::Num(1), PointId::Num(2), PointId::Num(3)]);
+        assert_eq!(collection.count(&FilterType::default()), 1);
+        assert_eq!(collection.count(&user_filter("bob")), 1);
+    }
+
+    #[test]
+    fn queries_return_the_stored_payloads() {
+        let search: PointSearchVectors = serde_json::from_value(json!({
+            "query": [0.0, 1.0],
+            "filter": user_filter("alice"),
+            "with_payload": true,
+            "limit": 1
+        }))
+        .unwrap();
+        let result = run_query(&collection(), &search).unwrap();
+        assert_eq!(result.points.len(), 1);
+        assert_eq!(result.points[0].id, "3");
+        assert_eq!(result.points[0].payload.text, "3");
+    }
+
+    #[test]
+    fn score_threshold_drops_weak_dense_matches() {
+        let mut search: PointSearchVectors = serde_json::from_value(json!({
+            "query": [1.0, 0.0],
+            "filter": user_filter("alice"),
+            "with_payload": true,
+            "limit": 3,
+            "score_threshold": 0.7
+        }))
+        .unwrap();
+        let result = run_query(&collection(), &search).unwrap();
+        assert_eq!(result.points.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), vec!["1"]);
+
+        // the threshold also cuts the candidates of a prefetch
+        search = serde_json::from_value(json!({
+            "prefetch": [{ "query": [1.0, 0.0], "filter": user_filter("alice"), "limit": 3, "score_threshold": 0.7 }],
+            "query": { "fusion": "rrf" },
+            "filter": user_filter("alice"),
+            "with_payload": true,
+            "limit": 3
+        }))
+        .unwrap();
+        let result = run_query(&collection(), &search).unwrap();
+        assert_eq!(result.points.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), vec!["1"]);
+    }
+}
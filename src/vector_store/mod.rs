@@ -0,0 +1,129 @@
+pub mod breaker;
+pub mod embedded;
+pub mod local;
+pub mod memory;
+pub mod qdrant;
+pub mod tenancy;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value as Json;
+
+use crate::{
+    breaker::CircuitBreaker,
+    config::{Config, StoreBackend, UpsertConfig, WriteOrdering},
+    models::{
+        middleware::{FilterType, SearchRequest},
+        qdrant::{ScrollRequest, ScrollResult, UpdateResult},
+        similarity_result::ResultItem,
+    },
+    qdrant_functions::{
+        client::QdrantClient,
+        collections::{bootstrap, tweets_collection_body},
+        error::QdrantError,
+    },
+    vector_store::{breaker::BreakerStore, embedded::EmbeddedStore, memory::MemoryStore, qdrant::QdrantStore},
+};
+
+// Where an operation lands: a collection and, with custom sharding, one shard of it
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Scope {
+    pub collection: String,
+    pub shard_key: Option<String>,
+}
+
+impl Scope {
+    pub fn collection(name: &str) -> Scope {
+        Scope { collection: name.to_string(), shard_key: None }
+    }
+}
+
+// Write consistency of one upsert. The local backends apply every write
+// before returning and ignore both.
+#[derive(Debug, Clone, Copy)]
+pub struct WriteOptions {
+    pub wait: bool,
+    pub ordering: WriteOrdering,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions { wait: true, ordering: WriteOrdering::Weak }
+    }
+}
+
+impl From<&UpsertConfig> for WriteOptions {
+    fn from(config: &UpsertConfig) -> Self {
+        WriteOptions { wait: config.wait, ordering: config.ordering }
+    }
+}
+
+// Point storage and kNN behind one interface, modelled on Qdrant's point API.
+// Points travel in their Qdrant REST JSON shape ({"id", "vector", "payload"}),
+// use `to_points` / `from_scroll` to get typed values in and out. Saved tweets
+// are only reached through `TenantStore`, which adds the tenant scoping.
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    fn backend(&self) -> &'static str;
+    // collection holding the saved tweets (the shared one in `collection` tenancy)
+    fn tweets(&self) -> &str;
+    // collection holding one entitlement point per user
+    fn entitlements(&self) -> &str;
+
+    // Creates the shard key / collection behind `scope` if it is missing
+    async fn prepare(&self, _scope: &Scope) -> Result<(), QdrantError> {
+        Ok(())
+    }
+
+    async fn upsert(&self, scope: &Scope, points: &[Json], options: WriteOptions) -> Result<UpdateResult, QdrantError>;
+    async fn delete_by_filter(&self, scope: &Scope, filter: &FilterType) -> Result<UpdateResult, QdrantError>;
+    async fn scroll(&self, scope: &Scope, request: &ScrollRequest) -> Result<ScrollResult<Json>, QdrantError>;
+    async fn count(&self, scope: &Scope, filter: &FilterType) -> Result<u64, QdrantError>;
+    // one filtered kNN query per entry of `request.searches`, results in the same order
+    async fn query_batch(&self, scope: &Scope, request: &SearchRequest) -> Result<Vec<ResultItem>, QdrantError>;
+}
+
+// Connects the configured backend, Qdrant collections are created / checked
+// against the probed embedding size first. Only Qdrant sits behind `breaker`,
+// the local backends have no remote side to go down.
+pub async fn build_store(
+    config: &Config,
+    dimensions: usize,
+    breaker: Arc<CircuitBreaker>,
+) -> Result<Arc<dyn VectorStore>, anyhow::Error> {
+    match config.store {
+        StoreBackend::Qdrant => {
+            let client = QdrantClient::new(&config.qdrant)?;
+            println!("Qdrant transport: {}", client.transport());
+            bootstrap(&client, config, dimensions).await?;
+            let store = QdrantStore::new(client, tweets_collection_body(config, dimensions));
+            Ok(Arc::new(BreakerStore::new(Arc::new(store), breaker)))
+        }
+        StoreBackend::Memory => Ok(Arc::new(MemoryStore::new(&config.qdrant))),
+        StoreBackend::Embedded => {
+            let store = EmbeddedStore::open(&config.embedded, &config.qdrant).await?;
+            if let Some(every) = config.embedded.snapshot_interval {
+                store.spawn_snapshots(every, config.embedded.snapshot_keep);
+            }
+            Ok(Arc::new(store))
+        }
+    }
+}
+
+pub fn to_points<P: Serialize>(points: &[P]) -> Result<Vec<Json>, QdrantError> {
+    points
+        .iter()
+        .map(|point| serde_json::to_value(point).map_err(|e| QdrantError::Encode(e.to_string())))
+        .collect()
+}
+
+pub fn from_scroll<P: DeserializeOwned>(result: ScrollResult<Json>) -> Result<ScrollResult<P>, QdrantError> {
+    let points = result
+        .points
+        .into_iter()
+        .map(serde_json::from_value)
+        .collect::<Result<_, _>>()?;
+    Ok(ScrollResult { points, next_page_offset: result.next_page_offset })
+}
@@ -0,0 +1,90 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use async_trait::async_trait;
+use serde_json::Value as Json;
+
+use crate::{
+    models::{
+        middleware::{FilterType, SearchRequest},
+        qdrant::{ScrollRequest, ScrollResult, UpdateResult},
+        similarity_result::ResultItem,
+    },
+    qdrant_functions::{client::QdrantClient, collections::create_tenant_collection, error::QdrantError},
+    vector_store::{Scope, VectorStore, WriteOptions},
+};
+
+pub struct QdrantStore {
+    client: QdrantClient,
+    // PUT /collections body for per team collections
+    template: Json,
+    // scopes known to exist, saves a round trip per request
+    prepared: Mutex<HashSet<Scope>>,
+}
+
+impl QdrantStore {
+    pub fn new(client: QdrantClient, template: Json) -> Self {
+        QdrantStore { client, template, prepared: Mutex::new(HashSet::new()) }
+    }
+}
+
+#[async_trait]
+impl VectorStore for QdrantStore {
+    fn backend(&self) -> &'static str {
+        "qdrant"
+    }
+
+    fn tweets(&self) -> &str {
+        &self.client.tweets
+    }
+
+    fn entitlements(&self) -> &str {
+        &self.client.entitlements
+    }
+
+    async fn prepare(&self, scope: &Scope) -> Result<(), QdrantError> {
+        let bootstrapped = scope.shard_key.is_none()
+            && (scope.collection == self.client.tweets || scope.collection == self.client.entitlements);
+        if bootstrapped || self.prepared.lock().unwrap().contains(scope) {
+            return Ok(());
+        }
+
+        match &scope.shard_key {
+            Some(shard_key) => self.client.create_shard_key(&scope.collection, shard_key).await?,
+            None => create_tenant_collection(&self.client, &scope.collection, &self.template)
+                .await
+                .map_err(|e| QdrantError::Status { status: 500, body: e.to_string() })?,
+        }
+        self.prepared.lock().unwrap().insert(scope.clone());
+        Ok(())
+    }
+
+    async fn upsert(&self, scope: &Scope, points: &[Json], options: WriteOptions) -> Result<UpdateResult, QdrantError> {
+        self.client.upsert(&scope.collection, scope.shard_key.as_deref(), points, options).await
+    }
+
+    async fn delete_by_filter(&self, scope: &Scope, filter: &FilterType) -> Result<UpdateResult, QdrantError> {
+        self.client.delete_by_filter(&scope.collection, scope.shard_key.as_deref(), filter).await
+    }
+
+    async fn scroll(&self, scope: &Scope, request: &ScrollRequest) -> Result<ScrollResult<Json>, QdrantError> {
+        let request = ScrollRequest { shard_key: scope.shard_key.clone(), ..request.clone() };
+        self.client.scroll(&scope.collection, &request).await
+    }
+
+    async fn count(&self, scope: &Scope, filter: &FilterType) -> Result<u64, QdrantError> {
+        self.client.count(&scope.collection, scope.shard_key.as_deref(), filter).await
+    }
+
+    async fn query_batch(&self, scope: &Scope, request: &SearchRequest) -> Result<Vec<ResultItem>, QdrantError> {
+        let searches = request
+            .searches
+            .iter()
+            .map(|search| {
+                let mut search = search.clone();
+                search.shard_key = scope.shard_key.clone();
+                search
+            })
+            .collect();
+        self.client.query_batch(&scope.collection, &SearchRequest { searches }).await
+    }
+}
@@ -0,0 +1,311 @@
+use std::{collections::HashMap, sync::Arc};
+
+use futures_util::{stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde_json::Value as Json;
+
+use crate::{
+    auth::extractor::AuthUser,
+    config::{TenancyMode, UpsertConfig},
+    models::{
+        internal::Tweet,
+        middleware::{Condition, FilterType, PointSearchVectors, SearchRequest},
+        qdrant::{FailedPoint, PointId, ScrollRequest, ScrollResult, UpdateResult, UpsertReport},
+        similarity_result::ResultItem,
+    },
+    qdrant_functions::{error::QdrantError, search::user_filter},
+    vector_store::{from_scroll, Scope, VectorStore, WriteOptions},
+};
+
+// Owner of saved tweets, taken from the verified JWT
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tenant {
+    pub user_id: String,
+    pub team_id: Option<String>,
+}
+
+impl From<&AuthUser> for Tenant {
+    fn from(user: &AuthUser) -> Self {
+        Tenant { user_id: user.user_id.clone(), team_id: user.team_id.clone() }
+    }
+}
+
+impl From<&Tweet> for Tenant {
+    fn from(tweet: &Tweet) -> Self {
+        Tenant { user_id: tweet.user_id.clone(), team_id: tweet.team_id.clone() }
+    }
+}
+
+fn point_id(point: &Json) -> String {
+    match &point["id"] {
+        Json::String(id) => id.clone(),
+        other => other.to_string(),
+    }
+}
+
+// Collection names end up in URLs and directory names
+fn collection_suffix(team_id: &str) -> String {
+    team_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+// The only way to reach saved tweets. Every call is scoped to one tenant:
+// the `user_id` filter is always added (and the payload `user_id` always
+// set) whatever the mode, shard keys / team collections come on top.
+pub struct TenantStore {
+    store: Arc<dyn VectorStore>,
+    mode: TenancyMode,
+}
+
+impl TenantStore {
+    pub fn new(store: Arc<dyn VectorStore>, mode: TenancyMode) -> Self {
+        TenantStore { store, mode }
+    }
+
+    pub fn scope(&self, tenant: &Tenant) -> Scope {
+        let base = self.store.tweets();
+        match (self.mode, &tenant.team_id) {
+            (TenancyMode::Filter, _) => Scope::collection(base),
+            (TenancyMode::ShardKey, _) => Scope {
+                collection: base.to_string(),
+                shard_key: Some(tenant.user_id.clone()),
+            },
+            (TenancyMode::Collection, Some(team_id)) => {
+                Scope::collection(&format!("{}_{}", base, collection_suffix(team_id)))
+            }
+            (TenancyMode::Collection, None) => Scope::collection(base),
+        }
+    }
+
+    // `filter` narrowed down to the tenant's points
+    fn scoped(tenant: &Tenant, filter: &FilterType) -> FilterType {
+        let mut scoped = user_filter(&tenant.user_id);
+        scoped.must.extend(filter.must.iter().cloned());
+        scoped
+    }
+
+    async fn prepared(&self, tenant: &Tenant) -> Result<Scope, QdrantError> {
+        let scope = self.scope(tenant);
+        self.store.prepare(&scope).await?;
+        Ok(scope)
+    }
+
+    // Writes `points` in chunks of `config.chunk_size`, up to `config.parallelism`
+    // chunks at a time. A failed chunk does not stop the others, the report
+    // says which ids made it. Ids already saved by another user are never
+    // written and reported as failed. Only a failure to reach the tenant's
+    // scope (or to check the ids) is an Err.
+    pub async fn upsert(&self, tenant: &Tenant, points: &[Json], config: &UpsertConfig) -> Result<UpsertReport, QdrantError> {
+        let scope = self.prepared(tenant).await?;
+        let mut report = UpsertReport::default();
+
+        let ids: Vec<String> = points.iter().map(point_id).collect();
+        let foreign = self.foreign_ids(tenant, &ids).await?;
+        if !foreign.is_empty() {
+            eprintln!("Refusing to overwrite {} points of other users", foreign.len());
+        }
+        let points: Vec<Json> = points
+            .iter()
+            .filter(|point| !foreign.contains(&point_id(point)))
+            .map(|point| {
+                let mut point = point.clone();
+                point["payload"]["user_id"] = Json::String(tenant.user_id.clone());
+                point
+            })
+            .collect();
+
+        let options = WriteOptions::from(config);
+        let (store, scope) = (&self.store, &scope);
+        let outcomes: Vec<(Vec<String>, Result<UpdateResult, QdrantError>)> = stream::iter(points.chunks(config.chunk_size))
+            .map(|chunk| async move {
+                let ids = chunk.iter().map(point_id).collect();
+                (ids, store.upsert(scope, chunk, options).await)
+            })
+            .buffer_unordered(config.parallelism)
+            .collect()
+            .await;
+
+        report.failed.extend(foreign.into_iter().map(|id| FailedPoint { id, error: "Point belongs to another user".to_string() }));
+        for (ids, outcome) in outcomes {
+            match outcome {
+                Ok(result) if result.status == "completed" => report.stored.extend(ids),
+                Ok(_) => report.acknowledged.extend(ids),
+                Err(e) => {
+                    eprintln!("Upsert of {} points failed: {}", ids.len(), e);
+                    let error = e.to_string();
+                    report.failed.extend(ids.into_iter().map(|id| FailedPoint { id, error: error.clone() }));
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    pub async fn delete_all(&self, tenant: &Tenant) -> Result<UpdateResult, QdrantError> {
+        let scope = self.prepared(tenant).await?;
+        self.store.delete_by_filter(&scope, &user_filter(&tenant.user_id)).await
+    }
+
+    // ids of other users are silently ignored
+    pub async fn delete_ids(&self, tenant: &Tenant, ids: &[String]) -> Result<UpdateResult, QdrantError> {
+        let scope = self.prepared(tenant).await?;
+        let filter = FilterType { must: vec![Condition::HasId { has_id: ids.to_vec() }] };
+        self.store.delete_by_filter(&scope, &Self::scoped(tenant, &filter)).await
+    }
+
+    pub async fn scroll<P: DeserializeOwned>(
+        &self,
+        tenant: &Tenant,
+        filter: &FilterType,
+        limit: u32,
+        offset: Option<PointId>,
+        with_vector: bool,
+    ) -> Result<ScrollResult<P>, QdrantError> {
+        let scope = self.prepared(tenant).await?;
+        let request = ScrollRequest {
+            filter: Self::scoped(tenant, filter),
+            limit,
+            with_payload: true,
+            with_vector,
+            offset,
+            shard_key: None,
+        };
+        from_scroll(self.store.scroll(&scope, &request).await?)
+    }
+
+    // The tenant's points among `ids`, ids of other users are left out
+    pub async fn retrieve(&self, tenant: &Tenant, ids: &[String], with_vector: bool) -> Result<Vec<Json>, QdrantError> {
+        let scope = self.prepared(tenant).await?;
+        let mut points = Vec::new();
+        for chunk in ids.chunks(500) {
+            let filter = FilterType { must: vec![Condition::HasId { has_id: chunk.to_vec() }] };
+            let request = ScrollRequest {
+                filter: Self::scoped(tenant, &filter),
+                limit: chunk.len() as u32,
+                with_payload: true,
+                with_vector,
+                offset: None,
+                shard_key: None,
+            };
+            points.extend(self.store.scroll(&scope, &request).await?.points);
+        }
+        Ok(points)
+    }
+
+    // The ids among `ids` that exist in the tenant's scope but were saved by
+    // someone else, writes to them would overwrite another user's point
+    pub async fn foreign_ids(&self, tenant: &Tenant, ids: &[String]) -> Result<Vec<String>, QdrantError> {
+        let scope = self.prepared(tenant).await?;
+        let mut foreign = Vec::new();
+        for chunk in ids.chunks(500) {
+            let request = ScrollRequest {
+                filter: FilterType { must: vec![Condition::HasId { has_id: chunk.to_vec() }] },
+                limit: chunk.len() as u32,
+                with_payload: true,
+                with_vector: false,
+                offset: None,
+                shard_key: None,
+            };
+            let existing = self.store.scroll(&scope, &request).await?;
+            foreign.extend(
+                existing
+                    .points
+                    .iter()
+                    .filter(|point| point["payload"]["user_id"].as_str() != Some(tenant.user_id.as_str()))
+                    .map(point_id),
+            );
+        }
+        Ok(foreign)
+    }
+
+    pub async fn count(&self, tenant: &Tenant) -> Result<u64, QdrantError> {
+        let scope = self.prepared(tenant).await?;
+        self.store.count(&scope, &user_filter(&tenant.user_id)).await
+    }
+
+    // `tenants[i]` owns `request.searches[i]`. Searches are grouped into one
+    // batch per scope and the results put back in request order.
+    pub async fn query_batch(&self, tenants: &[Tenant], request: SearchRequest) -> Result<Vec<ResultItem>, QdrantError> {
+        let mut batches: HashMap<Scope, (Vec<usize>, Vec<PointSearchVectors>)> = HashMap::new();
+        for (position, (tenant, mut search)) in tenants.iter().zip(request.searches).enumerate() {
+            search.filter = Self::scoped(tenant, &search.filter);
+            if let Some(prefetches) = search.prefetch.as_mut() {
+                for prefetch in prefetches {
+                    prefetch.filter = Self::scoped(tenant, &prefetch.filter);
+                }
+            }
+            let batch = batches.entry(self.prepared(tenant).await?).or_default();
+            batch.0.push(position);
+            batch.1.push(search);
+        }
+
+        let mut results: Vec<Option<ResultItem>> = (0..tenants.len()).map(|_| None).collect();
+        for (scope, (positions, searches)) in batches {
+            let items = self.store.query_batch(&scope, &SearchRequest { searches }).await?;
+            for (position, item) in positions.into_iter().zip(items) {
+                results[position] = Some(item);
+            }
+        }
+        Ok(results.into_iter().map(|item| item.unwrap_or(ResultItem { points: Vec::new() })).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        config::{QdrantConfig, QdrantTransport, WriteOrdering},
+        vector_store::memory::MemoryStore,
+    };
+
+    fn tenant_store() -> TenantStore {
+        let config = QdrantConfig {
+            endpoint: String::new(),
+            api_key: None,
+            tweets_collection: "tweets".to_string(),
+            entitlements_collection: "entitlements".to_string(),
+            distance: "Cosine".to_string(),
+            transport: QdrantTransport::Rest,
+            grpc_endpoint: String::new(),
+        };
+        TenantStore::new(Arc::new(MemoryStore::new(&config)), TenancyMode::Filter)
+    }
+
+    fn tenant(user_id: &str) -> Tenant {
+        Tenant { user_id: user_id.to_string(), team_id: None }
+    }
+
+    fn upsert_config() -> UpsertConfig {
+        UpsertConfig { chunk_size: 1, parallelism: 2, wait: true, ordering: WriteOrdering::Weak }
+    }
+
+    fn point(id: u64, text: &str) -> Json {
+        // the payload user_id is always overwritten with the tenant's
+        json!({ "id": id, "vector": [1.0, 0.0], "payload": { "user_id": "someone", "text": text } })
+    }
+
+    #[tokio::test]
+    async fn points_of_another_user_are_never_overwritten() {
+        let store = tenant_store();
+        let report = store.upsert(&tenant("alice"), &[point(1, "alice's")], &upsert_config()).await.unwrap();
+        assert_eq!(report.stored, vec!["1"]);
+
+        let report = store.upsert(&tenant("bob"), &[point(1, "bob's"), point(2, "bob's")], &upsert_config()).await.unwrap();
+        assert_eq!(report.stored, vec!["2"]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].id, "1");
+
+        let alice: Vec<Json> = store.retrieve(&tenant("alice"), &["1".to_string()], false).await.unwrap();
+        assert_eq!(alice[0]["payload"]["text"], "alice's");
+        assert_eq!(alice[0]["payload"]["user_id"], "alice");
+        assert_eq!(store.count(&tenant("bob")).await.unwrap(), 1);
+
+        // the owner can still update it
+        let report = store.upsert(&tenant("alice"), &[point(1, "edited")], &upsert_config()).await.unwrap();
+        assert_eq!(report.stored, vec!["1"]);
+        assert!(report.failed.is_empty());
+    }
+}